@@ -2,7 +2,8 @@ use std::str::FromStr;
 
 use anchor_client::{
     solana_sdk::{
-        commitment_config::CommitmentConfig, pubkey::Pubkey, signature::read_keypair_file,
+        commitment_config::CommitmentConfig, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey,
+        signature::read_keypair_file,
     },
     Client,
 };
@@ -140,6 +141,83 @@ impl FromStr for ConfigPreset {
     }
 }
 
+/// Amount of SOL given as a decimal, i.e. `0.25`, in lamports.
+#[derive(Debug, Clone, Copy)]
+struct Sol(u64);
+
+#[derive(Debug)]
+enum SolParseError {
+    InvalidAmount,
+    TooManyDecimals,
+    TooLarge,
+}
+
+impl std::fmt::Display for SolParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            SolParseError::InvalidAmount => write!(f, "invalid SOL amount"),
+            SolParseError::TooManyDecimals => write!(f, "SOL amount has more than 9 decimals"),
+            SolParseError::TooLarge => write!(f, "SOL amount is too large"),
+        }
+    }
+}
+
+impl FromStr for Sol {
+    type Err = SolParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        // Lamports are the 9th decimal of SOL.
+        if fraction.len() > 9 {
+            return Err(SolParseError::TooManyDecimals);
+        }
+        if !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+        {
+            return Err(SolParseError::InvalidAmount);
+        }
+
+        let whole: u64 = whole.parse().map_err(|_| SolParseError::InvalidAmount)?;
+        let fraction: u64 = format!("{:0<9}", fraction)
+            .parse()
+            .map_err(|_| SolParseError::InvalidAmount)?;
+
+        whole
+            .checked_mul(LAMPORTS_PER_SOL)
+            .and_then(|lamports| lamports.checked_add(fraction))
+            .map(Sol)
+            .ok_or(SolParseError::TooLarge)
+    }
+}
+
+/// Fee discount tier given as `<min balance>:<basis points>`, i.e. `1000:2500`.
+#[derive(Debug, Clone, Copy)]
+struct Tier(locker::DiscountTier);
+
+#[derive(Debug)]
+struct TierParseError;
+
+impl std::fmt::Display for TierParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "discount tier should be <min balance>:<basis points>")
+    }
+}
+
+impl FromStr for Tier {
+    type Err = TierParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min_balance, basis_points) = s.split_once(':').ok_or(TierParseError)?;
+
+        Ok(Tier(locker::DiscountTier {
+            min_balance: min_balance.parse().map_err(|_| TierParseError)?,
+            basis_points: basis_points.parse().map_err(|_| TierParseError)?,
+        }))
+    }
+}
+
 #[derive(Debug, StructOpt)]
 enum LockerCmd {
     /// Add some token to whitelist.
@@ -147,6 +225,33 @@ enum LockerCmd {
         #[structopt(long)]
         mint: Pubkey,
     },
+    /// Override the fees for the mint, the config fees apply to the ones
+    /// not given.
+    SetMintFee {
+        #[structopt(long)]
+        mint: Pubkey,
+        /// Fee in SOL, i.e. `0.25`, zero waives it.
+        #[structopt(long)]
+        fee_in_sol: Option<Sol>,
+        /// Zero waives the fee in tokens.
+        #[structopt(long, requires = "fee-in-token-denominator")]
+        fee_in_token_numerator: Option<u64>,
+        #[structopt(long, requires = "fee-in-token-numerator")]
+        fee_in_token_denominator: Option<u64>,
+        /// Waive both fees.
+        #[structopt(long, conflicts_with_all = &["fee-in-sol", "fee-in-token-numerator"])]
+        waive: bool,
+    },
+    /// Set the fee discounts for the holders of the mint, replaces
+    /// the previous tiers, no tiers stop the discounts.
+    SetFeeDiscounts {
+        #[structopt(long)]
+        mint: Pubkey,
+        /// Smallest balance in the smallest units of the mint and the discount
+        /// in basis points, i.e. `1000:2500`, can be repeated.
+        #[structopt(long = "tier")]
+        tiers: Vec<Tier>,
+    },
     /// Init config for locker program.
     InitConfig {
         #[structopt(long)]
@@ -155,6 +260,50 @@ enum LockerCmd {
         fee_wallet: Pubkey,
         #[structopt(long)]
         preset: ConfigPreset,
+        /// Fee in SOL, i.e. `0.25`, the preset one if not given.
+        #[structopt(long)]
+        fee_in_sol: Option<Sol>,
+    },
+    /// Accept the flat fee in the stablecoin instead of SOL, stops
+    /// accepting it if no mint is given.
+    SetStablecoinFee {
+        /// Mint of the stablecoin, i.e. USDC.
+        #[structopt(long, requires = "amount")]
+        mint: Option<Pubkey>,
+        /// Fee in the smallest units of the stablecoin mint.
+        #[structopt(long, requires = "mint")]
+        amount: Option<u64>,
+    },
+    /// Move the config created before the fee in lamports to them.
+    MigrateConfig {},
+    /// Pause deposits and/or withdrawals, pauses both if no flag is given.
+    Pause {
+        #[structopt(long)]
+        deposits: bool,
+        #[structopt(long)]
+        withdrawals: bool,
+    },
+    /// Unpause deposits and/or withdrawals, unpauses both if no flag is given.
+    Unpause {
+        #[structopt(long)]
+        deposits: bool,
+        #[structopt(long)]
+        withdrawals: bool,
+    },
+    /// Propose the new admin, it should accept the handover afterwards.
+    ProposeAdmin {
+        #[structopt(long)]
+        new_admin: Pubkey,
+    },
+    /// Accept the handover, should be signed by the proposed admin.
+    AcceptAdmin {},
+    /// Unlock the locker before its unlock date, leaves an audit record.
+    EmergencyUnlock {
+        #[structopt(long)]
+        locker: Pubkey,
+        /// Support case the unlock is made for.
+        #[structopt(long)]
+        reason: String,
     },
     /// Show config for program.
     ShowConfig {},
@@ -163,6 +312,35 @@ enum LockerCmd {
         #[structopt(long)]
         locker: Pubkey,
     },
+    /// Print the address of the locker created with `create_locker_with_pda`.
+    LockerAddress {
+        #[structopt(long)]
+        creator: Pubkey,
+        #[structopt(long)]
+        mint: Pubkey,
+        /// Number of lockers created by the creator before this one.
+        #[structopt(long)]
+        nonce: u64,
+    },
+    /// Show the lockers of the mint created with `create_locker_with_pda`.
+    ShowCreatedLockers {
+        #[structopt(long)]
+        creator: Pubkey,
+        #[structopt(long)]
+        mint: Pubkey,
+    },
+}
+
+fn set_pause_args(deposits: bool, withdrawals: bool, paused: bool) -> locker::SetPauseArgs {
+    // No flags means both of them.
+    let both = !deposits && !withdrawals;
+
+    let flag = |selected: bool| if both || selected { Some(paused) } else { None };
+
+    locker::SetPauseArgs {
+        deposits: flag(deposits),
+        withdrawals: flag(withdrawals),
+    }
 }
 
 fn main() -> Result<()> {
@@ -256,20 +434,67 @@ fn main() -> Result<()> {
         },
         Command::Locker(cmd) => match cmd {
             LockerCmd::AddToken { mint } => {
-                let (config, _) = Pubkey::find_program_address(&["config".as_ref()], &client.id());
-                let (mint_info, bump) =
-                    Pubkey::find_program_address(&[mint.as_ref()], &client.id());
+                let r = client
+                    .request()
+                    .instruction(locker_client::instruction::init_mint_info(
+                        &client.id(),
+                        &client.payer(),
+                        &mint,
+                    ))
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::SetMintFee {
+                mint,
+                fee_in_sol,
+                fee_in_token_numerator,
+                fee_in_token_denominator,
+                waive,
+            } => {
+                let fee = if waive {
+                    locker::MintFee {
+                        fee_in_sol: Some(0),
+                        fee_in_token: Some(locker::TokenFee {
+                            numerator: 0,
+                            denominator: 1,
+                        }),
+                    }
+                } else {
+                    locker::MintFee {
+                        fee_in_sol: fee_in_sol.map(|fee| fee.0),
+                        fee_in_token: fee_in_token_numerator.zip(fee_in_token_denominator).map(
+                            |(numerator, denominator)| locker::TokenFee {
+                                numerator,
+                                denominator,
+                            },
+                        ),
+                    }
+                };
 
                 let r = client
                     .request()
-                    .accounts(locker::accounts::InitMintInfo {
-                        system_program: anchor_client::solana_sdk::system_program::id(),
-                        payer: client.payer(),
-                        mint_info,
-                        mint,
-                        config,
-                    })
-                    .args(locker::instruction::InitMintInfo { bump })
+                    .instruction(locker_client::instruction::set_mint_fee(
+                        &client.id(),
+                        &client.payer(),
+                        &mint,
+                        fee,
+                    ))
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::SetFeeDiscounts { mint, tiers } => {
+                let r = client
+                    .request()
+                    .instruction(locker_client::instruction::set_fee_discounts(
+                        &client.id(),
+                        &client.payer(),
+                        &mint,
+                        tiers.into_iter().map(|tier| tier.0).collect(),
+                    ))
                     .signer(&payer_copy)
                     .send()?;
 
@@ -279,13 +504,15 @@ fn main() -> Result<()> {
                 country_list,
                 fee_wallet,
                 preset,
+                fee_in_sol,
             } => {
-                let (config, bump) =
-                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+                // Bump is filled by the instruction builder.
+                let bump = 0;
+                let fee_in_sol = fee_in_sol.map_or(LAMPORTS_PER_SOL, |fee| fee.0);
 
                 let args = match preset {
                     ConfigPreset::TokenLocker => locker::CreateConfigArgs {
-                        fee_in_sol: 1,
+                        fee_in_sol,
                         fee_in_token_numerator: 35,
                         fee_in_token_denominator: 10000,
                         mint_info_permissioned: false,
@@ -293,7 +520,7 @@ fn main() -> Result<()> {
                         bump,
                     },
                     ConfigPreset::LPLocker => locker::CreateConfigArgs {
-                        fee_in_sol: 1,
+                        fee_in_sol,
                         fee_in_token_numerator: 10,
                         fee_in_token_denominator: 1000,
                         mint_info_permissioned: true,
@@ -304,30 +531,148 @@ fn main() -> Result<()> {
 
                 let r = client
                     .request()
-                    .accounts(locker::accounts::InitConfig {
-                        admin: client.payer(),
-                        config,
-                        fee_wallet,
-                        country_list,
-                        system_program: anchor_client::solana_sdk::system_program::id(),
-                    })
-                    .args(locker::instruction::InitConfig { args })
+                    .instruction(locker_client::instruction::init_config(
+                        &client.id(),
+                        &client.payer(),
+                        &fee_wallet,
+                        &country_list,
+                        args,
+                    ))
                     .signer(&payer_copy)
                     .send()?;
 
                 println!("Result:\n{}", r);
             }
-            LockerCmd::ShowConfig {} => {
-                let (config, _bump) =
-                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+            LockerCmd::SetStablecoinFee { mint, amount } => {
+                let fee = mint
+                    .zip(amount)
+                    .map(|(mint, amount)| locker::StablecoinFee { mint, amount });
+
+                let r = client
+                    .request()
+                    .instruction(locker_client::instruction::set_stablecoin_fee(
+                        &client.id(),
+                        &client.payer(),
+                        fee,
+                    ))
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::MigrateConfig {} => {
+                let r = client
+                    .request()
+                    .instruction(locker_client::instruction::migrate_config(&client.id()))
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::Pause {
+                deposits,
+                withdrawals,
+            } => {
+                let r = client
+                    .request()
+                    .instruction(locker_client::instruction::set_pause(
+                        &client.id(),
+                        &client.payer(),
+                        set_pause_args(deposits, withdrawals, true),
+                    ))
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::Unpause {
+                deposits,
+                withdrawals,
+            } => {
+                let r = client
+                    .request()
+                    .instruction(locker_client::instruction::set_pause(
+                        &client.id(),
+                        &client.payer(),
+                        set_pause_args(deposits, withdrawals, false),
+                    ))
+                    .signer(&payer_copy)
+                    .send()?;
 
-                let config: locker::Config = client.account(config)?;
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::ProposeAdmin { new_admin } => {
+                let r = client
+                    .request()
+                    .instruction(locker_client::instruction::propose_admin(
+                        &client.id(),
+                        &client.payer(),
+                        &new_admin,
+                    ))
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::AcceptAdmin {} => {
+                let config = locker_client::fetch_config(&client.rpc(), &client.id())?;
+
+                let r = client
+                    .request()
+                    .instruction(locker_client::instruction::accept_admin(
+                        &client.id(),
+                        &config.admin,
+                        &client.payer(),
+                    ))
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::EmergencyUnlock { locker, reason } => {
+                let locker_account = locker_client::fetch_locker(&client.rpc(), &locker)?;
+
+                let r = client
+                    .request()
+                    .instruction(locker_client::instruction::emergency_unlock(
+                        &client.id(),
+                        &client.payer(),
+                        &locker,
+                        &locker_account,
+                        reason,
+                    ))
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::ShowConfig {} => {
+                let config = locker_client::fetch_config(&client.rpc(), &client.id())?;
                 println!("{:#?}", config);
             }
             LockerCmd::ShowLocker { locker } => {
-                let locker: locker::Locker = client.account(locker)?;
+                let locker = locker_client::fetch_locker(&client.rpc(), &locker)?;
                 println!("{:#?}", locker);
             }
+            LockerCmd::LockerAddress {
+                creator,
+                mint,
+                nonce,
+            } => {
+                let (locker, _bump) =
+                    locker_client::pda::find_locker_address(&client.id(), &creator, &mint, nonce);
+                println!("{}", locker);
+            }
+            LockerCmd::ShowCreatedLockers { creator, mint } => {
+                let lockers = locker_client::fetch_lockers_created_by(
+                    &client.rpc(),
+                    &client.id(),
+                    &creator,
+                    &mint,
+                )?;
+                for (address, locker) in lockers {
+                    println!("{}: {:#?}", address, locker);
+                }
+            }
         },
     }
 
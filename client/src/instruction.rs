@@ -0,0 +1,889 @@
+//! Instruction builders for the locker program.
+//!
+//! Builders derive all the program addresses themselves, so the bumps
+//! passed within instruction arguments are overwritten with the derived ones.
+
+use anchor_client::{
+    anchor_lang::{InstructionData, ToAccountMetas},
+    solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        program_pack::Pack,
+        pubkey::Pubkey,
+        system_instruction, system_program, sysvar,
+    },
+};
+use anchor_spl::associated_token::get_associated_token_address;
+use anyhow::Result;
+
+use crate::pda;
+use locker::{
+    Config, CreateConfigArgs, CreateLockerArgs, DiscountTier, EmergencyUnlockArgs, Locker, MintFee,
+    SetPauseArgs, StablecoinFee, UpdateConfigArgs,
+};
+
+fn instruction(
+    program_id: &Pubkey,
+    accounts: impl ToAccountMetas,
+    data: impl InstructionData,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+pub fn init_config(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    fee_wallet: &Pubkey,
+    country_list: &Pubkey,
+    mut args: CreateConfigArgs,
+) -> Instruction {
+    let (config, bump) = pda::find_config_address(program_id);
+    args.bump = bump;
+
+    instruction(
+        program_id,
+        locker::accounts::InitConfig {
+            admin: *admin,
+            config,
+            fee_wallet: *fee_wallet,
+            country_list: *country_list,
+            system_program: system_program::ID,
+        },
+        locker::instruction::InitConfig { args },
+    )
+}
+
+pub fn update_config(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    fee_wallet: &Pubkey,
+    country_list: &Pubkey,
+    args: UpdateConfigArgs,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+
+    instruction(
+        program_id,
+        locker::accounts::UpdateConfig {
+            admin: *admin,
+            config,
+            fee_wallet: *fee_wallet,
+            country_list: *country_list,
+        },
+        locker::instruction::UpdateConfig { args },
+    )
+}
+
+/// Moves the fee in SOL of the config created before the fee in lamports
+/// to them, anyone could sign it.
+pub fn migrate_config(program_id: &Pubkey) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+
+    instruction(
+        program_id,
+        locker::accounts::MigrateConfig { config },
+        locker::instruction::MigrateConfig {},
+    )
+}
+
+pub fn set_pause(program_id: &Pubkey, admin: &Pubkey, args: SetPauseArgs) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+
+    instruction(
+        program_id,
+        locker::accounts::SetPause {
+            admin: *admin,
+            config,
+        },
+        locker::instruction::SetPause { args },
+    )
+}
+
+pub fn propose_admin(program_id: &Pubkey, admin: &Pubkey, new_admin: &Pubkey) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (pending_admin, _bump) = pda::find_pending_admin_address(program_id);
+
+    instruction(
+        program_id,
+        locker::accounts::ProposeAdmin {
+            admin: *admin,
+            config,
+            pending_admin,
+            new_admin: *new_admin,
+            system_program: system_program::ID,
+        },
+        locker::instruction::ProposeAdmin {},
+    )
+}
+
+/// Should be signed by the admin proposed with `propose_admin`,
+/// `admin` is the current one.
+pub fn accept_admin(program_id: &Pubkey, admin: &Pubkey, new_admin: &Pubkey) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (pending_admin, _bump) = pda::find_pending_admin_address(program_id);
+
+    instruction(
+        program_id,
+        locker::accounts::AcceptAdmin {
+            new_admin: *new_admin,
+            config,
+            pending_admin,
+            admin: *admin,
+        },
+        locker::instruction::AcceptAdmin {},
+    )
+}
+
+pub fn init_mint_info(program_id: &Pubkey, payer: &Pubkey, mint: &Pubkey) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (mint_info, bump) = pda::find_mint_info_address(program_id, mint);
+
+    instruction(
+        program_id,
+        locker::accounts::InitMintInfo {
+            payer: *payer,
+            mint_info,
+            mint: *mint,
+            config,
+            system_program: system_program::ID,
+        },
+        locker::instruction::InitMintInfo { bump },
+    )
+}
+
+/// Should be signed by the config admin, the default `fee` restores
+/// the config fees for the mint.
+pub fn set_mint_fee(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    fee: MintFee,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (mint_fee, _bump) = pda::find_mint_fee_address(program_id, mint);
+
+    instruction(
+        program_id,
+        locker::accounts::SetMintFee {
+            admin: *admin,
+            mint_fee,
+            mint: *mint,
+            config,
+            system_program: system_program::ID,
+        },
+        locker::instruction::SetMintFee { fee },
+    )
+}
+
+/// Should be signed by the config admin, no `tiers` stop the discounts.
+pub fn set_fee_discounts(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    tiers: Vec<DiscountTier>,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (fee_discounts, _bump) = pda::find_fee_discounts_address(program_id);
+
+    instruction(
+        program_id,
+        locker::accounts::SetFeeDiscounts {
+            admin: *admin,
+            fee_discounts,
+            config,
+            system_program: system_program::ID,
+        },
+        locker::instruction::SetFeeDiscounts { mint: *mint, tiers },
+    )
+}
+
+/// Should be signed by the config admin, `None` stops accepting the fee
+/// in the stablecoin.
+pub fn set_stablecoin_fee(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    fee: Option<StablecoinFee>,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (stablecoin_fee, _bump) = pda::find_stablecoin_fee_address(program_id);
+
+    instruction(
+        program_id,
+        locker::accounts::SetStablecoinFee {
+            admin: *admin,
+            stablecoin_fee,
+            config,
+            system_program: system_program::ID,
+        },
+        locker::instruction::SetStablecoinFee { fee },
+    )
+}
+
+/// Pays the flat fee of `create_locker` or `create_locker_with_pda` with
+/// `fee_in_stablecoin` set, `fee` is the one from `fetch_stablecoin_fee`.
+/// The wallet associated with the funding wallet authority pays to the one
+/// of the fee wallet. Should be added before the fee discount accounts.
+pub fn add_stablecoin_fee_accounts(
+    instruction: &mut Instruction,
+    config: &Config,
+    fee: &StablecoinFee,
+    funding_wallet_authority: &Pubkey,
+) {
+    instruction.accounts.extend([
+        AccountMeta::new(
+            get_associated_token_address(funding_wallet_authority, &fee.mint),
+            false,
+        ),
+        AccountMeta::new(
+            get_associated_token_address(&config.fee_wallet, &fee.mint),
+            false,
+        ),
+    ]);
+}
+
+/// Claims the fee discount for `create_locker`, `create_locker_with_pda`
+/// or `increment_lock`, `holder_wallet` should be owned by the funding
+/// wallet authority and hold the discount token.
+pub fn add_fee_discount_accounts(
+    program_id: &Pubkey,
+    instruction: &mut Instruction,
+    holder_wallet: &Pubkey,
+) {
+    let (fee_discounts, _bump) = pda::find_fee_discounts_address(program_id);
+
+    instruction.accounts.extend([
+        AccountMeta::new_readonly(fee_discounts, false),
+        AccountMeta::new_readonly(*holder_wallet, false),
+    ]);
+}
+
+/// Creates and initializes the SPL token account owned by `authority`.
+/// Use it to create a vault before `split_locker`, `lamports` should be
+/// enough for the account to be rent exempt.
+pub fn create_vault(
+    payer: &Pubkey,
+    vault: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    lamports: u64,
+) -> Result<Vec<Instruction>> {
+    Ok(vec![
+        system_instruction::create_account(
+            payer,
+            vault,
+            lamports,
+            spl_token::state::Account::LEN as u64,
+            &spl_token::ID,
+        ),
+        spl_token::instruction::initialize_account(&spl_token::ID, vault, mint, authority)?,
+    ])
+}
+
+/// Accounts required to create a new locker.
+pub struct CreateLockerAccounts {
+    /// New locker account, it should sign the transaction.
+    pub locker: Pubkey,
+    pub creator: Pubkey,
+    pub owner: Pubkey,
+    pub funding_wallet_authority: Pubkey,
+    pub funding_wallet: Pubkey,
+    pub mint: Pubkey,
+}
+
+pub fn create_locker(
+    program_id: &Pubkey,
+    accounts: CreateLockerAccounts,
+    config: &Config,
+    mut args: CreateLockerArgs,
+) -> Instruction {
+    let (config_address, _bump) = pda::find_config_address(program_id);
+    let (mint_info, _bump) = pda::find_mint_info_address(program_id, &accounts.mint);
+    let (mint_fee, _bump) = pda::find_mint_fee_address(program_id, &accounts.mint);
+    let (stablecoin_fee, _bump) = pda::find_stablecoin_fee_address(program_id);
+    let (vault_authority, vault_bump) =
+        pda::find_vault_authority_address(program_id, &accounts.locker);
+    let (vault, _bump) = pda::find_vault_address(program_id, &accounts.locker);
+    args.vault_bump = vault_bump;
+
+    instruction(
+        program_id,
+        locker::accounts::CreateLocker {
+            locker: accounts.locker,
+            creator: accounts.creator,
+            owner: accounts.owner,
+            funding_wallet_authority: accounts.funding_wallet_authority,
+            funding_wallet: accounts.funding_wallet,
+            vault_authority,
+            mint: accounts.mint,
+            vault,
+            fee_wallet: config.fee_wallet,
+            fee_token_wallet: get_associated_token_address(&config.fee_wallet, &accounts.mint),
+            mint_info,
+            mint_fee,
+            stablecoin_fee,
+            config: config_address,
+            country_banlist: config.country_list,
+            clock: sysvar::clock::ID,
+            rent: sysvar::rent::ID,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+        },
+        locker::instruction::CreateLocker { args },
+    )
+}
+
+pub fn init_creator_info(program_id: &Pubkey, creator: &Pubkey) -> Instruction {
+    let (creator_info, bump) = pda::find_creator_info_address(program_id, creator);
+
+    instruction(
+        program_id,
+        locker::accounts::InitCreatorInfo {
+            creator: *creator,
+            creator_info,
+            system_program: system_program::ID,
+        },
+        locker::instruction::InitCreatorInfo { bump },
+    )
+}
+
+/// Accounts required to create a new locker at the PDA.
+pub struct CreatePdaLockerAccounts {
+    pub creator: Pubkey,
+    pub owner: Pubkey,
+    pub funding_wallet_authority: Pubkey,
+    pub funding_wallet: Pubkey,
+    pub mint: Pubkey,
+}
+
+/// Creates the locker at `pda::find_locker_address`, `nonce` should be
+/// equal to `CreatorInfo::lockers_created` of the creator, see
+/// `init_creator_info`.
+pub fn create_locker_with_pda(
+    program_id: &Pubkey,
+    accounts: CreatePdaLockerAccounts,
+    config: &Config,
+    nonce: u64,
+    mut args: CreateLockerArgs,
+) -> Instruction {
+    let (config_address, _bump) = pda::find_config_address(program_id);
+    let (creator_info, _bump) = pda::find_creator_info_address(program_id, &accounts.creator);
+    let (locker, _bump) =
+        pda::find_locker_address(program_id, &accounts.creator, &accounts.mint, nonce);
+    let (mint_info, _bump) = pda::find_mint_info_address(program_id, &accounts.mint);
+    let (mint_fee, _bump) = pda::find_mint_fee_address(program_id, &accounts.mint);
+    let (stablecoin_fee, _bump) = pda::find_stablecoin_fee_address(program_id);
+    let (vault_authority, vault_bump) = pda::find_vault_authority_address(program_id, &locker);
+    let (vault, _bump) = pda::find_vault_address(program_id, &locker);
+    args.vault_bump = vault_bump;
+
+    instruction(
+        program_id,
+        locker::accounts::CreatePdaLocker {
+            creator: accounts.creator,
+            creator_info,
+            owner: accounts.owner,
+            funding_wallet_authority: accounts.funding_wallet_authority,
+            funding_wallet: accounts.funding_wallet,
+            locker,
+            vault_authority,
+            mint: accounts.mint,
+            vault,
+            fee_wallet: config.fee_wallet,
+            fee_token_wallet: get_associated_token_address(&config.fee_wallet, &accounts.mint),
+            mint_info,
+            mint_fee,
+            stablecoin_fee,
+            config: config_address,
+            country_banlist: config.country_list,
+            clock: sysvar::clock::ID,
+            rent: sysvar::rent::ID,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+        },
+        locker::instruction::CreateLockerWithPda { args },
+    )
+}
+
+pub fn relock(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    owner: &Pubkey,
+    unlock_date: i64,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+
+    instruction(
+        program_id,
+        locker::accounts::Relock {
+            locker: *locker,
+            owner: *owner,
+            config,
+        },
+        locker::instruction::Relock { unlock_date },
+    )
+}
+
+/// One-step transfer, prefer `propose_owner` unless the new owner can't sign.
+/// The program rejects it unless `unsafe_skip_acceptance` is set.
+pub fn transfer_ownership(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    owner: &Pubkey,
+    new_owner: &Pubkey,
+    unsafe_skip_acceptance: bool,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+
+    instruction(
+        program_id,
+        locker::accounts::TransferOwnership {
+            locker: *locker,
+            owner: *owner,
+            new_owner: *new_owner,
+            config,
+        },
+        locker::instruction::TransferOwnership {
+            unsafe_skip_acceptance,
+        },
+    )
+}
+
+pub fn propose_owner(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    owner: &Pubkey,
+    new_owner: &Pubkey,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (pending_owner, _bump) = pda::find_pending_owner_address(program_id, locker);
+
+    instruction(
+        program_id,
+        locker::accounts::ProposeOwner {
+            locker: *locker,
+            owner: *owner,
+            pending_owner,
+            new_owner: *new_owner,
+            config,
+            system_program: system_program::ID,
+        },
+        locker::instruction::ProposeOwner {},
+    )
+}
+
+/// Should be signed by the owner proposed with `propose_owner`,
+/// `owner` is the current one.
+pub fn accept_ownership(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    owner: &Pubkey,
+    new_owner: &Pubkey,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (pending_owner, _bump) = pda::find_pending_owner_address(program_id, locker);
+
+    instruction(
+        program_id,
+        locker::accounts::AcceptOwnership {
+            locker: *locker,
+            new_owner: *new_owner,
+            pending_owner,
+            owner: *owner,
+            config,
+        },
+        locker::instruction::AcceptOwnership {},
+    )
+}
+
+pub fn cancel_ownership_transfer(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    owner: &Pubkey,
+) -> Instruction {
+    let (pending_owner, _bump) = pda::find_pending_owner_address(program_id, locker);
+
+    instruction(
+        program_id,
+        locker::accounts::CancelOwnershipTransfer {
+            locker: *locker,
+            owner: *owner,
+            pending_owner,
+        },
+        locker::instruction::CancelOwnershipTransfer {},
+    )
+}
+
+/// Should be signed by the locker owner, who pays for the beneficiary account.
+pub fn set_beneficiary(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    owner: &Pubkey,
+    beneficiary: Pubkey,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (locker_beneficiary, _bump) = pda::find_beneficiary_address(program_id, locker);
+
+    instruction(
+        program_id,
+        locker::accounts::SetBeneficiary {
+            locker: *locker,
+            owner: *owner,
+            beneficiary: locker_beneficiary,
+            config,
+            system_program: system_program::ID,
+        },
+        locker::instruction::SetBeneficiary { beneficiary },
+    )
+}
+
+/// Should be signed by the locker owner, who gets the rent back.
+pub fn remove_beneficiary(program_id: &Pubkey, locker: &Pubkey, owner: &Pubkey) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (beneficiary, _bump) = pda::find_beneficiary_address(program_id, locker);
+
+    instruction(
+        program_id,
+        locker::accounts::RemoveBeneficiary {
+            locker: *locker,
+            owner: *owner,
+            beneficiary,
+            config,
+        },
+        locker::instruction::RemoveBeneficiary {},
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn increment_lock(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    locker_account: &Locker,
+    mint: &Pubkey,
+    config: &Config,
+    funding_wallet_authority: &Pubkey,
+    funding_wallet: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (config_address, _bump) = pda::find_config_address(program_id);
+    let (mint_info, _bump) = pda::find_mint_info_address(program_id, mint);
+    let (mint_fee, _bump) = pda::find_mint_fee_address(program_id, mint);
+
+    instruction(
+        program_id,
+        locker::accounts::IncrementLock {
+            locker: *locker,
+            vault: locker_account.vault,
+            mint_info,
+            mint_fee,
+            funding_wallet_authority: *funding_wallet_authority,
+            funding_wallet: *funding_wallet,
+            fee_wallet: get_associated_token_address(&config.fee_wallet, mint),
+            config: config_address,
+            token_program: spl_token::ID,
+        },
+        locker::instruction::IncrementLock { amount },
+    )
+}
+
+pub fn withdraw_funds(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    locker_account: &Locker,
+    target_wallet: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (vault_authority, _bump) = pda::find_vault_authority_address(program_id, locker);
+    let (beneficiary, _bump) = pda::find_beneficiary_address(program_id, locker);
+
+    instruction(
+        program_id,
+        locker::accounts::WithdrawFunds {
+            locker: *locker,
+            owner: locker_account.owner,
+            vault_authority,
+            vault: locker_account.vault,
+            target_wallet: *target_wallet,
+            beneficiary,
+            config,
+            clock: sysvar::clock::ID,
+            token_program: spl_token::ID,
+        },
+        locker::instruction::WithdrawFunds { amount },
+    )
+}
+
+/// Releases the vested funds to the associated token account of the
+/// beneficiary, or of the owner if there's none. Anyone could sign it.
+///
+/// `beneficiary` is the one of the locker, see `fetch_beneficiary`.
+pub fn release_vested(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    locker_account: &Locker,
+    beneficiary: Option<Pubkey>,
+    mint: &Pubkey,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (vault_authority, _bump) = pda::find_vault_authority_address(program_id, locker);
+    let recipient = beneficiary.unwrap_or(locker_account.owner);
+    let (beneficiary, _bump) = pda::find_beneficiary_address(program_id, locker);
+
+    instruction(
+        program_id,
+        locker::accounts::ReleaseVested {
+            locker: *locker,
+            owner: locker_account.owner,
+            vault_authority,
+            vault: locker_account.vault,
+            target_wallet: get_associated_token_address(&recipient, mint),
+            beneficiary,
+            config,
+            clock: sysvar::clock::ID,
+            token_program: spl_token::ID,
+        },
+        locker::instruction::ReleaseVested {},
+    )
+}
+
+/// Should be signed by the revoke authority of the locker, the unvested
+/// funds go to `treasury_wallet`.
+pub fn revoke_locker(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    locker_account: &Locker,
+    revoke_authority: &Pubkey,
+    treasury_wallet: &Pubkey,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (vault_authority, _bump) = pda::find_vault_authority_address(program_id, locker);
+    let (beneficiary, _bump) = pda::find_beneficiary_address(program_id, locker);
+
+    instruction(
+        program_id,
+        locker::accounts::RevokeLocker {
+            locker: *locker,
+            revoke_authority: *revoke_authority,
+            owner: locker_account.owner,
+            vault_authority,
+            vault: locker_account.vault,
+            treasury_wallet: *treasury_wallet,
+            beneficiary,
+            config,
+            clock: sysvar::clock::ID,
+            token_program: spl_token::ID,
+        },
+        locker::instruction::RevokeLocker {},
+    )
+}
+
+/// Splits `amount` from the old locker to the new one. Returns the instruction
+/// and the address of the new locker, `new_vault` should be owned by its
+/// vault authority. The new locker gets the beneficiary of the old one.
+pub fn split_locker(
+    program_id: &Pubkey,
+    old_locker: &Pubkey,
+    old_locker_account: &Locker,
+    new_owner: &Pubkey,
+    new_vault: &Pubkey,
+    amount: u64,
+) -> (Instruction, Pubkey) {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (old_vault_authority, _bump) = pda::find_vault_authority_address(program_id, old_locker);
+    let (new_locker, locker_bump) = pda::find_split_locker_address(
+        program_id,
+        old_locker,
+        old_locker_account.current_unlock_date,
+        amount,
+    );
+    let (new_vault_authority, vault_bump) =
+        pda::find_vault_authority_address(program_id, &new_locker);
+    let (old_beneficiary, _bump) = pda::find_beneficiary_address(program_id, old_locker);
+    let (new_beneficiary, _bump) = pda::find_beneficiary_address(program_id, &new_locker);
+
+    let ix = instruction(
+        program_id,
+        locker::accounts::SplitLocker {
+            old_locker: *old_locker,
+            old_owner: old_locker_account.owner,
+            old_vault_authority,
+            old_vault: old_locker_account.vault,
+            new_locker,
+            new_owner: *new_owner,
+            new_vault_authority,
+            new_vault: *new_vault,
+            old_beneficiary,
+            new_beneficiary,
+            config,
+            rent: sysvar::rent::ID,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        locker::instruction::SplitLocker {
+            args: locker::SplitLockerArgs {
+                locker_bump,
+                vault_bump,
+                amount,
+            },
+        },
+    );
+
+    (ix, new_locker)
+}
+
+/// Splits the old locker into `parts` of `(amount, new_owner)` at once.
+/// Returns the instruction and the addresses of the new lockers, their
+/// vaults are created by the program. The new lockers get the beneficiary
+/// of the old one.
+pub fn split_locker_batch(
+    program_id: &Pubkey,
+    old_locker: &Pubkey,
+    old_locker_account: &Locker,
+    mint: &Pubkey,
+    parts: &[(u64, Pubkey)],
+) -> (Instruction, Vec<Pubkey>) {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (old_vault_authority, _bump) = pda::find_vault_authority_address(program_id, old_locker);
+    let (old_beneficiary, _bump) = pda::find_beneficiary_address(program_id, old_locker);
+
+    let mut new_accounts = vec![];
+    let mut split_parts = vec![];
+    for (index, &(amount, new_owner)) in (0..).zip(parts) {
+        let (new_locker, locker_bump) = pda::find_batch_split_locker_address(
+            program_id,
+            old_locker,
+            old_locker_account.split_count + index,
+        );
+        let (new_vault_authority, vault_bump) =
+            pda::find_vault_authority_address(program_id, &new_locker);
+        let (new_vault, vault_account_bump) = pda::find_vault_address(program_id, &new_locker);
+        let (new_beneficiary, _bump) = pda::find_beneficiary_address(program_id, &new_locker);
+
+        new_accounts.push((new_locker, new_vault_authority, new_vault, new_beneficiary));
+        split_parts.push(locker::SplitPart {
+            amount,
+            new_owner,
+            locker_bump,
+            vault_bump,
+            vault_account_bump,
+        });
+    }
+
+    let mut ix = instruction(
+        program_id,
+        locker::accounts::SplitLockerBatch {
+            old_locker: *old_locker,
+            old_owner: old_locker_account.owner,
+            old_vault_authority,
+            old_vault: old_locker_account.vault,
+            mint: *mint,
+            old_beneficiary,
+            config,
+            rent: sysvar::rent::ID,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        locker::instruction::SplitLockerBatch {
+            args: locker::SplitLockerBatchArgs { parts: split_parts },
+        },
+    );
+
+    let mut new_lockers = vec![];
+    for (new_locker, new_vault_authority, new_vault, new_beneficiary) in new_accounts {
+        ix.accounts.extend([
+            AccountMeta::new(new_locker, false),
+            AccountMeta::new_readonly(new_vault_authority, false),
+            AccountMeta::new(new_vault, false),
+            AccountMeta::new(new_beneficiary, false),
+        ]);
+        new_lockers.push(new_locker);
+    }
+
+    (ix, new_lockers)
+}
+
+/// Moves the whole source locker into the target one, both should
+/// belong to the same owner.
+pub fn merge_lockers(
+    program_id: &Pubkey,
+    source_locker: &Pubkey,
+    source_locker_account: &Locker,
+    target_locker: &Pubkey,
+    target_locker_account: &Locker,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (source_vault_authority, _bump) =
+        pda::find_vault_authority_address(program_id, source_locker);
+    let (source_beneficiary, _bump) = pda::find_beneficiary_address(program_id, source_locker);
+    let (target_beneficiary, _bump) = pda::find_beneficiary_address(program_id, target_locker);
+
+    instruction(
+        program_id,
+        locker::accounts::MergeLockers {
+            source_locker: *source_locker,
+            target_locker: *target_locker,
+            owner: target_locker_account.owner,
+            source_vault_authority,
+            source_vault: source_locker_account.vault,
+            target_vault: target_locker_account.vault,
+            source_beneficiary,
+            target_beneficiary,
+            config,
+            token_program: spl_token::ID,
+        },
+        locker::instruction::MergeLockers {},
+    )
+}
+
+pub fn migrate_locker(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    locker_account: &Locker,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+
+    instruction(
+        program_id,
+        locker::accounts::MigrateLocker {
+            locker: *locker,
+            vault: locker_account.vault,
+            config,
+        },
+        locker::instruction::MigrateLocker {},
+    )
+}
+
+/// Unlocks the locker before the unlock date, admin only.
+pub fn emergency_unlock(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    locker: &Pubkey,
+    locker_account: &Locker,
+    reason: String,
+) -> Instruction {
+    let (config, _bump) = pda::find_config_address(program_id);
+    let (record, _bump) = pda::find_emergency_unlock_record_address(
+        program_id,
+        locker,
+        locker_account.emergency_unlocks,
+    );
+
+    instruction(
+        program_id,
+        locker::accounts::EmergencyUnlock {
+            admin: *admin,
+            config,
+            locker: *locker,
+            vault: locker_account.vault,
+            record,
+            clock: sysvar::clock::ID,
+            system_program: system_program::ID,
+        },
+        locker::instruction::EmergencyUnlock {
+            args: EmergencyUnlockArgs { reason },
+        },
+    )
+}
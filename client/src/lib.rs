@@ -0,0 +1,236 @@
+//! Rust client for the locker program.
+//!
+//! * [`pda`] -- program derived addresses used by the program;
+//! * [`instruction`] -- builders for every program instruction;
+//! * fetchers for `Config`, `Locker`, `MintInfo`, `CreatorInfo`,
+//!   `LockerBeneficiary` and `StablecoinFeeConfig` accounts.
+
+pub mod instruction;
+pub mod pda;
+
+use anchor_client::{
+    anchor_lang::{AccountDeserialize, Discriminator},
+    solana_client::{
+        rpc_client::RpcClient,
+        rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+        rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+    },
+    solana_sdk::{bs58, pubkey::Pubkey},
+};
+use anyhow::{anyhow, Result};
+use solana_account_decoder::UiAccountEncoding;
+
+pub use locker::{
+    Cliff, Config, CreateConfigArgs, CreateLockerArgs, CreatorInfo, EmergencyUnlockArgs,
+    EmergencyUnlockRecord, Locker, LockerBeneficiary, MintInfo, SetPauseArgs, SplitLockerArgs,
+    StablecoinFee, StablecoinFeeConfig, Tranche, UpdateConfigArgs,
+};
+
+/// Max number of accounts in `getMultipleAccounts`.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Fetches and decodes any account of the locker program.
+pub fn fetch_account<T: AccountDeserialize>(rpc: &RpcClient, address: &Pubkey) -> Result<T> {
+    let account = rpc.get_account(address)?;
+    let mut data: &[u8] = &account.data;
+
+    T::try_deserialize(&mut data)
+        .map_err(|err| anyhow!("failed to decode account {}: {}", address, err))
+}
+
+/// Fetches the config of the locker program.
+pub fn fetch_config(rpc: &RpcClient, program_id: &Pubkey) -> Result<Config> {
+    let (config, _bump) = pda::find_config_address(program_id);
+    fetch_account(rpc, &config)
+}
+
+pub fn fetch_locker(rpc: &RpcClient, locker: &Pubkey) -> Result<Locker> {
+    fetch_account(rpc, locker)
+}
+
+/// Fetches mint info for the given mint. Returns `None` if mint info
+/// hasn't been created yet, i.e. the mint has never been locked.
+pub fn fetch_mint_info(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    mint: &Pubkey,
+) -> Result<Option<MintInfo>> {
+    let (mint_info, _bump) = pda::find_mint_info_address(program_id, mint);
+    let account = rpc
+        .get_account_with_commitment(&mint_info, rpc.commitment())?
+        .value;
+
+    match account {
+        Some(account) => {
+            let mut data: &[u8] = &account.data;
+            let mint_info = MintInfo::try_deserialize(&mut data)
+                .map_err(|err| anyhow!("failed to decode mint info {}: {}", mint_info, err))?;
+            Ok(Some(mint_info))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Fetches the flat fee in the stablecoin. Returns `None` if the stablecoin
+/// isn't accepted.
+pub fn fetch_stablecoin_fee(rpc: &RpcClient, program_id: &Pubkey) -> Result<Option<StablecoinFee>> {
+    let (stablecoin_fee, _bump) = pda::find_stablecoin_fee_address(program_id);
+    let account = rpc
+        .get_account_with_commitment(&stablecoin_fee, rpc.commitment())?
+        .value;
+
+    match account {
+        Some(account) => {
+            let mut data: &[u8] = &account.data;
+            let stablecoin_fee_config =
+                StablecoinFeeConfig::try_deserialize(&mut data).map_err(|err| {
+                    anyhow!(
+                        "failed to decode stablecoin fee {}: {}",
+                        stablecoin_fee,
+                        err
+                    )
+                })?;
+            Ok(stablecoin_fee_config.fee)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Fetches the beneficiary of the given locker. Returns `None` if the
+/// locker has none, so the owner withdraws anywhere.
+pub fn fetch_beneficiary(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    locker: &Pubkey,
+) -> Result<Option<Pubkey>> {
+    let (beneficiary, _bump) = pda::find_beneficiary_address(program_id, locker);
+    let account = rpc
+        .get_account_with_commitment(&beneficiary, rpc.commitment())?
+        .value;
+
+    match account {
+        Some(account) => {
+            let mut data: &[u8] = &account.data;
+            let locker_beneficiary = LockerBeneficiary::try_deserialize(&mut data)
+                .map_err(|err| anyhow!("failed to decode beneficiary {}: {}", beneficiary, err))?;
+            Ok(Some(locker_beneficiary.beneficiary))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Fetches creator info of the given creator. Returns `None` if the creator
+/// has never created the lockers with `create_locker_with_pda`.
+pub fn fetch_creator_info(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    creator: &Pubkey,
+) -> Result<Option<CreatorInfo>> {
+    let (creator_info, _bump) = pda::find_creator_info_address(program_id, creator);
+    let account = rpc
+        .get_account_with_commitment(&creator_info, rpc.commitment())?
+        .value;
+
+    match account {
+        Some(account) => {
+            let mut data: &[u8] = &account.data;
+            let info = CreatorInfo::try_deserialize(&mut data).map_err(|err| {
+                anyhow!("failed to decode creator info {}: {}", creator_info, err)
+            })?;
+            Ok(Some(info))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Fetches the lockers of the mint created by `creator` with
+/// `create_locker_with_pda` by deriving their addresses, so unlike
+/// `fetch_lockers` it doesn't scan the program accounts. Closed lockers
+/// are skipped.
+pub fn fetch_lockers_created_by(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    creator: &Pubkey,
+    mint: &Pubkey,
+) -> Result<Vec<(Pubkey, Locker)>> {
+    let lockers_created = fetch_creator_info(rpc, program_id, creator)?
+        .map(|info| info.lockers_created)
+        .unwrap_or_default();
+
+    let addresses: Vec<Pubkey> = (0..lockers_created)
+        .map(|nonce| pda::find_locker_address(program_id, creator, mint, nonce).0)
+        .collect();
+
+    let mut lockers = vec![];
+    for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = rpc.get_multiple_accounts(chunk)?;
+
+        for (address, account) in chunk.iter().zip(accounts) {
+            if let Some(account) = account {
+                let mut data: &[u8] = &account.data;
+                let locker = Locker::try_deserialize(&mut data)
+                    .map_err(|err| anyhow!("failed to decode locker {}: {}", address, err))?;
+                lockers.push((*address, locker));
+            }
+        }
+    }
+
+    Ok(lockers)
+}
+
+/// Fetches all the lockers of the program.
+pub fn fetch_lockers(rpc: &RpcClient, program_id: &Pubkey) -> Result<Vec<(Pubkey, Locker)>> {
+    fetch_lockers_with_filters(rpc, program_id, vec![])
+}
+
+/// Fetches the lockers owned by the given account.
+pub fn fetch_lockers_owned_by(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    owner: &Pubkey,
+) -> Result<Vec<(Pubkey, Locker)>> {
+    let owner_filter = RpcFilterType::Memcmp(Memcmp {
+        // 8 bytes for discriminator
+        offset: 8,
+        bytes: MemcmpEncodedBytes::Base58(owner.to_string()),
+        encoding: None,
+    });
+
+    fetch_lockers_with_filters(rpc, program_id, vec![owner_filter])
+}
+
+fn fetch_lockers_with_filters(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    mut filters: Vec<RpcFilterType>,
+) -> Result<Vec<(Pubkey, Locker)>> {
+    // We can't filter by data size since lockers created by different
+    // versions of the program have different sizes.
+    filters.push(RpcFilterType::Memcmp(Memcmp {
+        offset: 0,
+        bytes: MemcmpEncodedBytes::Base58(bs58::encode(Locker::discriminator()).into_string()),
+        encoding: None,
+    }));
+
+    let accounts = rpc.get_program_accounts_with_config(
+        program_id,
+        RpcProgramAccountsConfig {
+            filters: Some(filters),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..Default::default()
+            },
+            with_context: None,
+        },
+    )?;
+
+    accounts
+        .into_iter()
+        .map(|(address, account)| {
+            let mut data: &[u8] = &account.data;
+            let locker = Locker::try_deserialize(&mut data)
+                .map_err(|err| anyhow!("failed to decode locker {}: {}", address, err))?;
+            Ok((address, locker))
+        })
+        .collect()
+}
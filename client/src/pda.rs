@@ -0,0 +1,129 @@
+//! Program derived addresses of the locker program.
+//!
+//! Every function returns the address together with its bump.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&["config".as_ref()], program_id)
+}
+
+/// Admin proposed by `propose_admin`, exists until accepted.
+pub fn find_pending_admin_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&["pending_admin".as_ref()], program_id)
+}
+
+/// Flat fee in the stablecoin set by `set_stablecoin_fee`.
+pub fn find_stablecoin_fee_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&["stablecoin_fee".as_ref()], program_id)
+}
+
+/// Mint info tracks the fees paid for the given mint.
+pub fn find_mint_info_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[mint.as_ref()], program_id)
+}
+
+/// Fees of the given mint set by `set_mint_fee` instead of the config ones.
+pub fn find_mint_fee_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&["mint_fee".as_ref(), mint.as_ref()], program_id)
+}
+
+/// Fee discount tiers for the holders of the platform token.
+pub fn find_fee_discounts_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&["fee_discounts".as_ref()], program_id)
+}
+
+/// Creator info counts the lockers created by `creator` with
+/// `create_locker_with_pda`.
+pub fn find_creator_info_address(program_id: &Pubkey, creator: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&["creator".as_ref(), creator.as_ref()], program_id)
+}
+
+/// Locker created with `create_locker_with_pda`, `nonce` is the number
+/// of lockers created by `creator` before this one.
+pub fn find_locker_address(
+    program_id: &Pubkey,
+    creator: &Pubkey,
+    mint: &Pubkey,
+    nonce: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            "locker".as_ref(),
+            creator.as_ref(),
+            mint.as_ref(),
+            nonce.to_be_bytes().as_ref(),
+        ],
+        program_id,
+    )
+}
+
+/// Owner proposed by `propose_owner`, exists until accepted or cancelled.
+pub fn find_pending_owner_address(program_id: &Pubkey, locker: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&["pending_owner".as_ref(), locker.as_ref()], program_id)
+}
+
+/// Beneficiary set by `set_beneficiary`, exists until removed or the
+/// locker is closed.
+pub fn find_beneficiary_address(program_id: &Pubkey, locker: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&["beneficiary".as_ref(), locker.as_ref()], program_id)
+}
+
+/// Vault authority signs the transfers from the locker vault.
+pub fn find_vault_authority_address(program_id: &Pubkey, locker: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[locker.as_ref()], program_id)
+}
+
+/// Vault of `locker` created by `create_locker` and `create_locker_with_pda`.
+pub fn find_vault_address(program_id: &Pubkey, locker: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&["vault".as_ref(), locker.as_ref()], program_id)
+}
+
+/// Audit record of the `index`-th emergency unlock of `locker`.
+pub fn find_emergency_unlock_record_address(
+    program_id: &Pubkey,
+    locker: &Pubkey,
+    index: u32,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            "emergency_unlock".as_ref(),
+            locker.as_ref(),
+            index.to_be_bytes().as_ref(),
+        ],
+        program_id,
+    )
+}
+
+/// New locker created by splitting `old_locker`.
+pub fn find_split_locker_address(
+    program_id: &Pubkey,
+    old_locker: &Pubkey,
+    current_unlock_date: i64,
+    amount: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            old_locker.as_ref(),
+            current_unlock_date.to_be_bytes().as_ref(),
+            amount.to_be_bytes().as_ref(),
+        ],
+        program_id,
+    )
+}
+
+/// `index`-th locker split out of `old_locker` by `split_locker_batch`.
+pub fn find_batch_split_locker_address(
+    program_id: &Pubkey,
+    old_locker: &Pubkey,
+    index: u32,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            "split".as_ref(),
+            old_locker.as_ref(),
+            index.to_be_bytes().as_ref(),
+        ],
+        program_id,
+    )
+}
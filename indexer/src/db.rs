@@ -0,0 +1,948 @@
+//! SQLite storage of the indexed lockers and their history.
+//!
+//! Token amounts are `u64` while SQLite integers are `i64`, so amounts are
+//! stored bit-cast and converted back on read. Comparisons and sums in SQL
+//! are wrong for amounts above `i64::MAX`, the queries below don't use them.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+
+use locker::FeeCurrency;
+
+use crate::decode::{DecodedTransaction, LockerEvent};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS lockers (
+    address TEXT PRIMARY KEY,
+    owner TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    vault TEXT NOT NULL,
+    unlock_date INTEGER NOT NULL,
+    start_emission INTEGER,
+    deposited_amount INTEGER NOT NULL,
+    withdrawn_amount INTEGER NOT NULL,
+    closed INTEGER NOT NULL,
+    created_slot INTEGER NOT NULL,
+    updated_slot INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS lockers_owner ON lockers (owner);
+CREATE INDEX IF NOT EXISTS lockers_mint ON lockers (mint);
+CREATE INDEX IF NOT EXISTS lockers_unlock_date ON lockers (unlock_date);
+
+CREATE TABLE IF NOT EXISTS deposits (
+    signature TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    locker TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    fee INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS deposits_locker ON deposits (locker);
+
+CREATE TABLE IF NOT EXISTS withdrawals (
+    signature TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    locker TEXT NOT NULL,
+    owner TEXT NOT NULL,
+    target_wallet TEXT NOT NULL,
+    amount INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS withdrawals_locker ON withdrawals (locker);
+
+CREATE TABLE IF NOT EXISTS fee_payments (
+    signature TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    payer TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    in_sol INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS fee_payments_mint ON fee_payments (mint);
+
+CREATE TABLE IF NOT EXISTS stablecoin_fee_payments (
+    signature TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    payer TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    stablecoin_mint TEXT NOT NULL,
+    amount INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS stablecoin_fee_payments_mint ON stablecoin_fee_payments (mint);
+
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT PRIMARY KEY,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    instructions TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sync_state (
+    program_id TEXT PRIMARY KEY,
+    last_signature TEXT NOT NULL
+);
+";
+
+/// Kinds of the deposits.
+pub const DEPOSIT_CREATE: &str = "create";
+pub const DEPOSIT_INCREMENT: &str = "increment";
+pub const DEPOSIT_SPLIT: &str = "split";
+pub const DEPOSIT_MERGE: &str = "merge";
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct LockerRow {
+    pub address: String,
+    pub owner: String,
+    pub mint: String,
+    pub vault: String,
+    pub unlock_date: i64,
+    pub start_emission: Option<i64>,
+    pub deposited_amount: u64,
+    pub withdrawn_amount: u64,
+    pub closed: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DepositRow {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub locker: String,
+    pub kind: String,
+    /// Amount locked after the fee in tokens.
+    pub amount: u64,
+    pub fee: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct WithdrawalRow {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub locker: String,
+    pub owner: String,
+    pub target_wallet: String,
+    pub amount: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FeePaymentRow {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub payer: String,
+    pub mint: String,
+    /// Lamports if paid in SOL, tokens of the mint otherwise.
+    pub amount: u64,
+    pub in_sol: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct StablecoinFeePaymentRow {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub payer: String,
+    pub mint: String,
+    pub stablecoin_mint: String,
+    /// Tokens of the stablecoin mint.
+    pub amount: u64,
+}
+
+/// Locker query, every field narrows the result.
+#[derive(Debug, Default)]
+pub struct LockerFilter {
+    pub owner: Option<Pubkey>,
+    pub mint: Option<Pubkey>,
+    /// Inclusive bounds of the current unlock date.
+    pub unlock_from: Option<i64>,
+    pub unlock_to: Option<i64>,
+    pub include_closed: bool,
+}
+
+pub struct Db {
+    conn: Connection,
+}
+
+fn to_sql(amount: u64) -> i64 {
+    amount as i64
+}
+
+fn from_sql(amount: i64) -> u64 {
+    amount as u64
+}
+
+impl Db {
+    pub fn open(path: &str) -> Result<Self> {
+        Self::new(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    fn new(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// The newest transaction of the program seen by the indexer.
+    pub fn last_signature(&self, program_id: &Pubkey) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT last_signature FROM sync_state WHERE program_id = ?1",
+                params![program_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Moves the cursor past the transaction which has nothing to index,
+    /// e.g. the failed one.
+    pub fn skip(&mut self, program_id: &Pubkey, signature: &str) -> Result<()> {
+        set_last_signature(&self.conn, program_id, signature)
+    }
+
+    /// Applies the transaction to the index. Returns `false` if it has
+    /// been applied already.
+    pub fn apply(&mut self, program_id: &Pubkey, tx: &DecodedTransaction) -> Result<bool> {
+        let db_tx = self.conn.transaction()?;
+
+        let instructions = tx
+            .instructions
+            .iter()
+            .map(|ix| ix.name)
+            .collect::<Vec<_>>()
+            .join(",");
+        let inserted = db_tx.execute(
+            "INSERT OR IGNORE INTO transactions (signature, slot, block_time, instructions)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![tx.signature, tx.slot, tx.block_time, instructions],
+        )?;
+        set_last_signature(&db_tx, program_id, &tx.signature)?;
+
+        if inserted == 0 {
+            db_tx.commit()?;
+            return Ok(false);
+        }
+
+        for event in &tx.events {
+            apply_event(&db_tx, tx, event)?;
+        }
+
+        db_tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn locker(&self, address: &Pubkey) -> Result<Option<LockerRow>> {
+        lookup_locker(&self.conn, address)
+    }
+
+    /// Lockers matching the filter ordered by the unlock date.
+    pub fn lockers(&self, filter: &LockerFilter) -> Result<Vec<LockerRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM lockers
+             WHERE (?1 IS NULL OR owner = ?1)
+               AND (?2 IS NULL OR mint = ?2)
+               AND (?3 IS NULL OR unlock_date >= ?3)
+               AND (?4 IS NULL OR unlock_date <= ?4)
+               AND (?5 OR closed = 0)
+             ORDER BY unlock_date, address",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                filter.owner.map(|owner| owner.to_string()),
+                filter.mint.map(|mint| mint.to_string()),
+                filter.unlock_from,
+                filter.unlock_to,
+                filter.include_closed,
+            ],
+            locker_row,
+        )?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn deposits(&self, locker: &Pubkey) -> Result<Vec<DepositRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT signature, slot, block_time, locker, kind, amount, fee
+             FROM deposits WHERE locker = ?1 ORDER BY slot, rowid",
+        )?;
+        let rows = stmt.query_map(params![locker.to_string()], |row| {
+            Ok(DepositRow {
+                signature: row.get(0)?,
+                slot: row.get(1)?,
+                block_time: row.get(2)?,
+                locker: row.get(3)?,
+                kind: row.get(4)?,
+                amount: from_sql(row.get(5)?),
+                fee: from_sql(row.get(6)?),
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn withdrawals(&self, locker: &Pubkey) -> Result<Vec<WithdrawalRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT signature, slot, block_time, locker, owner, target_wallet, amount
+             FROM withdrawals WHERE locker = ?1 ORDER BY slot, rowid",
+        )?;
+        let rows = stmt.query_map(params![locker.to_string()], |row| {
+            Ok(WithdrawalRow {
+                signature: row.get(0)?,
+                slot: row.get(1)?,
+                block_time: row.get(2)?,
+                locker: row.get(3)?,
+                owner: row.get(4)?,
+                target_wallet: row.get(5)?,
+                amount: from_sql(row.get(6)?),
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Fee payments for the mint, all of them if `mint` is `None`.
+    pub fn fee_payments(&self, mint: Option<&Pubkey>) -> Result<Vec<FeePaymentRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT signature, slot, block_time, payer, mint, amount, in_sol
+             FROM fee_payments WHERE (?1 IS NULL OR mint = ?1) ORDER BY slot, rowid",
+        )?;
+        let rows = stmt.query_map(params![mint.map(|mint| mint.to_string())], |row| {
+            Ok(FeePaymentRow {
+                signature: row.get(0)?,
+                slot: row.get(1)?,
+                block_time: row.get(2)?,
+                payer: row.get(3)?,
+                mint: row.get(4)?,
+                amount: from_sql(row.get(5)?),
+                in_sol: row.get(6)?,
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Fee payments in the stablecoin for the mint, all of them
+    /// if `mint` is `None`.
+    pub fn stablecoin_fee_payments(
+        &self,
+        mint: Option<&Pubkey>,
+    ) -> Result<Vec<StablecoinFeePaymentRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT signature, slot, block_time, payer, mint, stablecoin_mint, amount
+             FROM stablecoin_fee_payments WHERE (?1 IS NULL OR mint = ?1) ORDER BY slot, rowid",
+        )?;
+        let rows = stmt.query_map(params![mint.map(|mint| mint.to_string())], |row| {
+            Ok(StablecoinFeePaymentRow {
+                signature: row.get(0)?,
+                slot: row.get(1)?,
+                block_time: row.get(2)?,
+                payer: row.get(3)?,
+                mint: row.get(4)?,
+                stablecoin_mint: row.get(5)?,
+                amount: from_sql(row.get(6)?),
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+fn set_last_signature(conn: &Connection, program_id: &Pubkey, signature: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO sync_state (program_id, last_signature) VALUES (?1, ?2)",
+        params![program_id.to_string(), signature],
+    )?;
+    Ok(())
+}
+
+fn locker_row(row: &Row) -> rusqlite::Result<LockerRow> {
+    Ok(LockerRow {
+        address: row.get("address")?,
+        owner: row.get("owner")?,
+        mint: row.get("mint")?,
+        vault: row.get("vault")?,
+        unlock_date: row.get("unlock_date")?,
+        start_emission: row.get("start_emission")?,
+        deposited_amount: from_sql(row.get("deposited_amount")?),
+        withdrawn_amount: from_sql(row.get("withdrawn_amount")?),
+        closed: row.get("closed")?,
+    })
+}
+
+fn insert_deposit(
+    conn: &Connection,
+    tx: &DecodedTransaction,
+    locker: &Pubkey,
+    kind: &str,
+    amount: u64,
+    fee: u64,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO deposits (signature, slot, block_time, locker, kind, amount, fee)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            tx.signature,
+            tx.slot,
+            tx.block_time,
+            locker.to_string(),
+            kind,
+            to_sql(amount),
+            to_sql(fee),
+        ],
+    )?;
+    Ok(())
+}
+
+fn apply_event(conn: &Connection, tx: &DecodedTransaction, event: &LockerEvent) -> Result<()> {
+    match event {
+        LockerEvent::LockerCreated(event) => {
+            conn.execute(
+                "INSERT OR REPLACE INTO lockers (address, owner, mint, vault, unlock_date,
+                    start_emission, deposited_amount, withdrawn_amount, closed,
+                    created_slot, updated_slot)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, 0, ?8, ?8)",
+                params![
+                    event.locker.to_string(),
+                    event.owner.to_string(),
+                    event.mint.to_string(),
+                    event.vault.to_string(),
+                    event.unlock_date,
+                    event.start_emission,
+                    to_sql(event.amount),
+                    tx.slot,
+                ],
+            )?;
+            insert_deposit(
+                conn,
+                tx,
+                &event.locker,
+                DEPOSIT_CREATE,
+                event.amount,
+                event.fee,
+            )?;
+        }
+        LockerEvent::Relocked(event) => {
+            conn.execute(
+                "UPDATE lockers SET unlock_date = ?2, updated_slot = ?3 WHERE address = ?1",
+                params![event.locker.to_string(), event.new_unlock_date, tx.slot],
+            )?;
+        }
+        LockerEvent::LockIncremented(event) => {
+            conn.execute(
+                "UPDATE lockers SET deposited_amount = ?2, updated_slot = ?3 WHERE address = ?1",
+                params![
+                    event.locker.to_string(),
+                    to_sql(event.deposited_amount),
+                    tx.slot
+                ],
+            )?;
+            insert_deposit(
+                conn,
+                tx,
+                &event.locker,
+                DEPOSIT_INCREMENT,
+                event.amount,
+                event.fee,
+            )?;
+        }
+        LockerEvent::FundsWithdrawn(event) => {
+            conn.execute(
+                "UPDATE lockers SET withdrawn_amount = ?2, closed = ?3, updated_slot = ?4
+                 WHERE address = ?1",
+                params![
+                    event.locker.to_string(),
+                    to_sql(event.withdrawn_amount),
+                    event.closed,
+                    tx.slot
+                ],
+            )?;
+            conn.execute(
+                "INSERT INTO withdrawals (signature, slot, block_time, locker, owner,
+                    target_wallet, amount)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    tx.signature,
+                    tx.slot,
+                    tx.block_time,
+                    event.locker.to_string(),
+                    event.owner.to_string(),
+                    event.target_wallet.to_string(),
+                    to_sql(event.amount),
+                ],
+            )?;
+        }
+        LockerEvent::LockerRevoked(event) => {
+            // The program drops the schedule and unlocks the vested rest
+            // right before the revocation.
+            conn.execute(
+                "UPDATE lockers SET deposited_amount = ?2, start_emission = NULL,
+                    unlock_date = COALESCE(?3, unlock_date), closed = ?4, updated_slot = ?5
+                 WHERE address = ?1",
+                params![
+                    event.locker.to_string(),
+                    to_sql(event.deposited_amount),
+                    tx.block_time.map(|block_time| block_time - 1),
+                    event.closed,
+                    tx.slot
+                ],
+            )?;
+        }
+        LockerEvent::EmergencyUnlocked(event) => {
+            // Same as the revocation, unlocked right before the block time.
+            conn.execute(
+                "UPDATE lockers SET unlock_date = COALESCE(?2, unlock_date), updated_slot = ?3
+                 WHERE address = ?1",
+                params![
+                    event.locker.to_string(),
+                    tx.block_time.map(|block_time| block_time - 1),
+                    tx.slot
+                ],
+            )?;
+        }
+        LockerEvent::LockerSplit(event) => {
+            // Lockers created before the events were introduced aren't
+            // indexed, so neither are the lockers split out of them.
+            let old_locker = match lookup_locker(conn, &event.old_locker)? {
+                Some(old_locker) => old_locker,
+                None => return Ok(()),
+            };
+            let new_vault = tx
+                .split_vault(&event.new_locker)
+                .map(|vault| vault.to_string())
+                .unwrap_or_default();
+
+            let deposited_amount = old_locker
+                .deposited_amount
+                .saturating_sub(event.deposited_amount);
+            let withdrawn_amount = old_locker
+                .withdrawn_amount
+                .saturating_sub(event.withdrawn_amount);
+            // The program closes the old locker once its vault is empty.
+            let closed = deposited_amount <= withdrawn_amount;
+            conn.execute(
+                "UPDATE lockers SET deposited_amount = ?2, withdrawn_amount = ?3, closed = ?4,
+                    updated_slot = ?5
+                 WHERE address = ?1",
+                params![
+                    old_locker.address,
+                    to_sql(deposited_amount),
+                    to_sql(withdrawn_amount),
+                    closed,
+                    tx.slot
+                ],
+            )?;
+
+            conn.execute(
+                "INSERT OR REPLACE INTO lockers (address, owner, mint, vault, unlock_date,
+                    start_emission, deposited_amount, withdrawn_amount, closed,
+                    created_slot, updated_slot)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 0, ?9, ?9)",
+                params![
+                    event.new_locker.to_string(),
+                    event.new_owner.to_string(),
+                    old_locker.mint,
+                    new_vault,
+                    old_locker.unlock_date,
+                    old_locker.start_emission,
+                    to_sql(event.deposited_amount),
+                    to_sql(event.withdrawn_amount),
+                    tx.slot,
+                ],
+            )?;
+            insert_deposit(conn, tx, &event.new_locker, DEPOSIT_SPLIT, event.amount, 0)?;
+        }
+        LockerEvent::LockersMerged(event) => {
+            conn.execute(
+                "UPDATE lockers SET closed = 1, updated_slot = ?2 WHERE address = ?1",
+                params![event.source_locker.to_string(), tx.slot],
+            )?;
+            conn.execute(
+                "UPDATE lockers SET deposited_amount = ?2, withdrawn_amount = ?3,
+                    updated_slot = ?4
+                 WHERE address = ?1",
+                params![
+                    event.target_locker.to_string(),
+                    to_sql(event.deposited_amount),
+                    to_sql(event.withdrawn_amount),
+                    tx.slot
+                ],
+            )?;
+            insert_deposit(
+                conn,
+                tx,
+                &event.target_locker,
+                DEPOSIT_MERGE,
+                event.amount,
+                0,
+            )?;
+        }
+        LockerEvent::OwnershipTransferred(event) => {
+            conn.execute(
+                "UPDATE lockers SET owner = ?2, updated_slot = ?3 WHERE address = ?1",
+                params![
+                    event.locker.to_string(),
+                    event.new_owner.to_string(),
+                    tx.slot
+                ],
+            )?;
+        }
+        LockerEvent::FeePaid(event) => match event.currency {
+            FeeCurrency::Stablecoin {
+                mint: stablecoin_mint,
+            } => {
+                conn.execute(
+                    "INSERT INTO stablecoin_fee_payments
+                     (signature, slot, block_time, payer, mint, stablecoin_mint, amount)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        tx.signature,
+                        tx.slot,
+                        tx.block_time,
+                        event.payer.to_string(),
+                        event.mint.to_string(),
+                        stablecoin_mint.to_string(),
+                        to_sql(event.amount),
+                    ],
+                )?;
+            }
+            currency => {
+                conn.execute(
+                    "INSERT INTO fee_payments (signature, slot, block_time, payer, mint, amount, in_sol)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        tx.signature,
+                        tx.slot,
+                        tx.block_time,
+                        event.payer.to_string(),
+                        event.mint.to_string(),
+                        to_sql(event.amount),
+                        currency == FeeCurrency::Sol,
+                    ],
+                )?;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn lookup_locker(conn: &Connection, address: &Pubkey) -> Result<Option<LockerRow>> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM lockers WHERE address = ?1",
+            params![address.to_string()],
+            locker_row,
+        )
+        .optional()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use locker::{
+        EmergencyUnlocked, FeePaid, FundsWithdrawn, LockerCreated, LockerRevoked, LockerSplit,
+        LockersMerged, OwnershipTransferred,
+    };
+
+    struct Fixture {
+        db: Db,
+        program_id: Pubkey,
+        slot: u64,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Self {
+                db: Db::open_in_memory().unwrap(),
+                program_id: Pubkey::new_unique(),
+                slot: 0,
+            }
+        }
+
+        fn apply(&mut self, events: Vec<LockerEvent>) -> bool {
+            self.slot += 1;
+            let tx = DecodedTransaction {
+                signature: format!("signature-{}", self.slot),
+                slot: self.slot,
+                block_time: Some(self.slot as i64),
+                instructions: vec![],
+                events,
+            };
+            self.db.apply(&self.program_id, &tx).unwrap()
+        }
+
+        fn create_locker(&mut self, owner: Pubkey, mint: Pubkey, unlock_date: i64) -> Pubkey {
+            let locker = Pubkey::new_unique();
+            self.apply(vec![LockerEvent::LockerCreated(LockerCreated {
+                locker,
+                owner,
+                mint,
+                vault: Pubkey::new_unique(),
+                amount: 1000,
+                fee: 3,
+                unlock_date,
+                start_emission: None,
+            })]);
+            locker
+        }
+    }
+
+    #[test]
+    fn queries_lockers_by_owner_mint_and_unlock_date() {
+        let mut fixture = Fixture::new();
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let first = fixture.create_locker(alice, mint_a, 100);
+        let second = fixture.create_locker(alice, mint_b, 200);
+        let third = fixture.create_locker(bob, mint_a, 300);
+
+        let addresses = |filter: LockerFilter| {
+            fixture
+                .db
+                .lockers(&filter)
+                .unwrap()
+                .into_iter()
+                .map(|locker| locker.address)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            addresses(LockerFilter {
+                owner: Some(alice),
+                ..Default::default()
+            }),
+            vec![first.to_string(), second.to_string()]
+        );
+        assert_eq!(
+            addresses(LockerFilter {
+                mint: Some(mint_a),
+                ..Default::default()
+            }),
+            vec![first.to_string(), third.to_string()]
+        );
+        assert_eq!(
+            addresses(LockerFilter {
+                unlock_from: Some(150),
+                unlock_to: Some(300),
+                ..Default::default()
+            }),
+            vec![second.to_string(), third.to_string()]
+        );
+    }
+
+    #[test]
+    fn tracks_withdrawals_ownership_and_splits() {
+        let mut fixture = Fixture::new();
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mint = Pubkey::new_unique();
+        let locker = fixture.create_locker(alice, mint, 100);
+
+        let new_locker = Pubkey::new_unique();
+        fixture.apply(vec![LockerEvent::LockerSplit(LockerSplit {
+            old_locker: locker,
+            new_locker,
+            new_owner: bob,
+            amount: 400,
+            deposited_amount: 400,
+            withdrawn_amount: 0,
+        })]);
+        fixture.apply(vec![LockerEvent::OwnershipTransferred(
+            OwnershipTransferred {
+                locker,
+                old_owner: alice,
+                new_owner: bob,
+            },
+        )]);
+        fixture.apply(vec![LockerEvent::FundsWithdrawn(FundsWithdrawn {
+            locker,
+            owner: bob,
+            target_wallet: Pubkey::new_unique(),
+            amount: 600,
+            withdrawn_amount: 600,
+            closed: true,
+        })]);
+
+        let old = fixture.db.locker(&locker).unwrap().unwrap();
+        assert_eq!(old.owner, bob.to_string());
+        assert_eq!(old.deposited_amount, 600);
+        assert_eq!(old.withdrawn_amount, 600);
+        assert!(old.closed);
+
+        let new = fixture.db.locker(&new_locker).unwrap().unwrap();
+        assert_eq!(new.owner, bob.to_string());
+        assert_eq!(new.mint, mint.to_string());
+        assert_eq!(new.unlock_date, 100);
+        assert_eq!(new.deposited_amount, 400);
+        assert!(!new.closed);
+
+        let open = fixture
+            .db
+            .lockers(&LockerFilter {
+                owner: Some(bob),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(open, vec![new]);
+
+        assert_eq!(fixture.db.deposits(&new_locker).unwrap().len(), 1);
+        assert_eq!(fixture.db.withdrawals(&locker).unwrap()[0].amount, 600);
+    }
+
+    #[test]
+    fn tracks_merges() {
+        let mut fixture = Fixture::new();
+        let alice = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let source = fixture.create_locker(alice, mint, 100);
+        let target = fixture.create_locker(alice, mint, 100);
+
+        fixture.apply(vec![LockerEvent::LockersMerged(LockersMerged {
+            source_locker: source,
+            target_locker: target,
+            amount: 1000,
+            deposited_amount: 2000,
+            withdrawn_amount: 0,
+        })]);
+
+        assert!(fixture.db.locker(&source).unwrap().unwrap().closed);
+
+        let merged = fixture.db.locker(&target).unwrap().unwrap();
+        assert_eq!(merged.deposited_amount, 2000);
+        assert!(!merged.closed);
+
+        let deposits = fixture.db.deposits(&target).unwrap();
+        assert_eq!(deposits.len(), 2);
+        assert_eq!(deposits[1].kind, DEPOSIT_MERGE);
+        assert_eq!(deposits[1].amount, 1000);
+    }
+
+    #[test]
+    fn tracks_revocations() {
+        let mut fixture = Fixture::new();
+        let alice = Pubkey::new_unique();
+        let locker = fixture.create_locker(alice, Pubkey::new_unique(), 100);
+
+        fixture.apply(vec![LockerEvent::LockerRevoked(LockerRevoked {
+            locker,
+            revoke_authority: Pubkey::new_unique(),
+            treasury_wallet: Pubkey::new_unique(),
+            amount: 750,
+            deposited_amount: 250,
+            closed: false,
+        })]);
+
+        let revoked = fixture.db.locker(&locker).unwrap().unwrap();
+        assert_eq!(revoked.deposited_amount, 250);
+        assert_eq!(revoked.start_emission, None);
+        // Unlocked right before the revocation at the block time of 2.
+        assert_eq!(revoked.unlock_date, 1);
+        assert!(!revoked.closed);
+    }
+
+    #[test]
+    fn tracks_emergency_unlocks() {
+        let mut fixture = Fixture::new();
+        let alice = Pubkey::new_unique();
+        let locker = fixture.create_locker(alice, Pubkey::new_unique(), 100);
+
+        fixture.apply(vec![LockerEvent::EmergencyUnlocked(EmergencyUnlocked {
+            locker,
+            admin: Pubkey::new_unique(),
+            reason: "lost keys".to_string(),
+        })]);
+
+        let unlocked = fixture.db.locker(&locker).unwrap().unwrap();
+        assert_eq!(unlocked.deposited_amount, 1000);
+        // Unlocked right before the unlock at the block time of 2.
+        assert_eq!(unlocked.unlock_date, 1);
+        assert!(!unlocked.closed);
+    }
+
+    #[test]
+    fn tracks_fee_payments() {
+        let mut fixture = Fixture::new();
+        let (payer, mint, stablecoin_mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        fixture.apply(vec![LockerEvent::FeePaid(FeePaid {
+            payer,
+            mint,
+            amount: 1_000_000_000,
+            currency: FeeCurrency::Sol,
+        })]);
+        fixture.apply(vec![LockerEvent::FeePaid(FeePaid {
+            payer,
+            mint,
+            amount: 50_000_000,
+            currency: FeeCurrency::Stablecoin {
+                mint: stablecoin_mint,
+            },
+        })]);
+
+        fixture.apply(vec![LockerEvent::FeePaid(FeePaid {
+            payer,
+            mint,
+            amount: 3_500,
+            currency: FeeCurrency::Token,
+        })]);
+
+        let fees = fixture.db.fee_payments(Some(&mint)).unwrap();
+        assert_eq!(fees.len(), 2);
+        assert!(fees[0].in_sol);
+        assert!(!fees[1].in_sol);
+        assert_eq!(fees[1].amount, 3_500);
+
+        let stablecoin_fees = fixture.db.stablecoin_fee_payments(None).unwrap();
+        assert_eq!(
+            stablecoin_fees,
+            vec![StablecoinFeePaymentRow {
+                signature: "signature-2".to_string(),
+                slot: 2,
+                block_time: Some(2),
+                payer: payer.to_string(),
+                mint: mint.to_string(),
+                stablecoin_mint: stablecoin_mint.to_string(),
+                amount: 50_000_000,
+            }]
+        );
+        assert!(fixture
+            .db
+            .stablecoin_fee_payments(Some(&Pubkey::new_unique()))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn applies_transaction_once() {
+        let mut fixture = Fixture::new();
+        let tx = DecodedTransaction {
+            signature: "signature".to_string(),
+            slot: 1,
+            block_time: None,
+            instructions: vec![],
+            events: vec![],
+        };
+
+        assert!(fixture.db.apply(&fixture.program_id, &tx).unwrap());
+        assert!(!fixture.db.apply(&fixture.program_id, &tx).unwrap());
+        assert_eq!(
+            fixture.db.last_signature(&fixture.program_id).unwrap(),
+            Some("signature".to_string())
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! Decoding of locker instructions and events out of confirmed transactions.
+
+use anchor_client::{
+    anchor_lang::{AnchorDeserialize, Discriminator},
+    solana_sdk::{hash::hash, pubkey::Pubkey, transaction::Transaction},
+};
+
+use locker::{
+    EmergencyUnlocked, FeePaid, FundsWithdrawn, LockIncremented, LockerCreated, LockerRevoked,
+    LockerSplit, LockersMerged, OwnershipTransferred, Relocked,
+};
+
+/// Instructions of the locker program, the names are the ones used
+/// for the Anchor sighash.
+pub const INSTRUCTIONS: &[&str] = &[
+    "init_config",
+    "update_config",
+    "migrate_config",
+    "set_pause",
+    "propose_admin",
+    "accept_admin",
+    "init_mint_info",
+    "set_mint_fee",
+    "set_stablecoin_fee",
+    "set_fee_discounts",
+    "create_locker",
+    "init_creator_info",
+    "create_locker_with_pda",
+    "relock",
+    "transfer_ownership",
+    "propose_owner",
+    "accept_ownership",
+    "cancel_ownership_transfer",
+    "set_beneficiary",
+    "remove_beneficiary",
+    "increment_lock",
+    "withdraw_funds",
+    "release_vested",
+    "revoke_locker",
+    "split_locker",
+    "split_locker_batch",
+    "merge_lockers",
+    "migrate_locker",
+    "emergency_unlock",
+    "close_locker",
+];
+
+/// Position of `new_locker` in the `SplitLocker` accounts.
+const SPLIT_LOCKER_NEW_LOCKER: usize = 4;
+/// Position of `new_vault` in the `SplitLocker` accounts.
+const SPLIT_LOCKER_NEW_VAULT: usize = 7;
+/// Number of the `SplitLockerBatch` accounts, followed by the new locker,
+/// its vault authority, its vault and its beneficiary for every part.
+const SPLIT_LOCKER_BATCH_ACCOUNTS: usize = 10;
+/// Number of the `SplitLockerBatch` remaining accounts of every part.
+const SPLIT_LOCKER_BATCH_PART_ACCOUNTS: usize = 4;
+
+pub enum LockerEvent {
+    LockerCreated(LockerCreated),
+    Relocked(Relocked),
+    LockIncremented(LockIncremented),
+    FundsWithdrawn(FundsWithdrawn),
+    LockerRevoked(LockerRevoked),
+    EmergencyUnlocked(EmergencyUnlocked),
+    LockerSplit(LockerSplit),
+    LockersMerged(LockersMerged),
+    OwnershipTransferred(OwnershipTransferred),
+    FeePaid(FeePaid),
+}
+
+/// Top level instruction of the locker program.
+#[derive(Debug)]
+pub struct LockerInstruction {
+    pub name: &'static str,
+    pub accounts: Vec<Pubkey>,
+}
+
+/// Everything the indexer needs to know about a successful transaction.
+pub struct DecodedTransaction {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub instructions: Vec<LockerInstruction>,
+    pub events: Vec<LockerEvent>,
+}
+
+impl DecodedTransaction {
+    pub fn new(
+        program_id: &Pubkey,
+        signature: String,
+        slot: u64,
+        block_time: Option<i64>,
+        transaction: &Transaction,
+        logs: &[String],
+    ) -> Self {
+        Self {
+            signature,
+            slot,
+            block_time,
+            instructions: decode_instructions(program_id, transaction),
+            events: parse_events(program_id, logs),
+        }
+    }
+
+    /// Vault of the locker created by `split_locker` or `split_locker_batch`,
+    /// events don't carry it.
+    pub fn split_vault(&self, new_locker: &Pubkey) -> Option<Pubkey> {
+        self.instructions.iter().find_map(|ix| match ix.name {
+            "split_locker" if ix.accounts.get(SPLIT_LOCKER_NEW_LOCKER) == Some(new_locker) => {
+                ix.accounts.get(SPLIT_LOCKER_NEW_VAULT).copied()
+            }
+            "split_locker_batch" => ix
+                .accounts
+                .get(SPLIT_LOCKER_BATCH_ACCOUNTS..)?
+                .chunks_exact(SPLIT_LOCKER_BATCH_PART_ACCOUNTS)
+                .find(|part| &part[0] == new_locker)
+                .map(|part| part[2]),
+            _ => None,
+        })
+    }
+}
+
+fn sighash(name: &str) -> [u8; 8] {
+    let mut sighash = [0u8; 8];
+    sighash.copy_from_slice(&hash(format!("global:{}", name).as_bytes()).to_bytes()[..8]);
+    sighash
+}
+
+/// Returns the name of the instruction by its data.
+pub fn instruction_name(data: &[u8]) -> Option<&'static str> {
+    if data.len() < 8 {
+        return None;
+    }
+
+    INSTRUCTIONS
+        .iter()
+        .find(|name| sighash(name) == data[..8])
+        .copied()
+}
+
+/// Decodes top level instructions of the locker program. Instructions
+/// invoked via CPI aren't there, their effects are seen in the events.
+pub fn decode_instructions(
+    program_id: &Pubkey,
+    transaction: &Transaction,
+) -> Vec<LockerInstruction> {
+    let message = &transaction.message;
+
+    message
+        .instructions
+        .iter()
+        .filter(|ix| message.account_keys.get(ix.program_id_index as usize) == Some(program_id))
+        .filter_map(|ix| {
+            let name = instruction_name(&ix.data)?;
+            let accounts = ix
+                .accounts
+                .iter()
+                .filter_map(|index| message.account_keys.get(*index as usize).copied())
+                .collect();
+            Some(LockerInstruction { name, accounts })
+        })
+        .collect()
+}
+
+fn try_decode<T: AnchorDeserialize + Discriminator>(data: &[u8]) -> Option<T> {
+    if data.len() < 8 || data[..8] != T::discriminator() {
+        return None;
+    }
+
+    T::try_from_slice(&data[8..]).ok()
+}
+
+/// Decodes an event out of the `emit!` payload. `ConfigUpdated`, `AdminProposed`,
+/// `MintFeeSet`, `StablecoinFeeSet`, `FeeDiscountsSet` and `BeneficiaryChanged`
+/// aren't indexed, config, pending admin, mint and stablecoin fees, fee discounts
+/// and beneficiaries are cheaper to fetch directly.
+pub fn decode_event(data: &[u8]) -> Option<LockerEvent> {
+    None.or_else(|| try_decode(data).map(LockerEvent::LockerCreated))
+        .or_else(|| try_decode(data).map(LockerEvent::Relocked))
+        .or_else(|| try_decode(data).map(LockerEvent::LockIncremented))
+        .or_else(|| try_decode(data).map(LockerEvent::FundsWithdrawn))
+        .or_else(|| try_decode(data).map(LockerEvent::LockerRevoked))
+        .or_else(|| try_decode(data).map(LockerEvent::EmergencyUnlocked))
+        .or_else(|| try_decode(data).map(LockerEvent::LockerSplit))
+        .or_else(|| try_decode(data).map(LockerEvent::LockersMerged))
+        .or_else(|| try_decode(data).map(LockerEvent::OwnershipTransferred))
+        .or_else(|| try_decode(data).map(LockerEvent::FeePaid))
+}
+
+/// Extracts the events from the transaction logs. Only the logs written
+/// while the locker program is on top of the invocation stack are taken,
+/// so other programs can't forge the events.
+pub fn parse_events(program_id: &Pubkey, logs: &[String]) -> Vec<LockerEvent> {
+    let program_id = program_id.to_string();
+    let mut stack: Vec<&str> = vec![];
+    let mut events = vec![];
+
+    for log in logs {
+        if let Some(data) = log.strip_prefix("Program log: ") {
+            if stack.last() != Some(&program_id.as_str()) {
+                continue;
+            }
+            if let Some(event) = base64::decode(data)
+                .ok()
+                .and_then(|data| decode_event(&data))
+            {
+                events.push(event);
+            }
+            continue;
+        }
+
+        let mut words = log.split_whitespace();
+        if let (Some("Program"), Some(id), Some(status)) =
+            (words.next(), words.next(), words.next())
+        {
+            match status {
+                "invoke" => stack.push(id),
+                "success" | "failed:" => {
+                    stack.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anchor_client::anchor_lang::Event;
+    use locker::{ConfigUpdated, FeeCurrency};
+
+    fn event_log(event: &impl Event) -> String {
+        format!("Program log: {}", base64::encode(event.data()))
+    }
+
+    fn relocked() -> Relocked {
+        Relocked {
+            locker: Pubkey::new_unique(),
+            old_unlock_date: 10,
+            new_unlock_date: 20,
+        }
+    }
+
+    #[test]
+    fn instruction_names_match_anchor_sighash() {
+        let data =
+            anchor_client::anchor_lang::InstructionData::data(&locker::instruction::Relock {
+                unlock_date: 10,
+            });
+        assert_eq!(instruction_name(&data), Some("relock"));
+        assert_eq!(instruction_name(&[0; 4]), None);
+    }
+
+    #[test]
+    fn finds_vaults_of_batch_splits() {
+        let accounts: Vec<Pubkey> = (0..SPLIT_LOCKER_BATCH_ACCOUNTS
+            + 2 * SPLIT_LOCKER_BATCH_PART_ACCOUNTS)
+            .map(|_| Pubkey::new_unique())
+            .collect();
+        let tx = DecodedTransaction {
+            signature: "signature".to_string(),
+            slot: 1,
+            block_time: None,
+            instructions: vec![LockerInstruction {
+                name: "split_locker_batch",
+                accounts: accounts.clone(),
+            }],
+            events: vec![],
+        };
+
+        let second = SPLIT_LOCKER_BATCH_ACCOUNTS + SPLIT_LOCKER_BATCH_PART_ACCOUNTS;
+        assert_eq!(
+            tx.split_vault(&accounts[second]),
+            Some(accounts[second + 2])
+        );
+        assert_eq!(tx.split_vault(&accounts[second + 1]), None);
+        assert_eq!(tx.split_vault(&Pubkey::new_unique()), None);
+    }
+
+    #[test]
+    fn decodes_emitted_events() {
+        let program_id = Pubkey::new_unique();
+        let (locker, owner, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let stablecoin_mint = Pubkey::new_unique();
+        let config_updated = ConfigUpdated {
+            admin: owner,
+            fee_in_sol: 1_000_000_000,
+            fee_in_token_numerator: 35,
+            fee_in_token_denominator: 10000,
+            mint_info_permissioned: false,
+            has_linear_emission: true,
+            fee_wallet: Pubkey::new_unique(),
+            country_list: Pubkey::new_unique(),
+            deposits_paused: false,
+            withdrawals_paused: true,
+        };
+
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            event_log(&LockerCreated {
+                locker,
+                owner,
+                mint,
+                vault: Pubkey::new_unique(),
+                amount: 997,
+                fee: 3,
+                unlock_date: 20,
+                start_emission: Some(10),
+            }),
+            event_log(&relocked()),
+            event_log(&LockIncremented {
+                locker,
+                amount: 499,
+                fee: 1,
+                deposited_amount: 1496,
+            }),
+            event_log(&FundsWithdrawn {
+                locker,
+                owner,
+                target_wallet: Pubkey::new_unique(),
+                amount: 100,
+                withdrawn_amount: 100,
+                closed: false,
+            }),
+            event_log(&LockerSplit {
+                old_locker: locker,
+                new_locker: Pubkey::new_unique(),
+                new_owner: owner,
+                amount: 300,
+                deposited_amount: 400,
+                withdrawn_amount: 100,
+            }),
+            event_log(&OwnershipTransferred {
+                locker,
+                old_owner: owner,
+                new_owner: Pubkey::new_unique(),
+            }),
+            event_log(&FeePaid {
+                payer: owner,
+                mint,
+                amount: 50_000_000,
+                currency: FeeCurrency::Stablecoin {
+                    mint: stablecoin_mint,
+                },
+            }),
+            event_log(&config_updated),
+            format!("Program {} success", program_id),
+        ];
+
+        let events = parse_events(&program_id, &logs);
+        // `ConfigUpdated` isn't indexed.
+        assert_eq!(events.len(), 7);
+        match &events[0] {
+            LockerEvent::LockerCreated(event) => {
+                assert_eq!(
+                    (event.locker, event.owner, event.mint),
+                    (locker, owner, mint)
+                );
+                assert_eq!((event.amount, event.fee), (997, 3));
+                assert_eq!(event.start_emission, Some(10));
+            }
+            _ => panic!("expected LockerCreated"),
+        }
+        match &events[1] {
+            LockerEvent::Relocked(event) => assert_eq!(event.new_unlock_date, 20),
+            _ => panic!("expected Relocked"),
+        }
+        match &events[2] {
+            LockerEvent::LockIncremented(event) => {
+                assert_eq!(event.locker, locker);
+                assert_eq!((event.amount, event.fee), (499, 1));
+                assert_eq!(event.deposited_amount, 1496);
+            }
+            _ => panic!("expected LockIncremented"),
+        }
+        match &events[3] {
+            LockerEvent::FundsWithdrawn(event) => {
+                assert_eq!((event.locker, event.owner), (locker, owner));
+                assert_eq!((event.amount, event.withdrawn_amount), (100, 100));
+                assert!(!event.closed);
+            }
+            _ => panic!("expected FundsWithdrawn"),
+        }
+        match &events[4] {
+            LockerEvent::LockerSplit(event) => {
+                assert_eq!((event.old_locker, event.new_owner), (locker, owner));
+                assert_eq!(event.amount, 300);
+                assert_eq!((event.deposited_amount, event.withdrawn_amount), (400, 100));
+            }
+            _ => panic!("expected LockerSplit"),
+        }
+        match &events[5] {
+            LockerEvent::OwnershipTransferred(event) => {
+                assert_eq!((event.locker, event.old_owner), (locker, owner));
+            }
+            _ => panic!("expected OwnershipTransferred"),
+        }
+        match &events[6] {
+            LockerEvent::FeePaid(event) => {
+                assert_eq!((event.payer, event.mint), (owner, mint));
+                assert_eq!(event.amount, 50_000_000);
+                assert_eq!(
+                    event.currency,
+                    FeeCurrency::Stablecoin {
+                        mint: stablecoin_mint
+                    }
+                );
+            }
+            _ => panic!("expected FeePaid"),
+        }
+
+        let decoded: ConfigUpdated = try_decode(&config_updated.data()).unwrap();
+        assert_eq!(decoded.fee_in_sol, config_updated.fee_in_sol);
+        assert_eq!(decoded.fee_wallet, config_updated.fee_wallet);
+        assert!(decoded.withdrawals_paused);
+    }
+
+    #[test]
+    fn parses_events_of_the_program_only() {
+        let program_id = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+        let event = relocked();
+
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            "Program log: Relock".to_string(),
+            event_log(&event),
+            format!("Program {} invoke [2]", other_program),
+            event_log(&relocked()),
+            format!("Program {} success", other_program),
+            format!(
+                "Program {} consumed 1000 of 200000 compute units",
+                program_id
+            ),
+            format!("Program {} success", program_id),
+            event_log(&relocked()),
+        ];
+
+        let events = parse_events(&program_id, &logs);
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            LockerEvent::Relocked(relocked) => {
+                assert_eq!(relocked.locker, event.locker);
+                assert_eq!(relocked.new_unlock_date, 20);
+            }
+            _ => panic!("expected Relocked"),
+        }
+    }
+}
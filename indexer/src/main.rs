@@ -0,0 +1,107 @@
+//! Off-chain indexer of the locker program.
+//!
+//! Replays the program transactions into SQLite so lockers can be queried
+//! by owner, mint and unlock date without scanning the program accounts.
+
+mod db;
+mod decode;
+mod server;
+mod sync;
+
+use std::{thread, time::Duration};
+
+use anchor_client::{
+    solana_client::rpc_client::RpcClient,
+    solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey},
+};
+use anyhow::Result;
+use structopt::StructOpt;
+
+use db::Db;
+
+#[derive(Debug, StructOpt)]
+struct Opts {
+    #[structopt(long)]
+    program_id: Pubkey,
+    /// Path to the SQLite database, created if missing.
+    #[structopt(long, default_value = "locker-index.sqlite")]
+    db: String,
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Index the transactions since the last sync.
+    Sync {
+        /// `localnet` for the local test validator.
+        #[structopt(long)]
+        cluster: anchor_client::Cluster,
+        /// Keep polling for the new transactions every given number of seconds.
+        #[structopt(long)]
+        watch: Option<u64>,
+    },
+    /// Print the indexed lockers as JSON.
+    Lockers {
+        #[structopt(long)]
+        owner: Option<Pubkey>,
+        #[structopt(long)]
+        mint: Option<Pubkey>,
+        /// Unix timestamp, inclusive.
+        #[structopt(long)]
+        unlock_from: Option<i64>,
+        /// Unix timestamp, inclusive.
+        #[structopt(long)]
+        unlock_to: Option<i64>,
+        #[structopt(long)]
+        include_closed: bool,
+    },
+    /// Serve the index over HTTP.
+    Serve {
+        #[structopt(long, default_value = "127.0.0.1:8080")]
+        address: String,
+    },
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::from_args();
+    let mut db = Db::open(&opts.db)?;
+
+    match opts.cmd {
+        Command::Sync { cluster, watch } => {
+            let rpc = RpcClient::new_with_commitment(
+                cluster.url().to_string(),
+                CommitmentConfig::confirmed(),
+            );
+
+            loop {
+                let applied = sync::sync(&rpc, &opts.program_id, &mut db)?;
+                println!("Indexed {} transactions", applied);
+
+                match watch {
+                    Some(seconds) => thread::sleep(Duration::from_secs(seconds)),
+                    None => break,
+                }
+            }
+        }
+        Command::Lockers {
+            owner,
+            mint,
+            unlock_from,
+            unlock_to,
+            include_closed,
+        } => {
+            let lockers = db.lockers(&db::LockerFilter {
+                owner,
+                mint,
+                unlock_from,
+                unlock_to,
+                include_closed,
+            })?;
+            println!("{}", serde_json::to_string_pretty(&lockers)?);
+        }
+        Command::Serve { address } => server::serve(&db, &address)?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! Read-only HTTP API over the index.
+//!
+//! * `GET /lockers?owner=&mint=&unlock_from=&unlock_to=&include_closed=`
+//! * `GET /lockers/<address>`
+//! * `GET /lockers/<address>/deposits`
+//! * `GET /lockers/<address>/withdrawals`
+//! * `GET /fees?mint=`
+//! * `GET /fees/stablecoin?mint=`
+
+use std::str::FromStr;
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::db::{Db, LockerFilter};
+
+enum Reply {
+    Ok(String),
+    BadRequest(String),
+    NotFound,
+}
+
+fn json<T: Serialize>(value: &T) -> Result<Reply> {
+    Ok(Reply::Ok(serde_json::to_string(value)?))
+}
+
+fn parse_query(query: &str) -> Vec<(&str, &str)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            (
+                parts.next().unwrap_or_default(),
+                parts.next().unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+fn parse_param<T>(name: &str, value: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|err| anyhow!("invalid {}: {}", name, err))
+}
+
+fn locker_filter(query: &str) -> Result<LockerFilter> {
+    let mut filter = LockerFilter::default();
+
+    for (name, value) in parse_query(query) {
+        match name {
+            "owner" => filter.owner = Some(parse_param(name, value)?),
+            "mint" => filter.mint = Some(parse_param(name, value)?),
+            "unlock_from" => filter.unlock_from = Some(parse_param(name, value)?),
+            "unlock_to" => filter.unlock_to = Some(parse_param(name, value)?),
+            "include_closed" => filter.include_closed = parse_param(name, value)?,
+            _ => return Err(anyhow!("unknown parameter {}", name)),
+        }
+    }
+
+    Ok(filter)
+}
+
+fn route(db: &Db, url: &str) -> Result<Reply> {
+    let (path, query) = match url.find('?') {
+        Some(index) => (&url[..index], &url[index + 1..]),
+        None => (url, ""),
+    };
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    match segments.as_slice() {
+        ["lockers"] => match locker_filter(query) {
+            Ok(filter) => json(&db.lockers(&filter)?),
+            Err(err) => Ok(Reply::BadRequest(err.to_string())),
+        },
+        ["lockers", address, rest @ ..] => {
+            let address = match Pubkey::from_str(address) {
+                Ok(address) => address,
+                Err(_) => return Ok(Reply::BadRequest("invalid locker address".to_string())),
+            };
+            match rest {
+                [] => match db.locker(&address)? {
+                    Some(locker) => json(&locker),
+                    None => Ok(Reply::NotFound),
+                },
+                ["deposits"] => json(&db.deposits(&address)?),
+                ["withdrawals"] => json(&db.withdrawals(&address)?),
+                _ => Ok(Reply::NotFound),
+            }
+        }
+        ["fees", rest @ ..] => {
+            let mut mint = None;
+            for (name, value) in parse_query(query) {
+                match (name, parse_param::<Pubkey>(name, value)) {
+                    ("mint", Ok(value)) => mint = Some(value),
+                    ("mint", Err(err)) => return Ok(Reply::BadRequest(err.to_string())),
+                    _ => return Ok(Reply::BadRequest(format!("unknown parameter {}", name))),
+                }
+            }
+            match rest {
+                [] => json(&db.fee_payments(mint.as_ref())?),
+                ["stablecoin"] => json(&db.stablecoin_fee_payments(mint.as_ref())?),
+                _ => Ok(Reply::NotFound),
+            }
+        }
+        _ => Ok(Reply::NotFound),
+    }
+}
+
+fn respond(db: &Db, request: Request) -> Result<()> {
+    let reply = if *request.method() == Method::Get {
+        route(db, request.url())?
+    } else {
+        Reply::NotFound
+    };
+
+    let (status, body) = match reply {
+        Reply::Ok(body) => (200, body),
+        Reply::BadRequest(message) => (400, serde_json::json!({ "error": message }).to_string()),
+        Reply::NotFound => (404, serde_json::json!({ "error": "not found" }).to_string()),
+    };
+    let content_type = Header::from_bytes("Content-Type", "application/json")
+        .map_err(|_| anyhow!("invalid header"))?;
+
+    request.respond(
+        Response::from_string(body)
+            .with_status_code(status)
+            .with_header(content_type),
+    )?;
+    Ok(())
+}
+
+/// Serves the requests one by one, SQLite reads are fast enough for that.
+pub fn serve(db: &Db, address: &str) -> Result<()> {
+    let server =
+        Server::http(address).map_err(|err| anyhow!("failed to bind {}: {}", address, err))?;
+    println!("Listening on {}", address);
+
+    for request in server.incoming_requests() {
+        if let Err(err) = respond(db, request) {
+            eprintln!("Failed to handle request: {}", err);
+        }
+    }
+
+    Ok(())
+}
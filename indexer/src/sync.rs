@@ -0,0 +1,95 @@
+//! Replays the program transactions from RPC into the index.
+
+use std::str::FromStr;
+
+use anchor_client::{
+    solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+};
+use anyhow::{anyhow, Result};
+use solana_transaction_status::UiTransactionEncoding;
+
+use crate::{db::Db, decode::DecodedTransaction};
+
+/// Max page size of `getSignaturesForAddress`.
+const PAGE_SIZE: usize = 1000;
+
+/// Fetches the signatures of the program newer than the last indexed one,
+/// oldest first.
+fn fetch_new_signatures(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    until: Option<Signature>,
+) -> Result<Vec<(Signature, bool)>> {
+    let mut signatures = vec![];
+    let mut before = None;
+
+    loop {
+        let page = rpc.get_signatures_for_address_with_config(
+            program_id,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                until,
+                limit: Some(PAGE_SIZE),
+                commitment: Some(rpc.commitment()),
+            },
+        )?;
+        let is_last_page = page.len() < PAGE_SIZE;
+
+        for status in page {
+            let signature = Signature::from_str(&status.signature)?;
+            signatures.push((signature, status.err.is_none()));
+        }
+
+        before = signatures.last().map(|(signature, _)| *signature);
+        if is_last_page {
+            break;
+        }
+    }
+
+    signatures.reverse();
+    Ok(signatures)
+}
+
+/// Indexes the transactions since the last sync. Returns the number of
+/// applied transactions.
+pub fn sync(rpc: &RpcClient, program_id: &Pubkey, db: &mut Db) -> Result<usize> {
+    let until = db
+        .last_signature(program_id)?
+        .map(|signature| Signature::from_str(&signature))
+        .transpose()?;
+
+    let mut applied = 0;
+    for (signature, succeeded) in fetch_new_signatures(rpc, program_id, until)? {
+        if !succeeded {
+            db.skip(program_id, &signature.to_string())?;
+            continue;
+        }
+
+        let confirmed = rpc.get_transaction(&signature, UiTransactionEncoding::Base64)?;
+        let transaction = confirmed
+            .transaction
+            .transaction
+            .decode()
+            .ok_or_else(|| anyhow!("failed to decode transaction {}", signature))?;
+        let logs = confirmed
+            .transaction
+            .meta
+            .and_then(|meta| meta.log_messages)
+            .unwrap_or_default();
+
+        let decoded = DecodedTransaction::new(
+            program_id,
+            signature.to_string(),
+            confirmed.slot,
+            confirmed.block_time,
+            &transaction,
+            &logs,
+        );
+        if db.apply(program_id, &decoded)? {
+            applied += 1;
+        }
+    }
+
+    Ok(applied)
+}
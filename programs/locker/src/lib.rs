@@ -5,12 +5,13 @@ use anchor_lang::{
     solana_program::{
         self,
         log::{sol_log, sol_log_64},
+        system_instruction, sysvar,
     },
     AccountsClose,
 };
 use anchor_spl::{
     associated_token::get_associated_token_address,
-    token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer},
+    token::{self, CloseAccount, InitializeAccount, Mint, Token, TokenAccount, Transfer},
 };
 
 use az::CheckedAs;
@@ -34,6 +35,50 @@ pub enum ErrorCode {
     InvalidCountry,
     InitMintInfoNotAuthorized,
     LinearEmissionDisabled,
+    InvalidBasisPoints,
+    InvalidSchedule,
+    #[msg("The program is paused by admin")]
+    ProgramPaused,
+    #[msg("The instruction is available in test builds only")]
+    TestOnlyInstruction,
+    #[msg("The reason is too long")]
+    ReasonTooLong,
+    #[msg("The vault doesn't belong to the locker")]
+    InvalidVault,
+    #[msg("The token account is frozen")]
+    TokenAccountFrozen,
+    #[msg("The token account has a delegate")]
+    TokenAccountHasDelegate,
+    #[msg("The token account has a close authority")]
+    TokenAccountHasCloseAuthority,
+    #[msg("The lockers have different terms and can't be merged")]
+    IncompatibleLockers,
+    #[msg("Wrong number of the split parts or accounts for them")]
+    InvalidSplitParts,
+    #[msg("The target wallet doesn't belong to the locker beneficiary")]
+    InvalidBeneficiary,
+    #[msg("The target wallet is not associated with the locker beneficiary")]
+    InvalidReleaseWallet,
+    #[msg("The locker can't be revoked by the given authority")]
+    InvalidRevokeAuthority,
+    #[msg("The fee in tokens should be a fraction not greater than one")]
+    InvalidFee,
+    #[msg("The fee in the stablecoin isn't accepted")]
+    StablecoinFeeDisabled,
+    #[msg("The fee can be paid either in SOL or in the stablecoin")]
+    InvalidFeeMode,
+    #[msg("Wrong number of the stablecoin fee accounts")]
+    InvalidStablecoinFeeAccounts,
+    #[msg("Discount tiers should go by the min balance, at most 8 of them")]
+    InvalidDiscountTiers,
+    #[msg("Wrong number of the fee discount accounts")]
+    InvalidDiscountAccounts,
+    #[msg("The wallet doesn't hold the discount token of the funding wallet authority")]
+    InvalidDiscountWallet,
+    #[msg("The new owner should accept the ownership, see propose_owner")]
+    AcceptanceRequired,
+    #[msg("Lockers with tranches or a cliff can't be relocked")]
+    CannotRelockSchedule,
 }
 
 #[program]
@@ -43,6 +88,12 @@ pub mod locker {
     pub fn init_config(ctx: Context<InitConfig>, args: CreateConfigArgs) -> Result<()> {
         sol_log("Init config");
 
+        TokenFee {
+            numerator: args.fee_in_token_numerator,
+            denominator: args.fee_in_token_denominator,
+        }
+        .validate()?;
+
         let config = ctx.accounts.config.deref_mut();
 
         *config = Config {
@@ -55,8 +106,13 @@ pub mod locker {
             fee_wallet: ctx.accounts.fee_wallet.key(),
             country_list: ctx.accounts.country_list.key(),
             bump: args.bump,
+            deposits_paused: false,
+            withdrawals_paused: false,
+            fee_in_lamports: true,
         };
 
+        emit!(config.updated_event());
+
         Ok(())
     }
 
@@ -64,6 +120,9 @@ pub mod locker {
         sol_log("Update config");
 
         let config = &mut ctx.accounts.config;
+        // The new fee in SOL is in lamports.
+        config.migrate()?;
+
         let UpdateConfigArgs {
             fee_in_sol,
             fee_in_token_numerator,
@@ -81,9 +140,79 @@ pub mod locker {
             mint_info_permissioned.unwrap_or(config.mint_info_permissioned);
         config.has_linear_emission = has_linear_emission.unwrap_or(config.has_linear_emission);
 
+        TokenFee {
+            numerator: config.fee_in_token_numerator,
+            denominator: config.fee_in_token_denominator,
+        }
+        .validate()?;
+
         config.fee_wallet = ctx.accounts.fee_wallet.key();
         config.country_list = ctx.accounts.country_list.key();
 
+        emit!(config.updated_event());
+
+        Ok(())
+    }
+
+    /// Moves the config created before the fee in lamports to it. Anyone can
+    /// call it since the result is fully determined by the config state.
+    pub fn migrate_config(ctx: Context<MigrateConfig>) -> Result<()> {
+        sol_log("Migrate config");
+
+        let config = &mut ctx.accounts.config;
+        config.migrate()?;
+
+        emit!(config.updated_event());
+
+        Ok(())
+    }
+
+    /// Allows admin to stop the program in case of emergency.
+    /// `None` keeps the current value of the flag.
+    pub fn set_pause(ctx: Context<SetPause>, args: SetPauseArgs) -> Result<()> {
+        sol_log("Set pause");
+
+        let config = &mut ctx.accounts.config;
+
+        config.deposits_paused = args.deposits.unwrap_or(config.deposits_paused);
+        config.withdrawals_paused = args.withdrawals.unwrap_or(config.withdrawals_paused);
+
+        emit!(config.updated_event());
+
+        Ok(())
+    }
+
+    /// First step of the admin handover, the new admin should accept it.
+    /// Proposing again replaces the pending admin.
+    pub fn propose_admin(ctx: Context<ProposeAdmin>) -> Result<()> {
+        sol_log("Propose admin");
+
+        let (_, bump) = Pubkey::find_program_address(&["pending_admin".as_ref()], ctx.program_id);
+        let pending_admin = ctx.accounts.pending_admin.deref_mut();
+
+        *pending_admin = PendingAdmin {
+            bump,
+            admin: ctx.accounts.new_admin.key(),
+        };
+
+        emit!(AdminProposed {
+            admin: ctx.accounts.admin.key(),
+            pending_admin: pending_admin.admin,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the rent of the pending admin to the old admin.
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        sol_log("Accept admin");
+
+        let config = &mut ctx.accounts.config;
+
+        config.admin = ctx.accounts.new_admin.key();
+
+        emit!(config.updated_event());
+
         Ok(())
     }
 
@@ -110,168 +239,345 @@ pub mod locker {
         Ok(())
     }
 
-    pub fn create_locker<'info>(
-        ctx: Context<'_, '_, '_, 'info, CreateLocker<'info>>,
-        args: CreateLockerArgs,
+    /// Overrides the config fees for the mint, e.g. for partner projects.
+    /// The default `MintFee` restores the config fees.
+    pub fn set_mint_fee(ctx: Context<SetMintFee>, fee: MintFee) -> Result<()> {
+        sol_log("Set mint fee");
+
+        if let Some(fee_in_token) = &fee.fee_in_token {
+            fee_in_token.validate()?;
+        }
+
+        let mint = ctx.accounts.mint.key();
+        let (_, bump) =
+            Pubkey::find_program_address(&["mint_fee".as_ref(), mint.as_ref()], ctx.program_id);
+        let mint_fee = ctx.accounts.mint_fee.deref_mut();
+        *mint_fee = MintFeeOverride { bump, fee };
+
+        emit!(MintFeeSet { mint, fee });
+
+        Ok(())
+    }
+
+    /// Accepts the flat fee in the stablecoin instead of SOL, `None` stops
+    /// accepting it.
+    pub fn set_stablecoin_fee(
+        ctx: Context<SetStablecoinFee>,
+        fee: Option<StablecoinFee>,
     ) -> Result<()> {
-        sol_log("Create locker: start");
+        sol_log("Set stablecoin fee");
 
-        let now = ctx.accounts.clock.unix_timestamp;
-        require!(args.unlock_date > now, UnlockInThePast);
-        // Prevents errors when timestamp entered as milliseconds.
-        require!(args.unlock_date < 10000000000, InvalidTimestamp);
+        let (_, bump) = Pubkey::find_program_address(&["stablecoin_fee".as_ref()], ctx.program_id);
+        let stablecoin_fee = ctx.accounts.stablecoin_fee.deref_mut();
+        *stablecoin_fee = StablecoinFeeConfig { bump, fee };
 
-        let config = &ctx.accounts.config;
+        emit!(StablecoinFeeSet { fee });
 
-        // Checking here that args has no linear emission if it's disabled
-        // for the given locker type.
-        if !config.has_linear_emission {
-            require!(args.start_emission.is_none(), LinearEmissionDisabled);
-        }
+        Ok(())
+    }
 
-        if let Some(start_emission) = args.start_emission {
-            //  now     start_emission     unlock_date
-            // |--------------------------------------> time, seconds
-            require!(args.unlock_date > start_emission, InvalidPeriod);
-            require!(start_emission >= now, InvalidPeriod);
-        }
+    /// Replaces the discount tiers for the holders of `mint`,
+    /// no tiers stop the discounts.
+    pub fn set_fee_discounts(
+        ctx: Context<SetFeeDiscounts>,
+        mint: Pubkey,
+        tiers: Vec<DiscountTier>,
+    ) -> Result<()> {
+        sol_log("Set fee discounts");
 
-        // Checking here that country is not banned in country list
-        // we've chosen in locker type config.
-        require!(
-            ctx.accounts
-                .country_banlist
-                .is_country_valid(&args.country_code),
-            InvalidCountry
-        );
+        FeeDiscounts::validate_tiers(&tiers)?;
 
-        sol_log("Create locker: checks passed");
+        let (_, bump) = Pubkey::find_program_address(&["fee_discounts".as_ref()], ctx.program_id);
+        let fee_discounts = ctx.accounts.fee_discounts.deref_mut();
+        *fee_discounts = FeeDiscounts { bump, mint, tiers };
 
-        let mint_info = &mut ctx.accounts.mint_info;
+        emit!(FeeDiscountsSet {
+            mint,
+            tiers: fee_discounts.tiers.clone(),
+        });
 
-        // Check if we should charge the fee in SOL.
-        if should_pay_in_sol(config, mint_info, args.fee_in_sol) {
-            FeeInSol {
-                fee_wallet: &ctx.accounts.fee_wallet,
-                payer: &ctx.accounts.owner,
-                config,
-                mint_info,
-                system_program: &ctx.accounts.system_program,
-            }
-            .pay()?;
+        Ok(())
+    }
+
+    /// Takes the stablecoin fee accounts from the remaining accounts,
+    /// see `FeeInStablecoin`, followed by the fee discount accounts,
+    /// see `holder_discount`.
+    pub fn create_locker<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateLocker<'info>>,
+        args: CreateLockerArgs,
+    ) -> Result<()> {
+        sol_log("Create locker: start");
+
+        let accounts = ctx.accounts;
+        NewLocker {
+            locker: &mut accounts.locker,
+            owner: &accounts.owner,
+            funding_wallet_authority: &accounts.funding_wallet_authority,
+            funding_wallet: &mut accounts.funding_wallet,
+            vault: &accounts.vault,
+            fee_wallet: &accounts.fee_wallet,
+            fee_token_wallet: &accounts.fee_token_wallet,
+            mint_info: &mut accounts.mint_info,
+            mint_fee: MintFeeOverride::load(&accounts.mint_fee)?,
+            stablecoin_fee: StablecoinFeeConfig::load(&accounts.stablecoin_fee)?,
+            config: &accounts.config,
+            country_banlist: &accounts.country_banlist,
+            clock: &accounts.clock,
+            system_program: &accounts.system_program,
+            token_program: &accounts.token_program,
+            remaining_accounts: ctx.remaining_accounts,
         }
+        .create(args)?;
 
-        sol_log("Create locker: after sol fee");
+        sol_log("Create locker: finish");
 
-        // Check if we should charge the fee in locked tokens.
-        let lock_fee = if should_pay_in_tokens(config, mint_info, args.fee_in_sol) {
-            FeeInTokens {
-                config,
-                funding_wallet: &mut ctx.accounts.funding_wallet,
-                funding_wallet_authority: &ctx.accounts.funding_wallet_authority,
-                fee_wallet: &ctx.accounts.fee_token_wallet,
-                amount: args.amount,
-                token_program: &ctx.accounts.token_program,
-            }
-            .pay()?
-        } else {
-            0
+        Ok(())
+    }
+
+    /// Creates the creator info, the counter of the lockers created
+    /// with `create_locker_with_pda`.
+    pub fn init_creator_info(ctx: Context<InitCreatorInfo>, bump: u8) -> Result<()> {
+        sol_log("Init creator info");
+
+        let creator_info = ctx.accounts.creator_info.deref_mut();
+
+        *creator_info = CreatorInfo {
+            bump,
+            lockers_created: 0,
         };
 
-        sol_log("Create locker: after token fee");
+        Ok(())
+    }
 
-        let amount_to_lock = args
-            .amount
-            .checked_sub(lock_fee)
-            .ok_or(ErrorCode::IntegerOverflow)?;
-        require!(amount_to_lock > 0, NothingToLock);
+    /// Same as `create_locker`, but the locker is a PDA of the creator,
+    /// the mint and the number of lockers created by the creator so far,
+    /// so it can be found without scanning all the program accounts.
+    pub fn create_locker_with_pda<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreatePdaLocker<'info>>,
+        args: CreateLockerArgs,
+    ) -> Result<()> {
+        sol_log("Create PDA locker: start");
 
-        let locker = ctx.accounts.locker.deref_mut();
+        let accounts = ctx.accounts;
 
-        *locker = Locker {
-            owner: ctx.accounts.owner.key(),
-            country_code: country_list::string_to_byte_array(&args.country_code),
-            current_unlock_date: args.unlock_date,
-            start_emission: args.start_emission,
-            last_withdraw: None,
-            deposited_amount: amount_to_lock,
-            vault: ctx.accounts.vault.key(),
-            vault_bump: args.vault_bump,
-        };
+        let creator_info = &mut accounts.creator_info;
+        creator_info.lockers_created = creator_info
+            .lockers_created
+            .checked_add(1)
+            .ok_or(ErrorCode::IntegerOverflow)?;
 
-        TokenTransfer {
-            amount: amount_to_lock,
-            from: &mut ctx.accounts.funding_wallet,
-            to: &ctx.accounts.vault,
-            authority: &ctx.accounts.funding_wallet_authority,
-            token_program: &ctx.accounts.token_program,
-            signers: None,
+        NewLocker {
+            locker: &mut accounts.locker,
+            owner: &accounts.owner,
+            funding_wallet_authority: &accounts.funding_wallet_authority,
+            funding_wallet: &mut accounts.funding_wallet,
+            vault: &accounts.vault,
+            fee_wallet: &accounts.fee_wallet,
+            fee_token_wallet: &accounts.fee_token_wallet,
+            mint_info: &mut accounts.mint_info,
+            mint_fee: MintFeeOverride::load(&accounts.mint_fee)?,
+            stablecoin_fee: StablecoinFeeConfig::load(&accounts.stablecoin_fee)?,
+            config: &accounts.config,
+            country_banlist: &accounts.country_banlist,
+            clock: &accounts.clock,
+            system_program: &accounts.system_program,
+            token_program: &accounts.token_program,
+            remaining_accounts: ctx.remaining_accounts,
         }
-        .make()?;
+        .create(args)?;
 
-        sol_log("Create locker: finish");
+        sol_log("Create PDA locker: finish");
 
         Ok(())
     }
 
+    /// Moves the unlock date later, the linear emission is stretched up to it.
+    /// Tranches and cliffs have their own dates, so such lockers are rejected.
     pub fn relock(ctx: Context<Relock>, unlock_date: i64) -> Result<()> {
         sol_log("Relock");
 
+        ctx.accounts.config.require_deposits_allowed()?;
+
         let locker = &mut ctx.accounts.locker;
 
+        require!(
+            locker.tranches.is_none() && locker.cliff.is_none(),
+            CannotRelockSchedule
+        );
+
         require!(
             unlock_date > locker.current_unlock_date,
             CannotUnlockToEarlierDate
         );
 
+        emit!(Relocked {
+            locker: locker.key(),
+            old_unlock_date: locker.current_unlock_date,
+            new_unlock_date: unlock_date,
+        });
+
         locker.current_unlock_date = unlock_date;
 
         Ok(())
     }
 
-    pub fn transfer_ownership(ctx: Context<TransferOwnership>) -> Result<()> {
+    /// One-step transfer, the new owner isn't required to accept it, so a wrong
+    /// `new_owner` loses the locker. The safe way is `propose_owner`, so the caller
+    /// should opt in with `unsafe_skip_acceptance`. The pending transfer lapses here.
+    pub fn transfer_ownership(
+        ctx: Context<TransferOwnership>,
+        unsafe_skip_acceptance: bool,
+    ) -> Result<()> {
         sol_log("Transfer ownership");
 
+        require!(unsafe_skip_acceptance, AcceptanceRequired);
+        ctx.accounts.config.require_withdrawals_allowed()?;
+
+        let locker = &mut ctx.accounts.locker;
+
+        emit!(OwnershipTransferred {
+            locker: locker.key(),
+            old_owner: locker.owner,
+            new_owner: ctx.accounts.new_owner.key(),
+        });
+
+        locker.owner = ctx.accounts.new_owner.key();
+
+        Ok(())
+    }
+
+    /// First step of the ownership transfer, the new owner should accept it.
+    /// Proposing again replaces the pending owner.
+    pub fn propose_owner(ctx: Context<ProposeOwner>) -> Result<()> {
+        sol_log("Propose owner");
+
+        ctx.accounts.config.require_withdrawals_allowed()?;
+
+        let locker = ctx.accounts.locker.key();
+        let (_, bump) = Pubkey::find_program_address(
+            &["pending_owner".as_ref(), locker.as_ref()],
+            ctx.program_id,
+        );
+        let pending_owner = ctx.accounts.pending_owner.deref_mut();
+
+        *pending_owner = PendingOwner {
+            bump,
+            proposed_by: ctx.accounts.owner.key(),
+            owner: ctx.accounts.new_owner.key(),
+        };
+
+        Ok(())
+    }
+
+    /// Returns the rent of the pending owner to the old owner.
+    pub fn accept_ownership(ctx: Context<AcceptOwnership>) -> Result<()> {
+        sol_log("Accept ownership");
+
+        ctx.accounts.config.require_withdrawals_allowed()?;
+
         let locker = &mut ctx.accounts.locker;
 
+        emit!(OwnershipTransferred {
+            locker: locker.key(),
+            old_owner: locker.owner,
+            new_owner: ctx.accounts.new_owner.key(),
+        });
+
         locker.owner = ctx.accounts.new_owner.key();
 
         Ok(())
     }
 
-    pub fn increment_lock(ctx: Context<IncrementLock>, amount: u64) -> Result<()> {
+    /// Closes the pending owner. Allowed when the program is paused
+    /// since it only reduces the risks.
+    pub fn cancel_ownership_transfer(_ctx: Context<CancelOwnershipTransfer>) -> Result<()> {
+        sol_log("Cancel ownership transfer");
+
+        Ok(())
+    }
+
+    /// Sets the wallet owner receiving the withdrawn funds, see
+    /// `LockerBeneficiary`. Setting it again replaces the beneficiary.
+    pub fn set_beneficiary(ctx: Context<SetBeneficiary>, beneficiary: Pubkey) -> Result<()> {
+        sol_log("Set beneficiary");
+
+        ctx.accounts.config.require_withdrawals_allowed()?;
+
+        let locker = ctx.accounts.locker.key();
+        let (_, bump) = Pubkey::find_program_address(
+            &["beneficiary".as_ref(), locker.as_ref()],
+            ctx.program_id,
+        );
+        let locker_beneficiary = ctx.accounts.beneficiary.deref_mut();
+        // The account created right now is zeroed.
+        let old_beneficiary =
+            Some(locker_beneficiary.beneficiary).filter(|old| *old != Pubkey::default());
+
+        emit!(BeneficiaryChanged {
+            locker,
+            old_beneficiary,
+            new_beneficiary: Some(beneficiary),
+        });
+
+        *locker_beneficiary = LockerBeneficiary { bump, beneficiary };
+
+        Ok(())
+    }
+
+    /// Closes the beneficiary, so the owner withdraws anywhere again.
+    pub fn remove_beneficiary(ctx: Context<RemoveBeneficiary>) -> Result<()> {
+        sol_log("Remove beneficiary");
+
+        ctx.accounts.config.require_withdrawals_allowed()?;
+
+        emit!(BeneficiaryChanged {
+            locker: ctx.accounts.locker.key(),
+            old_beneficiary: Some(ctx.accounts.beneficiary.beneficiary),
+            new_beneficiary: None,
+        });
+
+        Ok(())
+    }
+
+    /// Takes the fee discount accounts from the remaining accounts,
+    /// see `holder_discount`.
+    pub fn increment_lock<'info>(
+        ctx: Context<'_, '_, '_, 'info, IncrementLock<'info>>,
+        amount: u64,
+    ) -> Result<()> {
         sol_log("Increment lock");
 
         let locker = &mut ctx.accounts.locker;
         let mint_info = &ctx.accounts.mint_info;
+        let mint_fee = MintFeeOverride::load(&ctx.accounts.mint_fee)?;
         let config = &ctx.accounts.config;
+        config.require_deposits_allowed()?;
+
+        let discount = holder_discount(
+            ctx.remaining_accounts,
+            &ctx.accounts.funding_wallet_authority,
+        )?;
 
         // 3rd argument is false b/c we do not pay in sol here at all
         // but we need to check if there's fee in tokens.
-        let amount_to_lock = if should_pay_in_tokens(config, mint_info, false) {
-            let lock_fee = mul_div(
-                amount,
-                config.fee_in_token_numerator,
-                config.fee_in_token_denominator,
-            )
-            .ok_or(ErrorCode::IntegerOverflow)?;
-
+        let lock_fee = if should_pay_in_tokens(config, mint_info, &mint_fee, false) {
             FeeInTokens {
                 config,
+                mint_fee: &mint_fee,
                 funding_wallet: &mut ctx.accounts.funding_wallet,
                 funding_wallet_authority: &ctx.accounts.funding_wallet_authority,
                 fee_wallet: &ctx.accounts.fee_wallet,
-                amount: lock_fee,
+                amount,
+                discount,
                 token_program: &ctx.accounts.token_program,
             }
-            .pay()?;
-
-            amount
-                .checked_sub(lock_fee)
-                .ok_or(ErrorCode::IntegerOverflow)?
+            .pay()?
         } else {
-            amount
+            0
         };
+        let amount_to_lock = amount
+            .checked_sub(lock_fee)
+            .ok_or(ErrorCode::IntegerOverflow)?;
 
         TokenTransfer {
             amount: amount_to_lock,
@@ -289,67 +595,46 @@ pub mod locker {
             .checked_add(amount_to_lock)
             .ok_or(ErrorCode::IntegerOverflow)?;
 
+        emit!(LockIncremented {
+            locker: locker.key(),
+            amount: amount_to_lock,
+            fee: lock_fee,
+            deposited_amount: locker.deposited_amount,
+        });
+
         Ok(())
     }
 
     pub fn withdraw_funds(ctx: Context<WithdrawFunds>, amount: u64) -> Result<()> {
         sol_log("Withdraw funds");
 
+        ctx.accounts.config.require_withdrawals_allowed()?;
+
+        if let Some(beneficiary) = LockerBeneficiary::load(&ctx.accounts.beneficiary)? {
+            require!(
+                ctx.accounts.target_wallet.owner == beneficiary,
+                InvalidBeneficiary
+            );
+        }
+
         let now = ctx.accounts.clock.unix_timestamp;
         let locker = &mut ctx.accounts.locker;
         let vault = &mut ctx.accounts.vault;
 
-        let amount_to_transfer = match locker.start_emission {
-            // Allowing to withdraw everything after linear schedule
-            // (this is helpful in case of lock increments).
-            Some(_start_emission) if now > locker.current_unlock_date => amount.min(vault.amount),
-            Some(start_emission) => {
-                // If there's linear emission we should check the dates
-                // and calculate the maximum amount we can withdraw right now.
-
-                require!(now >= start_emission, TooEarlyToWithdraw);
-
-                //  start_emission                    unlock_date
-                // |----x-------x------------------------------>
-                //      ^       ^ here is the point we're in now and we should calculate
-                //      ^         this part of the total deposited amount available for
-                //      ^         withdraws
-                //      ^
-                //      ^ we could withdraw here so that point is saved as `last_withdraw`
-
-                let start = locker.last_withdraw.unwrap_or(start_emission);
-                let clamped_time = now.clamp(start, locker.current_unlock_date);
-                let elapsed = clamped_time
-                    .checked_sub(start)
-                    .ok_or(ErrorCode::IntegerOverflow)?;
-                let full_period = locker
-                    .current_unlock_date
-                    .checked_sub(start)
-                    .ok_or(ErrorCode::IntegerOverflow)?;
-                require!(full_period > 0, InvalidPeriod);
-
-                sol_log_64(
-                    amount,
-                    elapsed as u64,
-                    full_period as u64,
-                    now as u64,
-                    start as u64,
-                );
-
-                mul_div(locker.deposited_amount, elapsed, full_period as u64)
-                    .ok_or(ErrorCode::IntegerOverflow)?
-                    .min(amount)
-            }
-            None => {
-                // If there's no linear emission things are much simpler,
-                // just check the dates and withdraw either the requested amount
-                // or just the amount left in the vault
-                require!(now > locker.current_unlock_date, TooEarlyToWithdraw);
-                amount.min(vault.amount)
-            }
-        };
+        // Lockers created before cumulative withdrawals tracking
+        // should be moved to it first.
+        locker.migrate(vault.amount);
+
+        let amount_to_transfer = locker.withdraw(now, amount, vault.amount)?;
+
+        sol_log_64(
+            amount,
+            amount_to_transfer,
+            locker.withdrawn_amount,
+            locker.deposited_amount,
+            now as u64,
+        );
 
-        require!(amount_to_transfer > 0, InvalidAmount);
         require!(amount_to_transfer <= vault.amount, InvalidAmount);
 
         // Signing the transfer from the vault.
@@ -367,13 +652,19 @@ pub mod locker {
         }
         .make()?;
 
-        // Last withdraw allows us to track previous withdraws to
-        // correclty calculate amount available to withdraw with
-        // linear emission.
-        locker.last_withdraw = Some(now);
-
         vault.reload()?;
-        if vault.amount == 0 {
+        let closed = vault.amount == 0;
+
+        emit!(FundsWithdrawn {
+            locker: locker_key,
+            owner: locker.owner,
+            target_wallet: ctx.accounts.target_wallet.key(),
+            amount: amount_to_transfer,
+            withdrawn_amount: locker.withdrawn_amount,
+            closed,
+        });
+
+        if closed {
             // When we have withdrawn everything we should close
             // vault and locker accounts.
             let cpi_ctx = CpiContext::new_with_signer(
@@ -388,59 +679,230 @@ pub mod locker {
             token::close_account(cpi_ctx)?;
 
             locker.close(ctx.accounts.owner.to_account_info())?;
+            LockerBeneficiary::close(
+                &ctx.accounts.beneficiary,
+                ctx.accounts.owner.to_account_info(),
+            )?;
         }
 
         Ok(())
     }
 
-    pub fn split_locker(ctx: Context<SplitLocker>, args: SplitLockerArgs) -> Result<()> {
-        sol_log("Split locker");
+    /// Releases everything vested so far to the associated token account
+    /// of the beneficiary, or of the owner if there's none.
+    ///
+    /// Unlike `withdraw_funds` it doesn't require the owner signature,
+    /// so anyone could crank the releases of the linear emission.
+    pub fn release_vested(ctx: Context<ReleaseVested>) -> Result<()> {
+        sol_log("Release vested");
 
-        require!(args.amount > 0, InvalidAmount);
+        ctx.accounts.config.require_withdrawals_allowed()?;
 
-        let new_locker = ctx.accounts.new_locker.deref_mut();
-        let old_locker = &mut ctx.accounts.old_locker;
-        let old_vault = &mut ctx.accounts.old_vault;
+        let now = ctx.accounts.clock.unix_timestamp;
+        let locker = &mut ctx.accounts.locker;
+        let vault = &mut ctx.accounts.vault;
 
-        require!(args.amount <= old_vault.amount, InvalidAmount);
+        let recipient = LockerBeneficiary::load(&ctx.accounts.beneficiary)?.unwrap_or(locker.owner);
+        let associated_token_account = get_associated_token_address(&recipient, &vault.mint);
 
-        // Signing the transfer from the old vault to the new vault.
-        let locker_key = old_locker.key();
-        let seeds = &[locker_key.as_ref(), &[old_locker.vault_bump]];
+        require!(
+            associated_token_account == ctx.accounts.target_wallet.key(),
+            InvalidReleaseWallet
+        );
+
+        locker.migrate(vault.amount);
+
+        let amount_to_transfer = locker.withdraw(now, u64::MAX, vault.amount)?;
+
+        sol_log_64(
+            amount_to_transfer,
+            locker.withdrawn_amount,
+            locker.deposited_amount,
+            now as u64,
+            0,
+        );
+
+        let locker_key = locker.key();
+        let seeds = &[locker_key.as_ref(), &[locker.vault_bump]];
         let signers = &[&seeds[..]];
 
         TokenTransfer {
-            amount: args.amount,
-            from: old_vault,
-            to: &ctx.accounts.new_vault,
-            authority: &ctx.accounts.old_vault_authority,
+            amount: amount_to_transfer,
+            from: vault,
+            to: &ctx.accounts.target_wallet,
+            authority: &ctx.accounts.vault_authority,
             token_program: &ctx.accounts.token_program,
             signers: Some(signers),
         }
         .make()?;
 
-        // Decrease the deposited amount for linear emission calculations.
-        old_locker.deposited_amount = old_locker
-            .deposited_amount
-            .checked_sub(args.amount)
-            .ok_or(ErrorCode::IntegerOverflow)?;
+        vault.reload()?;
+        let closed = vault.amount == 0;
 
-        old_vault.reload()?;
-        if old_vault.amount == 0 {
-            // When we have withdrawn everything we should close
-            // vault and locker accounts.
+        emit!(FundsWithdrawn {
+            locker: locker_key,
+            owner: locker.owner,
+            target_wallet: ctx.accounts.target_wallet.key(),
+            amount: amount_to_transfer,
+            withdrawn_amount: locker.withdrawn_amount,
+            closed,
+        });
+
+        if closed {
+            // The rent of the closed accounts goes to the owner
+            // no matter who cranked the release.
             let cpi_ctx = CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
                 CloseAccount {
-                    account: old_vault.to_account_info(),
-                    destination: ctx.accounts.old_owner.to_account_info(),
-                    authority: ctx.accounts.old_vault_authority.to_account_info(),
+                    account: vault.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.vault_authority.to_account_info(),
+                },
+                signers,
+            );
+            token::close_account(cpi_ctx)?;
+
+            locker.close(ctx.accounts.owner.to_account_info())?;
+            LockerBeneficiary::close(
+                &ctx.accounts.beneficiary,
+                ctx.accounts.owner.to_account_info(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Claws back the unvested part of a revocable locker to the treasury
+    /// wallet of the revoke authority, the vested part stays withdrawable
+    /// by the owner.
+    pub fn revoke_locker(ctx: Context<RevokeLocker>) -> Result<()> {
+        sol_log("Revoke locker");
+
+        ctx.accounts.config.require_withdrawals_allowed()?;
+
+        let now = ctx.accounts.clock.unix_timestamp;
+        let locker = &mut ctx.accounts.locker;
+        let vault = &mut ctx.accounts.vault;
+
+        locker.migrate(vault.amount);
+
+        let amount = locker.revoke(now)?;
+
+        sol_log_64(
+            amount,
+            locker.withdrawn_amount,
+            locker.deposited_amount,
+            now as u64,
+            0,
+        );
+
+        require!(amount <= vault.amount, InvalidAmount);
+
+        let locker_key = locker.key();
+        let seeds = &[locker_key.as_ref(), &[locker.vault_bump]];
+        let signers = &[&seeds[..]];
+
+        TokenTransfer {
+            amount,
+            from: vault,
+            to: &ctx.accounts.treasury_wallet,
+            authority: &ctx.accounts.vault_authority,
+            token_program: &ctx.accounts.token_program,
+            signers: Some(signers),
+        }
+        .make()?;
+
+        vault.reload()?;
+        let closed = vault.amount == 0;
+
+        emit!(LockerRevoked {
+            locker: locker_key,
+            revoke_authority: ctx.accounts.revoke_authority.key(),
+            treasury_wallet: ctx.accounts.treasury_wallet.key(),
+            amount,
+            deposited_amount: locker.deposited_amount,
+            closed,
+        });
+
+        if closed {
+            // Nothing was vested or everything vested was withdrawn already.
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: vault.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.vault_authority.to_account_info(),
+                },
+                signers,
+            );
+            token::close_account(cpi_ctx)?;
+
+            locker.close(ctx.accounts.owner.to_account_info())?;
+            LockerBeneficiary::close(
+                &ctx.accounts.beneficiary,
+                ctx.accounts.owner.to_account_info(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn split_locker(ctx: Context<SplitLocker>, args: SplitLockerArgs) -> Result<()> {
+        sol_log("Split locker");
+
+        // Split moves the funds out of the old locker into the new one.
+        ctx.accounts.config.require_withdrawals_allowed()?;
+        ctx.accounts.config.require_deposits_allowed()?;
+
+        require!(args.amount > 0, InvalidAmount);
+
+        let beneficiary = LockerBeneficiary::load(&ctx.accounts.old_beneficiary)?;
+        let new_locker = ctx.accounts.new_locker.deref_mut();
+        let old_locker = &mut ctx.accounts.old_locker;
+        let old_vault = &mut ctx.accounts.old_vault;
+
+        require!(args.amount <= old_vault.amount, InvalidAmount);
+
+        // Lockers created before cumulative withdrawals tracking
+        // should be moved to it first.
+        old_locker.migrate(old_vault.amount);
+        let (deposited_amount, withdrawn_amount) = old_locker.split_off(args.amount)?;
+
+        // Signing the transfer from the old vault to the new vault.
+        let locker_key = old_locker.key();
+        let seeds = &[locker_key.as_ref(), &[old_locker.vault_bump]];
+        let signers = &[&seeds[..]];
+
+        TokenTransfer {
+            amount: args.amount,
+            from: old_vault,
+            to: &ctx.accounts.new_vault,
+            authority: &ctx.accounts.old_vault_authority,
+            token_program: &ctx.accounts.token_program,
+            signers: Some(signers),
+        }
+        .make()?;
+
+        old_vault.reload()?;
+        if old_vault.amount == 0 {
+            // When we have withdrawn everything we should close
+            // vault and locker accounts.
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: old_vault.to_account_info(),
+                    destination: ctx.accounts.old_owner.to_account_info(),
+                    authority: ctx.accounts.old_vault_authority.to_account_info(),
                 },
                 signers,
             );
             token::close_account(cpi_ctx)?;
 
             old_locker.close(ctx.accounts.old_owner.to_account_info())?;
+            LockerBeneficiary::close(
+                &ctx.accounts.old_beneficiary,
+                ctx.accounts.old_owner.to_account_info(),
+            )?;
         }
 
         *new_locker = Locker {
@@ -449,19 +911,388 @@ pub mod locker {
             current_unlock_date: old_locker.current_unlock_date,
             start_emission: old_locker.start_emission,
             last_withdraw: None,
-            deposited_amount: args.amount,
+            deposited_amount,
             vault: ctx.accounts.new_vault.key(),
             vault_bump: args.vault_bump,
+            cliff: old_locker.cliff,
+            tranches: old_locker.tranches,
+            withdrawn_amount,
+            split_count: 0,
+            revoke_authority: old_locker.revoke_authority,
+            emergency_unlocks: 0,
+        };
+
+        if let Some(beneficiary) = beneficiary {
+            LockerBeneficiary::carry_over(
+                beneficiary,
+                &ctx.accounts.new_locker.key(),
+                &ctx.accounts.new_beneficiary,
+                &ctx.accounts.old_owner,
+                &ctx.accounts.rent,
+                &ctx.accounts.system_program,
+            )?;
+        }
+
+        emit!(LockerSplit {
+            old_locker: locker_key,
+            new_locker: ctx.accounts.new_locker.key(),
+            new_owner: ctx.accounts.new_owner.key(),
+            amount: args.amount,
+            deposited_amount,
+            withdrawn_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Splits the locker into several new ones at once. The new lockers are
+    /// seeded by the index of the split rather than by the amount, so equal
+    /// parts don't collide. Every part takes the new locker, its vault
+    /// authority, its vault and its beneficiary from the remaining accounts,
+    /// in this order.
+    pub fn split_locker_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, SplitLockerBatch<'info>>,
+        args: SplitLockerBatchArgs,
+    ) -> Result<()> {
+        sol_log("Split locker batch");
+
+        // Split moves the funds out of the old locker into the new ones.
+        ctx.accounts.config.require_withdrawals_allowed()?;
+        ctx.accounts.config.require_deposits_allowed()?;
+
+        require!(
+            !args.parts.is_empty() && args.parts.len() <= SplitLockerBatchArgs::MAX_PARTS,
+            InvalidSplitParts
+        );
+        require!(
+            ctx.remaining_accounts.len() == args.parts.len() * 4,
+            InvalidSplitParts
+        );
+
+        let program_id = ctx.program_id;
+        let accounts = ctx.accounts;
+        let rent = Rent::from_account_info(&accounts.rent)?;
+
+        let locker_key = accounts.old_locker.key();
+        let beneficiary = LockerBeneficiary::load(&accounts.old_beneficiary)?;
+        let old_locker = &mut accounts.old_locker;
+        let old_vault = &mut accounts.old_vault;
+
+        // Signing the transfers from the old vault to the new vaults.
+        let seeds = &[locker_key.as_ref(), &[old_locker.vault_bump]];
+        let signers = &[&seeds[..]];
+
+        // Lockers created before cumulative withdrawals tracking
+        // should be moved to it first.
+        old_locker.migrate(old_vault.amount);
+
+        for (part, new_accounts) in args
+            .parts
+            .iter()
+            .zip(ctx.remaining_accounts.chunks_exact(4))
+        {
+            require!(part.amount <= old_vault.amount, InvalidAmount);
+            let (deposited_amount, withdrawn_amount) = old_locker.split_off(part.amount)?;
+
+            let (new_locker, new_vault_authority, new_vault, new_beneficiary) = (
+                &new_accounts[0],
+                &new_accounts[1],
+                &new_accounts[2],
+                &new_accounts[3],
+            );
+
+            let new_locker_key = new_locker.key();
+            let index = old_locker.split_count.to_be_bytes();
+            let locker_seeds = &[
+                "split".as_ref(),
+                locker_key.as_ref(),
+                index.as_ref(),
+                &[part.locker_bump],
+            ];
+            require!(
+                Pubkey::create_program_address(locker_seeds, program_id) == Ok(new_locker_key),
+                InvalidSplitParts
+            );
+            require!(
+                Pubkey::create_program_address(
+                    &[new_locker_key.as_ref(), &[part.vault_bump]],
+                    program_id
+                ) == Ok(new_vault_authority.key()),
+                InvalidVault
+            );
+            let vault_seeds = &[
+                "vault".as_ref(),
+                new_locker_key.as_ref(),
+                &[part.vault_account_bump],
+            ];
+            require!(
+                Pubkey::create_program_address(vault_seeds, program_id) == Ok(new_vault.key()),
+                InvalidVault
+            );
+
+            create_pda_account(
+                &accounts.old_owner,
+                new_vault,
+                TokenAccount::LEN,
+                &token::ID,
+                vault_seeds,
+                &rent,
+                &accounts.system_program,
+            )?;
+            token::initialize_account(CpiContext::new(
+                accounts.token_program.to_account_info(),
+                InitializeAccount {
+                    account: new_vault.clone(),
+                    mint: accounts.mint.to_account_info(),
+                    authority: new_vault_authority.clone(),
+                    rent: accounts.rent.clone(),
+                },
+            ))?;
+
+            TokenTransfer {
+                amount: part.amount,
+                from: old_vault,
+                to: &Account::try_from(new_vault)?,
+                authority: &accounts.old_vault_authority,
+                token_program: &accounts.token_program,
+                signers: Some(signers),
+            }
+            .make()?;
+
+            init_program_account(
+                &accounts.old_owner,
+                new_locker,
+                Locker::LEN,
+                locker_seeds,
+                Locker {
+                    owner: part.new_owner,
+                    country_code: old_locker.country_code,
+                    current_unlock_date: old_locker.current_unlock_date,
+                    start_emission: old_locker.start_emission,
+                    last_withdraw: None,
+                    deposited_amount,
+                    vault: new_vault.key(),
+                    vault_bump: part.vault_bump,
+                    cliff: old_locker.cliff,
+                    tranches: old_locker.tranches,
+                    withdrawn_amount,
+                    split_count: 0,
+                    revoke_authority: old_locker.revoke_authority,
+                    emergency_unlocks: 0,
+                },
+                &rent,
+                &accounts.system_program,
+            )?;
+
+            if let Some(beneficiary) = beneficiary {
+                LockerBeneficiary::carry_over(
+                    beneficiary,
+                    &new_locker_key,
+                    new_beneficiary,
+                    &accounts.old_owner,
+                    &rent,
+                    &accounts.system_program,
+                )?;
+            }
+
+            old_locker.split_count = old_locker
+                .split_count
+                .checked_add(1)
+                .ok_or(ErrorCode::IntegerOverflow)?;
+
+            emit!(LockerSplit {
+                old_locker: locker_key,
+                new_locker: new_locker_key,
+                new_owner: part.new_owner,
+                amount: part.amount,
+                deposited_amount,
+                withdrawn_amount,
+            });
+        }
+
+        if old_vault.amount == 0 {
+            // When we have split everything we should close
+            // vault and locker accounts.
+            let cpi_ctx = CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: old_vault.to_account_info(),
+                    destination: accounts.old_owner.to_account_info(),
+                    authority: accounts.old_vault_authority.to_account_info(),
+                },
+                signers,
+            );
+            token::close_account(cpi_ctx)?;
+
+            old_locker.close(accounts.old_owner.to_account_info())?;
+            LockerBeneficiary::close(
+                &accounts.old_beneficiary,
+                accounts.old_owner.to_account_info(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Inverse of `split_locker`: moves the whole source vault into the target
+    /// locker of the same owner and closes the source locker and vault.
+    /// See `Locker::can_merge` for the terms that allow the merge.
+    pub fn merge_lockers(ctx: Context<MergeLockers>) -> Result<()> {
+        sol_log("Merge lockers");
+
+        // Merge moves the funds out of the source locker into the target one.
+        ctx.accounts.config.require_withdrawals_allowed()?;
+        ctx.accounts.config.require_deposits_allowed()?;
+
+        let source_locker = &mut ctx.accounts.source_locker;
+        let target_locker = &mut ctx.accounts.target_locker;
+        let source_vault = &mut ctx.accounts.source_vault;
+
+        // Both lockers should use the cumulative withdrawals accounting
+        // before their amounts are added up.
+        source_locker.migrate(source_vault.amount);
+        target_locker.migrate(ctx.accounts.target_vault.amount);
+
+        require!(target_locker.can_merge(source_locker), IncompatibleLockers);
+        require!(
+            LockerBeneficiary::load(&ctx.accounts.source_beneficiary)?
+                == LockerBeneficiary::load(&ctx.accounts.target_beneficiary)?,
+            IncompatibleLockers
+        );
+
+        let amount = source_vault.amount;
+
+        // Signing the transfer from the source vault to the target vault.
+        let locker_key = source_locker.key();
+        let seeds = &[locker_key.as_ref(), &[source_locker.vault_bump]];
+        let signers = &[&seeds[..]];
+
+        TokenTransfer {
+            amount,
+            from: source_vault,
+            to: &ctx.accounts.target_vault,
+            authority: &ctx.accounts.source_vault_authority,
+            token_program: &ctx.accounts.token_program,
+            signers: Some(signers),
+        }
+        .make()?;
+
+        // Withdrawn amounts are added up as well, so the vested part of the
+        // merged deposit that's available to withdraw is the sum of both.
+        target_locker.deposited_amount = target_locker
+            .deposited_amount
+            .checked_add(source_locker.deposited_amount)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+        target_locker.withdrawn_amount = target_locker
+            .withdrawn_amount
+            .checked_add(source_locker.withdrawn_amount)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: source_vault.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: ctx.accounts.source_vault_authority.to_account_info(),
+            },
+            signers,
+        );
+        token::close_account(cpi_ctx)?;
+
+        source_locker.close(ctx.accounts.owner.to_account_info())?;
+        LockerBeneficiary::close(
+            &ctx.accounts.source_beneficiary,
+            ctx.accounts.owner.to_account_info(),
+        )?;
+
+        emit!(LockersMerged {
+            source_locker: locker_key,
+            target_locker: target_locker.key(),
+            amount,
+            deposited_amount: target_locker.deposited_amount,
+            withdrawn_amount: target_locker.withdrawn_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Moves the locker created before cumulative withdrawals tracking
+    /// to the new accounting. Anyone can call it since the result is fully
+    /// determined by the locker and vault state.
+    pub fn migrate_locker(ctx: Context<MigrateLocker>) -> Result<()> {
+        sol_log("Migrate locker");
+
+        ctx.accounts.config.require_withdrawals_allowed()?;
+
+        let vault_amount = ctx.accounts.vault.amount;
+        ctx.accounts.locker.migrate(vault_amount);
+
+        Ok(())
+    }
+
+    /// Allows admin to unlock the locker before the unlock date in genuine
+    /// support cases. The funds still go to the owner via `withdraw_funds`,
+    /// every unlock leaves an `EmergencyUnlockRecord` behind.
+    pub fn emergency_unlock(
+        ctx: Context<EmergencyUnlock>,
+        args: EmergencyUnlockArgs,
+    ) -> Result<()> {
+        sol_log("Emergency unlock");
+
+        require!(
+            args.reason.len() <= EmergencyUnlockRecord::MAX_REASON_LEN,
+            ReasonTooLong
+        );
+
+        let now = ctx.accounts.clock.unix_timestamp;
+        let locker = &mut ctx.accounts.locker;
+        let record = ctx.accounts.record.deref_mut();
+
+        *record = EmergencyUnlockRecord {
+            locker: locker.key(),
+            index: locker.emergency_unlocks,
+            owner: locker.owner,
+            admin: ctx.accounts.admin.key(),
+            previous_unlock_date: locker.current_unlock_date,
+            vault_amount: ctx.accounts.vault.amount,
+            unlocked_at: now,
+            reason: args.reason.clone(),
         };
 
+        emit!(EmergencyUnlocked {
+            locker: locker.key(),
+            admin: ctx.accounts.admin.key(),
+            reason: args.reason,
+        });
+
+        // The unlock date is in the past, so everything is withdrawable right away.
+        locker.current_unlock_date = now.checked_sub(1).ok_or(ErrorCode::IntegerOverflow)?;
+        locker.emergency_unlocks = locker
+            .emergency_unlocks
+            .checked_add(1)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+
         Ok(())
     }
 
     /// For the test purposes -- allows to close lockers.
-    /// TODO: hide it behind feature flag
+    /// Production builds reject it, see `test-utils` feature.
+    ///
+    /// `#[program]` of Anchor 0.18 dispatches every handler it sees
+    /// regardless of `#[cfg]`, so the handler can't be compiled out.
     pub fn close_locker(ctx: Context<CloseLocker>) -> Result<()> {
         sol_log("Close locker");
 
+        require!(cfg!(feature = "test-utils"), TestOnlyInstruction);
+        ctx.accounts.config.require_withdrawals_allowed()?;
+
+        if let Some(beneficiary) = LockerBeneficiary::load(&ctx.accounts.beneficiary)? {
+            require!(
+                ctx.accounts.target_wallet.owner == beneficiary,
+                InvalidBeneficiary
+            );
+        }
+
         let locker = &ctx.accounts.locker;
         let vault = &mut ctx.accounts.vault;
 
@@ -469,6 +1300,15 @@ pub mod locker {
         let seeds = &[locker_key.as_ref(), &[locker.vault_bump]];
         let signers = &[&seeds[..]];
 
+        emit!(FundsWithdrawn {
+            locker: locker_key,
+            owner: locker.owner,
+            target_wallet: ctx.accounts.target_wallet.key(),
+            amount: vault.amount,
+            withdrawn_amount: locker.withdrawn_amount,
+            closed: true,
+        });
+
         TokenTransfer {
             amount: vault.amount,
             from: vault,
@@ -491,6 +1331,10 @@ pub mod locker {
         token::close_account(cpi_ctx)?;
 
         locker.close(ctx.accounts.owner.to_account_info())?;
+        LockerBeneficiary::close(
+            &ctx.accounts.beneficiary,
+            ctx.accounts.owner.to_account_info(),
+        )?;
 
         Ok(())
     }
@@ -500,43 +1344,178 @@ pub mod locker {
 #[derive(Debug)]
 pub struct Config {
     /// Admin account.
-    admin: Pubkey,
-    /// Fee in SOL tokens (not the lamports!).
-    fee_in_sol: u64,
+    pub admin: Pubkey,
+    /// Fee in SOL, in lamports.
+    ///
+    /// Configs created before `fee_in_lamports` keep whole SOL here
+    /// until migrated.
+    pub fee_in_sol: u64,
     /// Numerator / Denominator = fee.
     /// i.e. 35 / 10000 = 0.035%
-    fee_in_token_numerator: u64,
-    fee_in_token_denominator: u64,
+    pub fee_in_token_numerator: u64,
+    pub fee_in_token_denominator: u64,
     /// Whether mint info can be created by anyone or just admin.
-    mint_info_permissioned: bool,
+    pub mint_info_permissioned: bool,
     /// Whether we should allow the lockers with linear emission.
-    has_linear_emission: bool,
+    pub has_linear_emission: bool,
     /// SOL wallet where we send the fees in SOL and the fees in
     /// tokens via token accounts associated with this account.
-    fee_wallet: Pubkey,
+    pub fee_wallet: Pubkey,
     /// List of countries under our control.
-    country_list: Pubkey,
-    bump: u8,
+    pub country_list: Pubkey,
+    pub bump: u8,
+    /// New fields are appended to the end of the struct, so the config
+    /// created before the field was introduced still decodes (as `false`).
+    ///
+    /// Stops new deposits: `create_locker`, `increment_lock`,
+    /// `relock`, `split_locker` and `merge_lockers`.
+    pub deposits_paused: bool,
+    /// Stops everything touching existing lockers: `withdraw_funds`,
+    /// `release_vested`, `revoke_locker`, `split_locker`, `merge_lockers`,
+    /// ownership transfers, `set_beneficiary`, `remove_beneficiary`,
+    /// `migrate_locker`, `close_locker`.
+    pub withdrawals_paused: bool,
+    /// Whether `fee_in_sol` is in lamports, it's `false` for the configs
+    /// created before the field was introduced until `migrate_config`.
+    pub fee_in_lamports: bool,
 }
 
 impl Config {
     pub const LEN: usize = 8 + std::mem::size_of::<Self>();
+
+    fn updated_event(&self) -> ConfigUpdated {
+        ConfigUpdated {
+            admin: self.admin,
+            fee_in_sol: self.fee_in_sol,
+            fee_in_token_numerator: self.fee_in_token_numerator,
+            fee_in_token_denominator: self.fee_in_token_denominator,
+            mint_info_permissioned: self.mint_info_permissioned,
+            has_linear_emission: self.has_linear_emission,
+            fee_wallet: self.fee_wallet,
+            country_list: self.country_list,
+            deposits_paused: self.deposits_paused,
+            withdrawals_paused: self.withdrawals_paused,
+        }
+    }
+
+    fn require_deposits_allowed(&self) -> Result<()> {
+        require!(!self.deposits_paused, ProgramPaused);
+        Ok(())
+    }
+
+    fn require_withdrawals_allowed(&self) -> Result<()> {
+        require!(!self.withdrawals_paused, ProgramPaused);
+        Ok(())
+    }
+
+    /// Fee in SOL in lamports, whatever unit the config keeps it in.
+    fn sol_fee(&self) -> Result<u64> {
+        if self.fee_in_lamports {
+            return Ok(self.fee_in_sol);
+        }
+
+        Ok(self
+            .fee_in_sol
+            .checked_mul(solana_program::native_token::LAMPORTS_PER_SOL)
+            .ok_or(ErrorCode::IntegerOverflow)?)
+    }
+
+    /// Moves the fee in SOL to lamports, does nothing if it's there already.
+    fn migrate(&mut self) -> Result<()> {
+        self.fee_in_sol = self.sol_fee()?;
+        self.fee_in_lamports = true;
+        Ok(())
+    }
 }
 
-#[derive(AnchorDeserialize, AnchorSerialize)]
-pub struct CreateConfigArgs {
-    pub fee_in_sol: u64,
-    pub fee_in_token_numerator: u64,
-    pub fee_in_token_denominator: u64,
-    pub mint_info_permissioned: bool,
-    pub has_linear_emission: bool,
+/// Admin proposed by `propose_admin`, becomes the admin once accepted.
+/// The configs created before the handover was introduced have no room
+/// left for it, so it's kept in a PDA next to the config.
+#[account]
+#[derive(Debug)]
+pub struct PendingAdmin {
+    pub bump: u8,
+    pub admin: Pubkey,
+}
+
+impl PendingAdmin {
+    pub const LEN: usize = 8 + 1 + 32;
+}
+
+/// Flat fee in the stablecoin accepted instead of the fee in SOL, set by
+/// `set_stablecoin_fee`. The configs created before it have no room left
+/// for it, so it's kept in a PDA next to the config.
+#[account]
+#[derive(Debug)]
+pub struct StablecoinFeeConfig {
     pub bump: u8,
+    /// `None` if the stablecoin isn't accepted.
+    pub fee: Option<StablecoinFee>,
+}
+
+impl StablecoinFeeConfig {
+    pub const LEN: usize = 8 + 1 + 1 + 32 + 8;
+
+    /// Reads the fee out of the account checked by the seeds,
+    /// `None` if `set_stablecoin_fee` hasn't created it.
+    fn load(info: &AccountInfo) -> Result<Option<StablecoinFee>> {
+        if info.owner != &ID || info.lamports() == 0 {
+            return Ok(None);
+        }
+
+        Ok(Account::<Self>::try_from(info)?.fee)
+    }
+}
+
+/// Flat fee in tokens of the stablecoin mint, i.e. USDC.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct StablecoinFee {
+    pub mint: Pubkey,
+    /// In the smallest units of the mint.
+    pub amount: u64,
 }
 
 #[derive(Accounts)]
-#[instruction(args: CreateConfigArgs)]
-pub struct InitConfig<'info> {
-    #[account(signer)]
+pub struct SetStablecoinFee<'info> {
+    #[account(mut, signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        seeds = [
+            "stablecoin_fee".as_ref()
+        ],
+        bump,
+        space = StablecoinFeeConfig::LEN
+    )]
+    stablecoin_fee: ProgramAccount<'info, StablecoinFeeConfig>,
+    #[account(
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorDeserialize, AnchorSerialize)]
+pub struct CreateConfigArgs {
+    /// In lamports.
+    pub fee_in_sol: u64,
+    pub fee_in_token_numerator: u64,
+    pub fee_in_token_denominator: u64,
+    pub mint_info_permissioned: bool,
+    pub has_linear_emission: bool,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+#[instruction(args: CreateConfigArgs)]
+pub struct InitConfig<'info> {
+    #[account(signer)]
     admin: AccountInfo<'info>,
     #[account(
         init,
@@ -556,11 +1535,12 @@ pub struct InitConfig<'info> {
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct UpdateConfigArgs {
-    fee_in_sol: Option<u64>,
-    fee_in_token_numerator: Option<u64>,
-    fee_in_token_denominator: Option<u64>,
-    mint_info_permissioned: Option<bool>,
-    has_linear_emission: Option<bool>,
+    /// In lamports.
+    pub fee_in_sol: Option<u64>,
+    pub fee_in_token_numerator: Option<u64>,
+    pub fee_in_token_denominator: Option<u64>,
+    pub mint_info_permissioned: Option<bool>,
+    pub has_linear_emission: Option<bool>,
 }
 
 #[derive(Accounts)]
@@ -582,21 +1562,358 @@ pub struct UpdateConfig<'info> {
     country_list: Account<'info, country_list::CountryBanList>,
 }
 
+#[derive(Accounts)]
+pub struct ProposeAdmin<'info> {
+    #[account(mut, signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        seeds = [
+            "pending_admin".as_ref()
+        ],
+        bump,
+        space = PendingAdmin::LEN
+    )]
+    pending_admin: ProgramAccount<'info, PendingAdmin>,
+    new_admin: AccountInfo<'info>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    #[account(signer)]
+    new_admin: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
+    #[account(
+        mut,
+        seeds = [
+            "pending_admin".as_ref()
+        ],
+        bump = pending_admin.bump,
+        constraint = pending_admin.admin == new_admin.key(),
+        close = admin
+    )]
+    pending_admin: ProgramAccount<'info, PendingAdmin>,
+    /// Current admin, receives the rent of the pending admin.
+    #[account(mut)]
+    admin: AccountInfo<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetPauseArgs {
+    pub deposits: Option<bool>,
+    pub withdrawals: Option<bool>,
+}
+
+#[derive(Accounts)]
+pub struct SetPause<'info> {
+    #[account(signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
+}
+
 #[account]
 #[derive(Debug)]
 pub struct Locker {
-    owner: Pubkey,
-    country_code: [u8; 2],
-    current_unlock_date: i64,
-    start_emission: Option<i64>,
-    last_withdraw: Option<i64>,
-    deposited_amount: u64,
-    vault: Pubkey,
-    vault_bump: u8,
+    pub owner: Pubkey,
+    pub country_code: [u8; 2],
+    pub current_unlock_date: i64,
+    pub start_emission: Option<i64>,
+    /// Deprecated: lockers created before `withdrawn_amount` was introduced
+    /// have it set if there were any withdraws, see `Locker::migrate`.
+    pub last_withdraw: Option<i64>,
+    pub deposited_amount: u64,
+    pub vault: Pubkey,
+    pub vault_bump: u8,
+    /// New fields are appended to the end of the struct, so the accounts
+    /// created before the field was introduced still decode (as `None`).
+    pub cliff: Option<Cliff>,
+    pub tranches: Option<TrancheSchedule>,
+    /// Total amount withdrawn from the locker.
+    pub withdrawn_amount: u64,
+    /// Number of lockers split out of this one with `split_locker_batch`,
+    /// the index of the next one seeds its address.
+    pub split_count: u32,
+    /// Could claw back the unvested part of the locker with `revoke_locker`.
+    ///
+    /// Set on creation only, so the lockers created before the field
+    /// was introduced keep `None`.
+    pub revoke_authority: Option<Pubkey>,
+    /// Number of the `emergency_unlock`s of the locker, the index of
+    /// the next one seeds its record.
+    pub emergency_unlocks: u32,
 }
 
 impl Locker {
     pub const LEN: usize = std::mem::size_of::<Self>() + 8;
+
+    /// Lockers created before `withdrawn_amount` was introduced track only
+    /// the `last_withdraw` timestamp. Increments and splits change the vault
+    /// balance together with the deposited amount, so the difference between
+    /// them is exactly the amount withdrawn so far.
+    fn migrate(&mut self, vault_amount: u64) {
+        if self.last_withdraw.take().is_some() {
+            self.withdrawn_amount = self.deposited_amount.saturating_sub(vault_amount);
+        }
+    }
+
+    fn has_schedule(&self) -> bool {
+        self.start_emission.is_some() || self.tranches.is_some()
+    }
+
+    fn has_same_schedule(&self, other: &Locker) -> bool {
+        self.current_unlock_date == other.current_unlock_date
+            && self.start_emission == other.start_emission
+            && self.cliff == other.cliff
+            && self.tranches.as_ref().map(TrancheSchedule::tranches)
+                == other.tranches.as_ref().map(TrancheSchedule::tranches)
+    }
+
+    /// Whether the funds of `source` can be moved into this locker without
+    /// releasing any of them earlier or to someone else: either the schedules
+    /// are identical, or this locker releases everything at once no earlier
+    /// than `source`. Revocable funds stay revocable by the same authority.
+    fn can_merge(&self, source: &Locker) -> bool {
+        self.country_code == source.country_code
+            && self.revoke_authority == source.revoke_authority
+            && (self.has_same_schedule(source)
+                || (!self.has_schedule() && self.current_unlock_date >= source.current_unlock_date))
+    }
+
+    /// Part of the deposited amount vested at `now` according to the locker schedule.
+    pub fn vested_amount(&self, now: i64) -> Option<u64> {
+        if now > self.current_unlock_date {
+            return Some(self.deposited_amount);
+        }
+
+        match (self.start_emission, &self.cliff, &self.tranches) {
+            (Some(start_emission), Some(cliff), _) => cliff_vested_amount(
+                self.deposited_amount,
+                start_emission,
+                cliff,
+                self.current_unlock_date,
+                now,
+            ),
+            (Some(start_emission), None, _) => linear_vested_amount(
+                self.deposited_amount,
+                start_emission,
+                self.current_unlock_date,
+                now,
+            ),
+            (None, _, Some(tranches)) => tranches.vested_amount(self.deposited_amount, now),
+            // If there's no emission schedule nothing is vested before the unlock date.
+            (None, _, None) => Some(0),
+        }
+    }
+
+    /// Amount available to withdraw from the vault at `now`.
+    fn available_amount(&self, now: i64, vault_amount: u64) -> Result<u64> {
+        // Allowing to withdraw everything after the schedule
+        // (this is helpful in case of lock increments).
+        if now > self.current_unlock_date {
+            return Ok(vault_amount);
+        }
+
+        //  start_emission                    unlock_date
+        // |----x-------x------------------------------>
+        //      ^       ^ here is the point we're in now and we should calculate
+        //      ^         the vested part of the total deposited amount
+        //      ^
+        //      ^ we could withdraw here so that amount is saved as `withdrawn_amount`
+        //
+        // The difference between these two is available for withdraws.
+
+        let vested = self.vested_amount(now).ok_or(ErrorCode::IntegerOverflow)?;
+        require!(vested > 0, TooEarlyToWithdraw);
+
+        Ok(vested
+            .saturating_sub(self.withdrawn_amount)
+            .min(vault_amount))
+    }
+
+    /// Records the withdraw of up to `amount` tokens and returns the amount
+    /// that should be transferred from the vault.
+    fn withdraw(&mut self, now: i64, amount: u64, vault_amount: u64) -> Result<u64> {
+        let amount = self.available_amount(now, vault_amount)?.min(amount);
+        require!(amount > 0, InvalidAmount);
+
+        self.withdrawn_amount = self
+            .withdrawn_amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+
+        Ok(amount)
+    }
+
+    /// Cuts the locker down to the amount vested at `now` and returns
+    /// the unvested rest that should be clawed back from the vault.
+    ///
+    /// The vested amount stays available to the owner at once, so the
+    /// schedule is dropped and the unlock date is moved before `now`.
+    fn revoke(&mut self, now: i64) -> Result<u64> {
+        let vested = self
+            .vested_amount(now)
+            .ok_or(ErrorCode::IntegerOverflow)?
+            // Relocks could move the vested amount below the withdrawn one.
+            .max(self.withdrawn_amount);
+        let unvested = self
+            .deposited_amount
+            .checked_sub(vested)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+        require!(unvested > 0, InvalidAmount);
+
+        self.deposited_amount = vested;
+        self.current_unlock_date = now.checked_sub(1).ok_or(ErrorCode::IntegerOverflow)?;
+        self.start_emission = None;
+        self.cliff = None;
+        self.tranches = None;
+        self.revoke_authority = None;
+
+        Ok(unvested)
+    }
+
+    /// Moves `amount` of the locked tokens out of the locker together with
+    /// the proportional part of the withdrawn amount. Returns the deposited
+    /// and withdrawn amounts of the new locker.
+    ///
+    /// Both lockers keep the withdrawn share of the original one, so with
+    /// the same schedule together they vest exactly as the original locker
+    /// would have, up to the rounding of the vested amounts.
+    fn split_off(&mut self, amount: u64) -> Result<(u64, u64)> {
+        let locked = self
+            .deposited_amount
+            .checked_sub(self.withdrawn_amount)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+        require!(amount > 0 && amount <= locked, InvalidAmount);
+
+        let withdrawn_amount =
+            mul_div(self.withdrawn_amount, amount, locked).ok_or(ErrorCode::IntegerOverflow)?;
+        let deposited_amount = amount
+            .checked_add(withdrawn_amount)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+
+        self.deposited_amount = self
+            .deposited_amount
+            .checked_sub(deposited_amount)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+        self.withdrawn_amount = self
+            .withdrawn_amount
+            .checked_sub(withdrawn_amount)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+
+        Ok((deposited_amount, withdrawn_amount))
+    }
+}
+
+/// Denominator for the values expressed in basis points,
+/// i.e. 2500 / 10000 = 25%.
+pub const BASIS_POINTS_DENOMINATOR: u16 = 10000;
+
+/// Cliff of the linear emission schedule.
+///
+/// Nothing could be withdrawn before the cliff `date`. At that date
+/// `basis_points` of the deposited amount are released at once and the rest
+/// follows the linear emission from `start_emission` to the unlock date.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Cliff {
+    pub date: i64,
+    pub basis_points: u16,
+}
+
+/// Part of the deposited amount released at the given date.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tranche {
+    pub date: i64,
+    pub basis_points: u16,
+}
+
+/// Step unlock schedule -- up to `MAX_TRANCHES` tranches sorted by date
+/// with basis points summing up to 100%.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct TrancheSchedule {
+    len: u8,
+    // Keep the length in sync with `MAX_TRANCHES`.
+    tranches: [Tranche; 8],
+}
+
+impl TrancheSchedule {
+    pub const MAX_TRANCHES: usize = 8;
+
+    fn new(tranches: &[Tranche], now: i64, unlock_date: i64) -> Result<Self> {
+        require!(!tranches.is_empty(), InvalidSchedule);
+        require!(tranches.len() <= Self::MAX_TRANCHES, InvalidSchedule);
+
+        //  now    tranche    tranche    tranche    unlock_date
+        // |--------------------------------------------> time, seconds
+        let mut previous_date = now;
+        let mut total_basis_points: u16 = 0;
+        for tranche in tranches {
+            require!(tranche.date > previous_date, InvalidSchedule);
+            require!(tranche.basis_points > 0, InvalidBasisPoints);
+
+            previous_date = tranche.date;
+            total_basis_points = total_basis_points
+                .checked_add(tranche.basis_points)
+                .ok_or(ErrorCode::InvalidBasisPoints)?;
+        }
+        require!(previous_date <= unlock_date, InvalidSchedule);
+        require!(
+            total_basis_points == BASIS_POINTS_DENOMINATOR,
+            InvalidBasisPoints
+        );
+
+        let mut schedule = Self {
+            len: tranches.len() as u8,
+            tranches: Default::default(),
+        };
+        schedule.tranches[..tranches.len()].copy_from_slice(tranches);
+
+        Ok(schedule)
+    }
+
+    pub fn tranches(&self) -> &[Tranche] {
+        &self.tranches[..self.len as usize]
+    }
+
+    /// Sum of the tranches matured at `now`.
+    pub fn vested_amount(&self, deposited_amount: u64, now: i64) -> Option<u64> {
+        let mut basis_points: u16 = 0;
+        for tranche in self.tranches().iter().filter(|t| t.date <= now) {
+            basis_points = basis_points.checked_add(tranche.basis_points)?;
+        }
+
+        if basis_points >= BASIS_POINTS_DENOMINATOR {
+            return Some(deposited_amount);
+        }
+
+        mul_div(deposited_amount, basis_points, BASIS_POINTS_DENOMINATOR)
+    }
 }
 
 /// Mint info tracks the fees paid for a given mint.
@@ -604,9 +1921,10 @@ impl Locker {
 /// There's a twist for LP lockers -- MintInfo accounts
 /// can be created by admins only.
 #[account]
+#[derive(Debug)]
 pub struct MintInfo {
-    bump: u8,
-    fee_paid: bool,
+    pub bump: u8,
+    pub fee_paid: bool,
 }
 
 impl Default for MintInfo {
@@ -618,6 +1936,73 @@ impl Default for MintInfo {
     }
 }
 
+/// Fees of a mint set by admin instead of the config ones. Mint infos
+/// have no room left for them, so they're kept in a PDA of the mint.
+#[account]
+#[derive(Debug)]
+pub struct MintFeeOverride {
+    pub bump: u8,
+    pub fee: MintFee,
+}
+
+impl MintFeeOverride {
+    pub const LEN: usize = 8 + 1 + (1 + 8) + (1 + 8 + 8);
+
+    /// Reads the fee out of the account checked by the seeds,
+    /// the config fees if `set_mint_fee` hasn't created it.
+    fn load(info: &AccountInfo) -> Result<MintFee> {
+        if info.owner != &ID || info.lamports() == 0 {
+            return Ok(MintFee::default());
+        }
+
+        Ok(Account::<Self>::try_from(info)?.fee)
+    }
+}
+
+/// Fees overriding the config ones for a mint, `None` keeps the config fee
+/// and zero waives it. Both fees set to zero waive the fees completely.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct MintFee {
+    /// Fee in SOL, in lamports.
+    pub fee_in_sol: Option<u64>,
+    pub fee_in_token: Option<TokenFee>,
+}
+
+impl MintFee {
+    /// Fee in SOL in lamports charged for the mint.
+    fn fee_in_sol(&self, config: &Config) -> Result<u64> {
+        match self.fee_in_sol {
+            Some(fee) => Ok(fee),
+            None => config.sol_fee(),
+        }
+    }
+
+    /// Fee in tokens charged for the mint.
+    fn fee_in_token(&self, config: &Config) -> TokenFee {
+        self.fee_in_token.unwrap_or(TokenFee {
+            numerator: config.fee_in_token_numerator,
+            denominator: config.fee_in_token_denominator,
+        })
+    }
+}
+
+/// Numerator / Denominator = fee, i.e. 35 / 10000 = 0.035%.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TokenFee {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl TokenFee {
+    fn validate(&self) -> Result<()> {
+        require!(
+            self.denominator > 0 && self.numerator <= self.denominator,
+            InvalidFee
+        );
+        Ok(())
+    }
+}
+
 #[derive(Accounts)]
 #[instruction(bump: u8)]
 pub struct InitMintInfo<'info> {
@@ -638,14 +2023,51 @@ pub struct InitMintInfo<'info> {
     system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct SetMintFee<'info> {
+    #[account(mut, signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        seeds = [
+            "mint_fee".as_ref(),
+            mint.key().as_ref()
+        ],
+        bump,
+        space = MintFeeOverride::LEN
+    )]
+    mint_fee: ProgramAccount<'info, MintFeeOverride>,
+    mint: Account<'info, Mint>,
+    #[account(
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
+
+    system_program: Program<'info, System>,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct CreateLockerArgs {
-    amount: u64,
-    unlock_date: i64,
-    country_code: String,
-    start_emission: Option<i64>,
-    vault_bump: u8,
-    fee_in_sol: bool,
+    pub amount: u64,
+    pub unlock_date: i64,
+    pub country_code: String,
+    pub start_emission: Option<i64>,
+    pub cliff: Option<Cliff>,
+    pub tranches: Option<Vec<Tranche>>,
+    pub vault_bump: u8,
+    /// Pays the flat fee instead of the fee in tokens. Both flat fees are
+    /// paid by the signing `funding_wallet_authority`, not by the owner.
+    pub fee_in_sol: bool,
+    /// Pays the flat fee in the config stablecoin instead of SOL,
+    /// see `FeeInStablecoin`.
+    pub fee_in_stablecoin: bool,
+    /// Makes the locker revocable, see `revoke_locker`.
+    pub revoke_authority: Option<Pubkey>,
 }
 
 #[derive(Accounts)]
@@ -660,7 +2082,8 @@ pub struct CreateLocker<'info> {
     #[account(signer)]
     creator: AccountInfo<'info>,
     owner: AccountInfo<'info>,
-    #[account(signer)]
+    /// Pays the flat fee, see `CreateLockerArgs::fee_in_sol`.
+    #[account(mut, signer)]
     funding_wallet_authority: AccountInfo<'info>,
     #[account(mut)]
     funding_wallet: Account<'info, TokenAccount>,
@@ -672,9 +2095,20 @@ pub struct CreateLocker<'info> {
     )]
     vault_authority: AccountInfo<'info>,
     #[account(
-        mut,
-        constraint = vault.mint == funding_wallet.mint,
-        constraint = vault.owner == vault_authority.key()
+        constraint = mint.key() == funding_wallet.mint
+    )]
+    mint: Account<'info, Mint>,
+    /// Created here, so it can't have a delegate or a close authority.
+    #[account(
+        init,
+        payer = creator,
+        seeds = [
+            "vault".as_ref(),
+            locker.key().as_ref()
+        ],
+        bump,
+        token::mint = mint,
+        token::authority = vault_authority,
     )]
     vault: Account<'info, TokenAccount>,
     #[account(mut)]
@@ -689,6 +2123,23 @@ pub struct CreateLocker<'info> {
         bump = mint_info.bump
     )]
     mint_info: ProgramAccount<'info, MintInfo>,
+    /// Created by `set_mint_fee` only for the mints with their own fees.
+    #[account(
+        seeds = [
+            "mint_fee".as_ref(),
+            vault.mint.key().as_ref()
+        ],
+        bump
+    )]
+    mint_fee: AccountInfo<'info>,
+    /// Created by `set_stablecoin_fee` only if the stablecoin is accepted.
+    #[account(
+        seeds = [
+            "stablecoin_fee".as_ref()
+        ],
+        bump
+    )]
+    stablecoin_fee: AccountInfo<'info>,
     config: ProgramAccount<'info, Config>,
     #[account(
         constraint = country_banlist.key() == config.country_list
@@ -696,63 +2147,219 @@ pub struct CreateLocker<'info> {
     country_banlist: Account<'info, country_list::CountryBanList>,
 
     clock: Sysvar<'info, Clock>,
+    rent: Sysvar<'info, Rent>,
     system_program: Program<'info, System>,
     token_program: Program<'info, Token>,
 }
 
-#[derive(Accounts)]
-pub struct Relock<'info> {
-    #[account(mut)]
-    locker: ProgramAccount<'info, Locker>,
-    #[account(
-        signer,
-        constraint = locker.owner == owner.key()
-    )]
-    owner: AccountInfo<'info>,
+/// Fee discounts for the holders of the platform token. The configs
+/// created before have no room left for them and the runtime can't
+/// resize accounts, so they're kept in a PDA next to the config.
+#[account]
+#[derive(Debug, Default)]
+pub struct FeeDiscounts {
+    pub bump: u8,
+    /// Mint of the token giving the discounts.
+    pub mint: Pubkey,
+    /// Sorted by the min balance, the highest tier reached applies.
+    pub tiers: Vec<DiscountTier>,
 }
 
-#[derive(Accounts)]
-pub struct TransferOwnership<'info> {
-    #[account(mut)]
-    locker: ProgramAccount<'info, Locker>,
-    #[account(
-        signer,
-        constraint = locker.owner == owner.key()
-    )]
-    owner: AccountInfo<'info>,
-    new_owner: AccountInfo<'info>,
+impl FeeDiscounts {
+    pub const MAX_TIERS: usize = 8;
+    pub const LEN: usize = 8 + 1 + 32 + 4 + Self::MAX_TIERS * std::mem::size_of::<DiscountTier>();
+
+    fn validate_tiers(tiers: &[DiscountTier]) -> Result<()> {
+        require!(tiers.len() <= Self::MAX_TIERS, InvalidDiscountTiers);
+        require!(
+            tiers
+                .windows(2)
+                .all(|pair| pair[0].min_balance < pair[1].min_balance),
+            InvalidDiscountTiers
+        );
+        require!(
+            tiers
+                .iter()
+                .all(|tier| tier.basis_points <= BASIS_POINTS_DENOMINATOR),
+            InvalidBasisPoints
+        );
+        Ok(())
+    }
+
+    /// Discount in basis points for the holder of `balance` tokens.
+    fn basis_points(&self, balance: u64) -> u16 {
+        self.tiers
+            .iter()
+            .rev()
+            .find(|tier| tier.min_balance <= balance)
+            .map_or(0, |tier| tier.basis_points)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct DiscountTier {
+    /// Tokens of the discount mint the holder should have.
+    pub min_balance: u64,
+    /// Part of the fee taken off.
+    pub basis_points: u16,
 }
 
 #[derive(Accounts)]
-pub struct IncrementLock<'info> {
+pub struct SetFeeDiscounts<'info> {
+    #[account(mut, signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        seeds = [
+            "fee_discounts".as_ref()
+        ],
+        bump,
+        space = FeeDiscounts::LEN
+    )]
+    fee_discounts: ProgramAccount<'info, FeeDiscounts>,
+    #[account(
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
+
+    system_program: Program<'info, System>,
+}
+
+/// Counts the lockers created by the creator with `create_locker_with_pda`,
+/// the counter is a part of the locker address.
+#[account]
+#[derive(Debug, Default)]
+pub struct CreatorInfo {
+    pub bump: u8,
+    pub lockers_created: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct InitCreatorInfo<'info> {
+    #[account(signer)]
+    creator: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = creator,
+        seeds = [
+            "creator".as_ref(),
+            creator.key().as_ref()
+        ],
+        bump = bump
+    )]
+    creator_info: ProgramAccount<'info, CreatorInfo>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: CreateLockerArgs)]
+pub struct CreatePdaLocker<'info> {
+    #[account(signer)]
+    creator: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            "creator".as_ref(),
+            creator.key().as_ref()
+        ],
+        bump = creator_info.bump
+    )]
+    creator_info: ProgramAccount<'info, CreatorInfo>,
+    owner: AccountInfo<'info>,
+    /// Pays the flat fee, see `CreateLockerArgs::fee_in_sol`.
+    #[account(mut, signer)]
+    funding_wallet_authority: AccountInfo<'info>,
     #[account(mut)]
+    funding_wallet: Account<'info, TokenAccount>,
+    /// The canonical bump is used, so there's exactly one locker
+    /// for every counter value.
+    #[account(
+        init,
+        payer = creator,
+        seeds = [
+            "locker".as_ref(),
+            creator.key().as_ref(),
+            funding_wallet.mint.as_ref(),
+            creator_info.lockers_created.to_be_bytes().as_ref()
+        ],
+        bump,
+        space = Locker::LEN,
+    )]
     locker: ProgramAccount<'info, Locker>,
     #[account(
-        mut,
-        constraint = vault.mint == funding_wallet.mint,
-        constraint = locker.vault == vault.key()
+        seeds = [
+            locker.key().as_ref()
+        ],
+        bump = args.vault_bump
+    )]
+    vault_authority: AccountInfo<'info>,
+    #[account(
+        constraint = mint.key() == funding_wallet.mint
+    )]
+    mint: Account<'info, Mint>,
+    /// Created here, so it can't have a delegate or a close authority.
+    #[account(
+        init,
+        payer = creator,
+        seeds = [
+            "vault".as_ref(),
+            locker.key().as_ref()
+        ],
+        bump,
+        token::mint = mint,
+        token::authority = vault_authority,
     )]
     vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    fee_wallet: AccountInfo<'info>,
+    #[account(mut)]
+    fee_token_wallet: Account<'info, TokenAccount>,
     #[account(
+        mut,
         seeds = [
             vault.mint.key().as_ref()
         ],
         bump = mint_info.bump
     )]
     mint_info: ProgramAccount<'info, MintInfo>,
-    #[account(signer)]
-    funding_wallet_authority: AccountInfo<'info>,
-    #[account(mut)]
-    funding_wallet: Account<'info, TokenAccount>,
-    #[account(mut)]
-    fee_wallet: Account<'info, TokenAccount>,
+    /// Created by `set_mint_fee` only for the mints with their own fees.
+    #[account(
+        seeds = [
+            "mint_fee".as_ref(),
+            vault.mint.key().as_ref()
+        ],
+        bump
+    )]
+    mint_fee: AccountInfo<'info>,
+    /// Created by `set_stablecoin_fee` only if the stablecoin is accepted.
+    #[account(
+        seeds = [
+            "stablecoin_fee".as_ref()
+        ],
+        bump
+    )]
+    stablecoin_fee: AccountInfo<'info>,
     config: ProgramAccount<'info, Config>,
+    #[account(
+        constraint = country_banlist.key() == config.country_list
+    )]
+    country_banlist: Account<'info, country_list::CountryBanList>,
 
+    clock: Sysvar<'info, Clock>,
+    rent: Sysvar<'info, Rent>,
+    system_program: Program<'info, System>,
     token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct WithdrawFunds<'info> {
+pub struct Relock<'info> {
     #[account(mut)]
     locker: ProgramAccount<'info, Locker>,
     #[account(
@@ -760,172 +2367,1656 @@ pub struct WithdrawFunds<'info> {
         constraint = locker.owner == owner.key()
     )]
     owner: AccountInfo<'info>,
-    /// This authority allows the program to sign token transfer
-    /// back to target wallet.
-    vault_authority: AccountInfo<'info>,
-    #[account(
-        mut,
-        constraint = vault.owner == vault_authority.key()
-    )]
-    vault: Account<'info, TokenAccount>,
+    config: ProgramAccount<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct TransferOwnership<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
     #[account(
-        mut,
-        constraint = target_wallet.mint == vault.mint
+        signer,
+        constraint = locker.owner == owner.key()
     )]
-    target_wallet: Account<'info, TokenAccount>,
+    owner: AccountInfo<'info>,
+    new_owner: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+}
 
-    clock: Sysvar<'info, Clock>,
-    token_program: Program<'info, Token>,
+/// Owner proposed by `propose_owner`, becomes the owner once accepted.
+/// The lockers created before the two-step transfer have no room left
+/// for it, so it's kept in a PDA of the locker.
+#[account]
+#[derive(Debug)]
+pub struct PendingOwner {
+    pub bump: u8,
+    /// The proposal lapses once the locker changes the owner otherwise,
+    /// i.e. with `transfer_ownership`.
+    pub proposed_by: Pubkey,
+    pub owner: Pubkey,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct SplitLockerArgs {
-    locker_bump: u8,
-    vault_bump: u8,
-    amount: u64,
+impl PendingOwner {
+    pub const LEN: usize = 8 + 1 + 32 + 32;
 }
 
 #[derive(Accounts)]
-#[instruction(args: SplitLockerArgs)]
-pub struct SplitLocker<'info> {
-    #[account(mut)]
-    old_locker: ProgramAccount<'info, Locker>,
+pub struct ProposeOwner<'info> {
+    locker: ProgramAccount<'info, Locker>,
     #[account(
+        mut,
         signer,
-        constraint = old_locker.owner == old_owner.key()
+        constraint = locker.owner == owner.key()
     )]
-    old_owner: AccountInfo<'info>,
-    /// This authority allows the program to sign token transfer
-    /// back to target wallet.
-    old_vault_authority: AccountInfo<'info>,
+    owner: AccountInfo<'info>,
     #[account(
-        mut,
-        constraint = old_vault.owner == old_vault_authority.key()
+        init_if_needed,
+        payer = owner,
+        seeds = [
+            "pending_owner".as_ref(),
+            locker.key().as_ref()
+        ],
+        bump,
+        space = PendingOwner::LEN
     )]
-    old_vault: Account<'info, TokenAccount>,
+    pending_owner: ProgramAccount<'info, PendingOwner>,
+    new_owner: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    system_program: Program<'info, System>,
+}
 
+#[derive(Accounts)]
+pub struct AcceptOwnership<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(signer)]
+    new_owner: AccountInfo<'info>,
     #[account(
-        init,
-        payer = old_owner,
+        mut,
         seeds = [
-            old_locker.key().as_ref(),
-            old_locker.current_unlock_date.to_be_bytes().as_ref(),
-            args.amount.to_be_bytes().as_ref()
+            "pending_owner".as_ref(),
+            locker.key().as_ref()
         ],
-        bump = args.locker_bump,
-        space = Locker::LEN,
+        bump = pending_owner.bump,
+        constraint = pending_owner.owner == new_owner.key(),
+        constraint = pending_owner.proposed_by == locker.owner,
+        close = owner
     )]
-    new_locker: ProgramAccount<'info, Locker>,
-    new_owner: AccountInfo<'info>,
-    /// This authority allows the program to sign token transfer
-    /// back to target wallet.
+    pending_owner: ProgramAccount<'info, PendingOwner>,
+    /// Current owner, receives the rent of the pending owner.
+    #[account(
+        mut,
+        constraint = locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOwnershipTransfer<'info> {
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        mut,
+        signer,
+        constraint = locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
     #[account(
+        mut,
         seeds = [
-            new_locker.key().as_ref()
+            "pending_owner".as_ref(),
+            locker.key().as_ref()
         ],
-        bump = args.vault_bump
+        bump = pending_owner.bump,
+        close = owner
     )]
-    new_vault_authority: AccountInfo<'info>,
+    pending_owner: ProgramAccount<'info, PendingOwner>,
+}
+
+/// Owner of the wallets receiving the withdrawn funds, while the locker
+/// owner keeps controlling the locker. The lockers created before the
+/// beneficiary was introduced have no room left for it, so it's kept in
+/// a PDA of the locker.
+///
+/// The lockers split out of this one get the same beneficiary, otherwise
+/// the owner could move the funds past it with a split.
+#[account]
+#[derive(Debug)]
+pub struct LockerBeneficiary {
+    pub bump: u8,
+    pub beneficiary: Pubkey,
+}
+
+impl LockerBeneficiary {
+    pub const LEN: usize = 8 + 1 + 32;
+
+    /// Reads the beneficiary out of the account checked by the seeds,
+    /// `None` if `set_beneficiary` hasn't created it.
+    fn load(info: &AccountInfo) -> Result<Option<Pubkey>> {
+        if info.owner != &ID || info.lamports() == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Account::<Self>::try_from(info)?.beneficiary))
+    }
+
+    /// Creates the account of the locker split out of the one with `beneficiary`.
+    /// The readers find it by the canonical bump, so it's searched for here
+    /// rather than taken from the caller.
+    fn carry_over<'info>(
+        beneficiary: Pubkey,
+        new_locker: &Pubkey,
+        info: &AccountInfo<'info>,
+        payer: &AccountInfo<'info>,
+        rent: &Rent,
+        system_program: &Program<'info, System>,
+    ) -> Result<()> {
+        let (address, bump) =
+            Pubkey::find_program_address(&["beneficiary".as_ref(), new_locker.as_ref()], &ID);
+        require!(info.key() == address, InvalidBeneficiary);
+
+        init_program_account(
+            payer,
+            info,
+            Self::LEN,
+            &["beneficiary".as_ref(), new_locker.as_ref(), &[bump]],
+            LockerBeneficiary { bump, beneficiary },
+            rent,
+            system_program,
+        )
+    }
+
+    /// Closes the account together with the locker, if there's one.
+    fn close<'info>(info: &AccountInfo<'info>, sol_destination: AccountInfo<'info>) -> Result<()> {
+        if info.owner == &ID && info.lamports() > 0 {
+            Account::<Self>::try_from(info)?.close(sol_destination)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct SetBeneficiary<'info> {
+    locker: ProgramAccount<'info, Locker>,
     #[account(
         mut,
-        constraint = new_vault.mint == old_vault.mint
+        signer,
+        constraint = locker.owner == owner.key()
     )]
-    new_vault: Account<'info, TokenAccount>,
+    owner: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        seeds = [
+            "beneficiary".as_ref(),
+            locker.key().as_ref()
+        ],
+        bump,
+        space = LockerBeneficiary::LEN
+    )]
+    beneficiary: ProgramAccount<'info, LockerBeneficiary>,
+    config: ProgramAccount<'info, Config>,
 
-    token_program: Program<'info, Token>,
     system_program: Program<'info, System>,
 }
 
-/// For test purposes only!
 #[derive(Accounts)]
-pub struct CloseLocker<'info> {
-    #[account(mut)]
+pub struct RemoveBeneficiary<'info> {
     locker: ProgramAccount<'info, Locker>,
     #[account(
+        mut,
         signer,
         constraint = locker.owner == owner.key()
     )]
     owner: AccountInfo<'info>,
-    /// This authority allows the program to sign token transfer
-    /// back to target wallet.
-    vault_authority: AccountInfo<'info>,
     #[account(
         mut,
-        constraint = vault.owner == vault_authority.key()
+        seeds = [
+            "beneficiary".as_ref(),
+            locker.key().as_ref()
+        ],
+        bump = beneficiary.bump,
+        close = owner
     )]
-    vault: Account<'info, TokenAccount>,
+    beneficiary: ProgramAccount<'info, LockerBeneficiary>,
+    config: ProgramAccount<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct IncrementLock<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    /// Vaults created before `create_locker` initialised them itself
+    /// are supplied by the clients, so they're checked on every deposit.
     #[account(
         mut,
-        constraint = target_wallet.mint == vault.mint
+        constraint = vault.mint == funding_wallet.mint,
+        constraint = locker.vault == vault.key() @ ErrorCode::InvalidVault,
+        constraint = !vault.is_frozen() @ ErrorCode::TokenAccountFrozen,
+        constraint = vault.delegate.is_none() @ ErrorCode::TokenAccountHasDelegate,
+        constraint = vault.close_authority.is_none() @ ErrorCode::TokenAccountHasCloseAuthority
     )]
-    target_wallet: Account<'info, TokenAccount>,
-
-    token_program: Program<'info, Token>,
-}
+    vault: Account<'info, TokenAccount>,
+    #[account(
+        seeds = [
+            vault.mint.key().as_ref()
+        ],
+        bump = mint_info.bump
+    )]
+    mint_info: ProgramAccount<'info, MintInfo>,
+    /// Created by `set_mint_fee` only for the mints with their own fees.
+    #[account(
+        seeds = [
+            "mint_fee".as_ref(),
+            vault.mint.key().as_ref()
+        ],
+        bump
+    )]
+    mint_fee: AccountInfo<'info>,
+    #[account(signer)]
+    funding_wallet_authority: AccountInfo<'info>,
+    #[account(mut)]
+    funding_wallet: Account<'info, TokenAccount>,
+    #[account(mut)]
+    fee_wallet: Account<'info, TokenAccount>,
+    config: ProgramAccount<'info, Config>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFunds<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// back to target wallet.
+    #[account(
+        seeds = [
+            locker.key().as_ref()
+        ],
+        bump = locker.vault_bump
+    )]
+    vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = vault.owner == vault_authority.key() @ ErrorCode::InvalidVault,
+        constraint = locker.vault == vault.key() @ ErrorCode::InvalidVault
+    )]
+    vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = target_wallet.mint == vault.mint,
+        constraint = target_wallet.delegate.is_none() @ ErrorCode::TokenAccountHasDelegate,
+        constraint = target_wallet.close_authority.is_none()
+            @ ErrorCode::TokenAccountHasCloseAuthority
+    )]
+    target_wallet: Account<'info, TokenAccount>,
+    /// Created by `set_beneficiary`, empty while the locker has none.
+    #[account(
+        mut,
+        seeds = [
+            "beneficiary".as_ref(),
+            locker.key().as_ref()
+        ],
+        bump
+    )]
+    beneficiary: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    clock: Sysvar<'info, Clock>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseVested<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    /// Receives the rent once the locker is closed, doesn't sign.
+    #[account(
+        mut,
+        constraint = locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            locker.key().as_ref()
+        ],
+        bump = locker.vault_bump
+    )]
+    vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = vault.owner == vault_authority.key() @ ErrorCode::InvalidVault,
+        constraint = locker.vault == vault.key() @ ErrorCode::InvalidVault
+    )]
+    vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    target_wallet: Account<'info, TokenAccount>,
+    /// Created by `set_beneficiary`, empty while the locker has none.
+    #[account(
+        mut,
+        seeds = [
+            "beneficiary".as_ref(),
+            locker.key().as_ref()
+        ],
+        bump
+    )]
+    beneficiary: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    clock: Sysvar<'info, Clock>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeLocker<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = locker.revoke_authority == Some(revoke_authority.key())
+            @ ErrorCode::InvalidRevokeAuthority
+    )]
+    revoke_authority: AccountInfo<'info>,
+    /// Receives the rent once the locker is closed.
+    #[account(
+        mut,
+        constraint = locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            locker.key().as_ref()
+        ],
+        bump = locker.vault_bump
+    )]
+    vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = vault.owner == vault_authority.key() @ ErrorCode::InvalidVault,
+        constraint = locker.vault == vault.key() @ ErrorCode::InvalidVault
+    )]
+    vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = treasury_wallet.mint == vault.mint
+    )]
+    treasury_wallet: Account<'info, TokenAccount>,
+    /// Closed together with the locker, see `WithdrawFunds`.
+    #[account(
+        mut,
+        seeds = [
+            "beneficiary".as_ref(),
+            locker.key().as_ref()
+        ],
+        bump
+    )]
+    beneficiary: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    clock: Sysvar<'info, Clock>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SplitLockerArgs {
+    pub locker_bump: u8,
+    pub vault_bump: u8,
+    pub amount: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(args: SplitLockerArgs)]
+pub struct SplitLocker<'info> {
+    #[account(mut)]
+    old_locker: ProgramAccount<'info, Locker>,
+    #[account(
+        mut,
+        signer,
+        constraint = old_locker.owner == old_owner.key()
+    )]
+    old_owner: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// back to target wallet.
+    #[account(
+        seeds = [
+            old_locker.key().as_ref()
+        ],
+        bump = old_locker.vault_bump
+    )]
+    old_vault_authority: AccountInfo<'info>,
+    /// Vaults created before `create_locker` initialised them itself
+    /// are supplied by the clients, so they're checked here as well.
+    #[account(
+        mut,
+        constraint = old_vault.owner == old_vault_authority.key() @ ErrorCode::InvalidVault,
+        constraint = old_locker.vault == old_vault.key() @ ErrorCode::InvalidVault,
+        constraint = old_vault.delegate.is_none() @ ErrorCode::TokenAccountHasDelegate,
+        constraint = old_vault.close_authority.is_none()
+            @ ErrorCode::TokenAccountHasCloseAuthority
+    )]
+    old_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = old_owner,
+        seeds = [
+            old_locker.key().as_ref(),
+            old_locker.current_unlock_date.to_be_bytes().as_ref(),
+            args.amount.to_be_bytes().as_ref()
+        ],
+        bump = args.locker_bump,
+        space = Locker::LEN,
+    )]
+    new_locker: ProgramAccount<'info, Locker>,
+    new_owner: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// back to target wallet.
+    #[account(
+        seeds = [
+            new_locker.key().as_ref()
+        ],
+        bump = args.vault_bump
+    )]
+    new_vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = new_vault.mint == old_vault.mint,
+        constraint = new_vault.owner == new_vault_authority.key() @ ErrorCode::InvalidVault,
+        constraint = !new_vault.is_frozen() @ ErrorCode::TokenAccountFrozen,
+        constraint = new_vault.delegate.is_none() @ ErrorCode::TokenAccountHasDelegate,
+        constraint = new_vault.close_authority.is_none()
+            @ ErrorCode::TokenAccountHasCloseAuthority
+    )]
+    new_vault: Account<'info, TokenAccount>,
+    /// Created by `set_beneficiary`, empty while the locker has none.
+    #[account(
+        mut,
+        seeds = [
+            "beneficiary".as_ref(),
+            old_locker.key().as_ref()
+        ],
+        bump
+    )]
+    old_beneficiary: AccountInfo<'info>,
+    /// Created here when the old locker has a beneficiary.
+    #[account(mut)]
+    new_beneficiary: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    rent: Sysvar<'info, Rent>,
+    token_program: Program<'info, Token>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SplitPart {
+    pub amount: u64,
+    pub new_owner: Pubkey,
+    pub locker_bump: u8,
+    /// Bump of the vault authority of the new locker.
+    pub vault_bump: u8,
+    /// Bump of the vault of the new locker.
+    pub vault_account_bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SplitLockerBatchArgs {
+    pub parts: Vec<SplitPart>,
+}
+
+impl SplitLockerBatchArgs {
+    /// Bound by the transaction size and the compute budget,
+    /// every part takes 4 accounts and creates 2 or 3 of them.
+    pub const MAX_PARTS: usize = 4;
+}
+
+/// The new lockers, their vault authorities, vaults and beneficiaries
+/// go in the remaining accounts, see `split_locker_batch`.
+#[derive(Accounts)]
+pub struct SplitLockerBatch<'info> {
+    #[account(mut)]
+    old_locker: ProgramAccount<'info, Locker>,
+    #[account(
+        mut,
+        signer,
+        constraint = old_locker.owner == old_owner.key()
+    )]
+    old_owner: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// to the new vaults.
+    #[account(
+        seeds = [
+            old_locker.key().as_ref()
+        ],
+        bump = old_locker.vault_bump
+    )]
+    old_vault_authority: AccountInfo<'info>,
+    /// Checked the same way as in `SplitLocker`.
+    #[account(
+        mut,
+        constraint = old_vault.owner == old_vault_authority.key() @ ErrorCode::InvalidVault,
+        constraint = old_locker.vault == old_vault.key() @ ErrorCode::InvalidVault,
+        constraint = old_vault.delegate.is_none() @ ErrorCode::TokenAccountHasDelegate,
+        constraint = old_vault.close_authority.is_none()
+            @ ErrorCode::TokenAccountHasCloseAuthority
+    )]
+    old_vault: Account<'info, TokenAccount>,
+    #[account(
+        constraint = mint.key() == old_vault.mint
+    )]
+    mint: Account<'info, Mint>,
+    /// Created by `set_beneficiary`, empty while the locker has none.
+    #[account(
+        mut,
+        seeds = [
+            "beneficiary".as_ref(),
+            old_locker.key().as_ref()
+        ],
+        bump
+    )]
+    old_beneficiary: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    #[account(address = sysvar::rent::ID)]
+    rent: AccountInfo<'info>,
+    token_program: Program<'info, Token>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MergeLockers<'info> {
+    #[account(
+        mut,
+        constraint = source_locker.key() != target_locker.key() @ ErrorCode::IncompatibleLockers
+    )]
+    source_locker: ProgramAccount<'info, Locker>,
+    #[account(mut)]
+    target_locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = source_locker.owner == owner.key(),
+        constraint = target_locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// to the target vault.
+    #[account(
+        seeds = [
+            source_locker.key().as_ref()
+        ],
+        bump = source_locker.vault_bump
+    )]
+    source_vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = source_vault.owner == source_vault_authority.key() @ ErrorCode::InvalidVault,
+        constraint = source_locker.vault == source_vault.key() @ ErrorCode::InvalidVault
+    )]
+    source_vault: Account<'info, TokenAccount>,
+    /// Receives the deposit, so it's checked the same way as in `increment_lock`.
+    #[account(
+        mut,
+        constraint = target_locker.vault == target_vault.key() @ ErrorCode::InvalidVault,
+        constraint = target_vault.mint == source_vault.mint @ ErrorCode::IncompatibleLockers,
+        constraint = !target_vault.is_frozen() @ ErrorCode::TokenAccountFrozen,
+        constraint = target_vault.delegate.is_none() @ ErrorCode::TokenAccountHasDelegate,
+        constraint = target_vault.close_authority.is_none()
+            @ ErrorCode::TokenAccountHasCloseAuthority
+    )]
+    target_vault: Account<'info, TokenAccount>,
+    /// The beneficiaries should match, so the funds don't go to someone
+    /// else, see `WithdrawFunds`.
+    #[account(
+        mut,
+        seeds = [
+            "beneficiary".as_ref(),
+            source_locker.key().as_ref()
+        ],
+        bump
+    )]
+    source_beneficiary: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            "beneficiary".as_ref(),
+            target_locker.key().as_ref()
+        ],
+        bump
+    )]
+    target_beneficiary: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump
+    )]
+    config: ProgramAccount<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateLocker<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        constraint = locker.vault == vault.key() @ ErrorCode::InvalidVault
+    )]
+    vault: Account<'info, TokenAccount>,
+    config: ProgramAccount<'info, Config>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct EmergencyUnlockArgs {
+    pub reason: String,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyUnlock<'info> {
+    #[account(mut, signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        constraint = locker.vault == vault.key() @ ErrorCode::InvalidVault
+    )]
+    vault: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [
+            "emergency_unlock".as_ref(),
+            locker.key().as_ref(),
+            locker.emergency_unlocks.to_be_bytes().as_ref()
+        ],
+        bump,
+        space = EmergencyUnlockRecord::LEN,
+    )]
+    record: ProgramAccount<'info, EmergencyUnlockRecord>,
+
+    clock: Sysvar<'info, Clock>,
+    system_program: Program<'info, System>,
+}
+
+/// Audit record of the `emergency_unlock`, one per unlock.
+#[account]
+#[derive(Debug, Default)]
+pub struct EmergencyUnlockRecord {
+    pub locker: Pubkey,
+    /// Number of the earlier emergency unlocks of the locker.
+    pub index: u32,
+    pub owner: Pubkey,
+    pub admin: Pubkey,
+    pub previous_unlock_date: i64,
+    /// Vault balance at the moment of unlock.
+    pub vault_amount: u64,
+    pub unlocked_at: i64,
+    /// Support case the unlock was made for.
+    pub reason: String,
+}
+
+impl EmergencyUnlockRecord {
+    pub const MAX_REASON_LEN: usize = 256;
+    // 8 -- discriminator, 4 -- length of the reason
+    pub const LEN: usize = std::mem::size_of::<Self>() + 8 + 4 + Self::MAX_REASON_LEN;
+}
+
+/// For test purposes only!
+#[derive(Accounts)]
+pub struct CloseLocker<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// back to target wallet.
+    #[account(
+        seeds = [
+            locker.key().as_ref()
+        ],
+        bump = locker.vault_bump
+    )]
+    vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = vault.owner == vault_authority.key() @ ErrorCode::InvalidVault,
+        constraint = locker.vault == vault.key() @ ErrorCode::InvalidVault
+    )]
+    vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = target_wallet.mint == vault.mint,
+        constraint = target_wallet.delegate.is_none() @ ErrorCode::TokenAccountHasDelegate,
+        constraint = target_wallet.close_authority.is_none()
+            @ ErrorCode::TokenAccountHasCloseAuthority
+    )]
+    target_wallet: Account<'info, TokenAccount>,
+    /// Created by `set_beneficiary`, empty while the locker has none.
+    #[account(
+        mut,
+        seeds = [
+            "beneficiary".as_ref(),
+            locker.key().as_ref()
+        ],
+        bump
+    )]
+    beneficiary: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[event]
+pub struct LockerCreated {
+    pub locker: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    /// Amount locked after the fee in tokens.
+    pub amount: u64,
+    pub fee: u64,
+    pub unlock_date: i64,
+    pub start_emission: Option<i64>,
+}
+
+#[event]
+pub struct Relocked {
+    pub locker: Pubkey,
+    pub old_unlock_date: i64,
+    pub new_unlock_date: i64,
+}
+
+#[event]
+pub struct LockIncremented {
+    pub locker: Pubkey,
+    /// Amount locked after the fee in tokens.
+    pub amount: u64,
+    pub fee: u64,
+    pub deposited_amount: u64,
+}
+
+#[event]
+pub struct FundsWithdrawn {
+    pub locker: Pubkey,
+    pub owner: Pubkey,
+    pub target_wallet: Pubkey,
+    pub amount: u64,
+    pub withdrawn_amount: u64,
+    /// Whether the locker is closed since the vault is empty.
+    pub closed: bool,
+}
+
+#[event]
+pub struct LockerRevoked {
+    pub locker: Pubkey,
+    pub revoke_authority: Pubkey,
+    pub treasury_wallet: Pubkey,
+    pub amount: u64,
+    pub deposited_amount: u64,
+    /// Whether the locker is closed since the vault is empty.
+    pub closed: bool,
+}
+
+#[event]
+pub struct LockerSplit {
+    pub old_locker: Pubkey,
+    pub new_locker: Pubkey,
+    pub new_owner: Pubkey,
+    /// Amount moved from the old vault.
+    pub amount: u64,
+    /// New locker amounts, the old locker amounts are reduced by them.
+    pub deposited_amount: u64,
+    pub withdrawn_amount: u64,
+}
+
+#[event]
+pub struct LockersMerged {
+    pub source_locker: Pubkey,
+    pub target_locker: Pubkey,
+    /// Amount moved from the source vault.
+    pub amount: u64,
+    /// Target locker amounts after the merge.
+    pub deposited_amount: u64,
+    pub withdrawn_amount: u64,
+}
+
+#[event]
+pub struct OwnershipTransferred {
+    pub locker: Pubkey,
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+}
+
+/// The locker is unlocked by admin with `emergency_unlock`.
+#[event]
+pub struct EmergencyUnlocked {
+    pub locker: Pubkey,
+    pub admin: Pubkey,
+    /// Support case the unlock was made for.
+    pub reason: String,
+}
+
+#[event]
+pub struct BeneficiaryChanged {
+    pub locker: Pubkey,
+    pub old_beneficiary: Option<Pubkey>,
+    pub new_beneficiary: Option<Pubkey>,
+}
+
+/// The fee for the locker of `mint`, flat or in tokens.
+#[event]
+pub struct FeePaid {
+    pub payer: Pubkey,
+    pub mint: Pubkey,
+    /// Lamports if paid in SOL, tokens of the `currency` otherwise.
+    pub amount: u64,
+    pub currency: FeeCurrency,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum FeeCurrency {
+    Sol,
+    /// Tokens of the locked mint.
+    Token,
+    /// Tokens of the stablecoin `mint`, see `StablecoinFee`.
+    Stablecoin {
+        mint: Pubkey,
+    },
+}
+
+#[event]
+pub struct FeeDiscountsSet {
+    pub mint: Pubkey,
+    pub tiers: Vec<DiscountTier>,
+}
+
+#[event]
+pub struct AdminProposed {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct StablecoinFeeSet {
+    pub fee: Option<StablecoinFee>,
+}
+
+#[event]
+pub struct MintFeeSet {
+    pub mint: Pubkey,
+    pub fee: MintFee,
+}
+
+/// Config state after the change.
+#[event]
+pub struct ConfigUpdated {
+    pub admin: Pubkey,
+    /// In lamports, unless the config isn't migrated yet.
+    pub fee_in_sol: u64,
+    pub fee_in_token_numerator: u64,
+    pub fee_in_token_denominator: u64,
+    pub mint_info_permissioned: bool,
+    pub has_linear_emission: bool,
+    pub fee_wallet: Pubkey,
+    pub country_list: Pubkey,
+    pub deposits_paused: bool,
+    pub withdrawals_paused: bool,
+}
+
+/// floor(a * b / denominator)
+pub fn mul_div<SrcA, SrcB, SrcD>(a: SrcA, b: SrcB, denominator: SrcD) -> Option<u64>
+where
+    SrcA: fixed::traits::ToFixed,
+    SrcB: fixed::traits::ToFixed,
+    SrcD: fixed::traits::ToFixed,
+{
+    use fixed::types::U64F64;
+
+    let a = U64F64::from_num(a);
+    let b = U64F64::from_num(b);
+    let denominator = U64F64::from_num(denominator);
+
+    let max = a.max(b);
+    let min = a.min(b);
+
+    // avoiding overflow inside of the computation so intermediate
+    // values are bound by max value
+    max.checked_div(denominator)
+        .and_then(|r| r.checked_mul(min))
+        .and_then(|r| r.floor().checked_as::<u64>())
+}
+
+/// Amount vested at `now` for the linear emission with a cliff:
+///
+/// * nothing before the cliff date;
+/// * `cliff.basis_points` of the deposited amount at the cliff date;
+/// * the rest is vested linearly from `start_emission` to `unlock_date`.
+pub fn cliff_vested_amount(
+    deposited_amount: u64,
+    start_emission: i64,
+    cliff: &Cliff,
+    unlock_date: i64,
+    now: i64,
+) -> Option<u64> {
+    if now < cliff.date {
+        return Some(0);
+    }
+    if now >= unlock_date {
+        return Some(deposited_amount);
+    }
+
+    let lump = mul_div(
+        deposited_amount,
+        cliff.basis_points,
+        BASIS_POINTS_DENOMINATOR,
+    )?;
+    let linear_amount = deposited_amount.checked_sub(lump)?;
+    let linear = linear_vested_amount(linear_amount, start_emission, unlock_date, now)?;
+
+    lump.checked_add(linear)
+}
+
+/// Amount vested at `now` for the linear emission
+/// from `start_emission` to `unlock_date`.
+pub fn linear_vested_amount(
+    deposited_amount: u64,
+    start_emission: i64,
+    unlock_date: i64,
+    now: i64,
+) -> Option<u64> {
+    let full_period = unlock_date.checked_sub(start_emission)?;
+    if full_period <= 0 {
+        return None;
+    }
+
+    if now <= start_emission {
+        return Some(0);
+    }
+    if now >= unlock_date {
+        return Some(deposited_amount);
+    }
+
+    let elapsed = now.checked_sub(start_emission)?;
+
+    mul_div(deposited_amount, elapsed, full_period)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use solana_program::native_token::LAMPORTS_PER_SOL;
+
+    /// floor(a * b / denominator)
+    pub fn mul_div_old<SrcA, SrcB, SrcD>(a: SrcA, b: SrcB, denominator: SrcD) -> Option<u64>
+    where
+        SrcA: fixed::traits::ToFixed,
+        SrcB: fixed::traits::ToFixed,
+        SrcD: fixed::traits::ToFixed,
+    {
+        use fixed::types::U64F64;
+
+        let a = U64F64::from_num(a);
+        let b = U64F64::from_num(b);
+        let denominator = U64F64::from_num(denominator);
+
+        a.checked_mul(b)
+            .and_then(|r| r.checked_div(denominator))
+            .and_then(|r| r.floor().checked_as::<u64>())
+    }
+
+    // this values cause mul_div to overflow
+    // they're erroneous and now it's not possible to have such values
+    // but we sanity check overflows here just in case
+    const AMOUNT: u64 = 90000000000;
+    const ELAPSED: u64 = 1644602094;
+    const FULL_PERIOD: u64 = 1644606000;
+
+    #[test]
+    fn mul_div_do_not_overflows() {
+        let r = mul_div(AMOUNT, ELAPSED, FULL_PERIOD);
+        assert!(r.is_some());
+    }
+
+    #[test]
+    fn mul_div_impls_are_equal() {
+        let r1 = mul_div(1000, 5, 1000);
+        let r2 = mul_div_old(1000, 5, 1000);
+        assert!(r1 == r2);
+    }
+
+    const START: i64 = 1_000;
+    const UNLOCK: i64 = 9_000;
+
+    fn cliff(date: i64, basis_points: u16) -> Cliff {
+        Cliff { date, basis_points }
+    }
+
+    #[test]
+    fn cliff_nothing_vested_before_cliff_date() {
+        let cliff = cliff(3_000, 2500);
+        assert_eq!(
+            cliff_vested_amount(1024, START, &cliff, UNLOCK, START),
+            Some(0)
+        );
+        assert_eq!(
+            cliff_vested_amount(1024, START, &cliff, UNLOCK, 2_999),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn cliff_releases_lump_and_accrued_emission_at_cliff_date() {
+        // 25% at the cliff plus 25% of the rest accrued since start.
+        let cliff = cliff(3_000, 2500);
+        assert_eq!(
+            cliff_vested_amount(1024, START, &cliff, UNLOCK, 3_000),
+            Some(256 + 192)
+        );
+    }
+
+    #[test]
+    fn cliff_vests_linearly_after_cliff_date() {
+        let cliff = cliff(3_000, 2500);
+        assert_eq!(
+            cliff_vested_amount(1024, START, &cliff, UNLOCK, 5_000),
+            Some(256 + 384)
+        );
+        assert_eq!(
+            cliff_vested_amount(1024, START, &cliff, UNLOCK, 8_999),
+            Some(256 + 767)
+        );
+    }
+
+    #[test]
+    fn cliff_vests_everything_at_unlock_date() {
+        let cliff = cliff(3_000, 2500);
+        assert_eq!(
+            cliff_vested_amount(1024, START, &cliff, UNLOCK, UNLOCK),
+            Some(1024)
+        );
+        assert_eq!(
+            cliff_vested_amount(1024, START, &cliff, UNLOCK, UNLOCK + 1),
+            Some(1024)
+        );
+    }
+
+    #[test]
+    fn cliff_with_full_lump_releases_everything_at_cliff_date() {
+        let cliff = cliff(3_000, BASIS_POINTS_DENOMINATOR);
+        assert_eq!(
+            cliff_vested_amount(1024, START, &cliff, UNLOCK, 3_000),
+            Some(1024)
+        );
+    }
+
+    #[test]
+    fn cliff_do_not_overflow() {
+        let cliff = cliff(FULL_PERIOD as i64 - 10, 3333);
+        let r = cliff_vested_amount(AMOUNT, 0, &cliff, FULL_PERIOD as i64, ELAPSED as i64);
+        assert!(r.is_some());
+        assert!(r.unwrap() <= AMOUNT);
+    }
+
+    fn tranche(date: i64, basis_points: u16) -> Tranche {
+        Tranche { date, basis_points }
+    }
+
+    fn quarterly() -> Vec<Tranche> {
+        vec![
+            tranche(2_000, 2500),
+            tranche(4_000, 2500),
+            tranche(6_000, 2500),
+            tranche(8_000, 2500),
+        ]
+    }
+
+    #[test]
+    fn tranches_release_matured_parts() {
+        let schedule = TrancheSchedule::new(&quarterly(), START, UNLOCK).unwrap();
+
+        assert_eq!(schedule.vested_amount(1000, 1_999), Some(0));
+        assert_eq!(schedule.vested_amount(1000, 2_000), Some(250));
+        assert_eq!(schedule.vested_amount(1000, 5_999), Some(500));
+        assert_eq!(schedule.vested_amount(1000, 6_000), Some(750));
+        assert_eq!(schedule.vested_amount(1000, 8_000), Some(1000));
+        assert_eq!(schedule.vested_amount(1000, UNLOCK), Some(1000));
+    }
+
+    #[test]
+    fn tranches_release_everything_at_the_end() {
+        let tranches = vec![tranche(2_000, 3333), tranche(4_000, 6667)];
+        let schedule = TrancheSchedule::new(&tranches, START, UNLOCK).unwrap();
+
+        assert_eq!(schedule.vested_amount(AMOUNT, 3_000), Some(29997000000));
+        assert_eq!(schedule.vested_amount(AMOUNT, 4_000), Some(AMOUNT));
+    }
+
+    #[test]
+    fn tranches_validation() {
+        // empty schedule
+        assert!(TrancheSchedule::new(&[], START, UNLOCK).is_err());
+        // too many tranches
+        let tranches = (1..=9)
+            .map(|i| tranche(START + i, if i == 1 { 2000 } else { 1000 }))
+            .collect::<Vec<_>>();
+        assert!(TrancheSchedule::new(&tranches, START, UNLOCK).is_err());
+        // tranche in the past
+        let tranches = vec![tranche(START, 5000), tranche(4_000, 5000)];
+        assert!(TrancheSchedule::new(&tranches, START, UNLOCK).is_err());
+        // unsorted dates
+        let tranches = vec![tranche(4_000, 5000), tranche(2_000, 5000)];
+        assert!(TrancheSchedule::new(&tranches, START, UNLOCK).is_err());
+        // tranche after the unlock date
+        let tranches = vec![tranche(2_000, 5000), tranche(UNLOCK + 1, 5000)];
+        assert!(TrancheSchedule::new(&tranches, START, UNLOCK).is_err());
+        // basis points do not sum up to 100%
+        let tranches = vec![tranche(2_000, 5000), tranche(4_000, 4000)];
+        assert!(TrancheSchedule::new(&tranches, START, UNLOCK).is_err());
+        // empty tranche
+        let tranches = vec![tranche(2_000, 10000), tranche(4_000, 0)];
+        assert!(TrancheSchedule::new(&tranches, START, UNLOCK).is_err());
+    }
+
+    fn test_locker(deposited_amount: u64, unlock_date: i64) -> Locker {
+        Locker {
+            owner: Pubkey::default(),
+            country_code: *b"RU",
+            current_unlock_date: unlock_date,
+            start_emission: None,
+            last_withdraw: None,
+            deposited_amount,
+            vault: Pubkey::default(),
+            vault_bump: 0,
+            cliff: None,
+            tranches: None,
+            withdrawn_amount: 0,
+            split_count: 0,
+            revoke_authority: None,
+            emergency_unlocks: 0,
+        }
+    }
+
+    #[test]
+    fn linear_partial_withdraws_do_not_over_release() {
+        let mut locker = test_locker(1024, UNLOCK);
+        locker.start_emission = Some(START);
+
+        // 25% is vested but we withdraw only a part of it.
+        assert_eq!(locker.withdraw(3_000, 100, 1024).unwrap(), 100);
+        // Rest of the vested amount is still available.
+        assert_eq!(locker.withdraw(3_000, 1024, 924).unwrap(), 156);
+        assert!(locker.withdraw(3_000, 1024, 768).is_err());
+        // 50% is vested.
+        assert_eq!(locker.withdraw(5_000, 1024, 768).unwrap(), 256);
+        assert_eq!(locker.withdrawn_amount, 512);
+    }
+
+    #[test]
+    fn increment_is_vested_proportionally() {
+        let mut locker = test_locker(1024, UNLOCK);
+        locker.start_emission = Some(START);
+
+        assert_eq!(locker.withdraw(5_000, 1024, 1024).unwrap(), 512);
+
+        // Deposit is doubled, so a half of the new total is vested.
+        locker.deposited_amount = 2048;
+        assert_eq!(locker.withdraw(5_000, 2048, 1536).unwrap(), 512);
+    }
+
+    #[test]
+    fn legacy_lockers_are_migrated() {
+        let mut locker = test_locker(1000, UNLOCK);
+        locker.start_emission = Some(START);
+
+        // Never withdrawn -- nothing to migrate.
+        locker.migrate(1000);
+        assert_eq!(locker.withdrawn_amount, 0);
+
+        locker.last_withdraw = Some(3_000);
+        locker.migrate(700);
+        assert_eq!(locker.last_withdraw, None);
+        assert_eq!(locker.withdrawn_amount, 300);
+
+        // Migration happens only once.
+        locker.migrate(500);
+        assert_eq!(locker.withdrawn_amount, 300);
+    }
+
+    #[test]
+    fn legacy_configs_are_migrated_to_lamports() {
+        let mut config = Config {
+            admin: Pubkey::default(),
+            fee_in_sol: 2,
+            fee_in_token_numerator: 35,
+            fee_in_token_denominator: 10000,
+            mint_info_permissioned: false,
+            has_linear_emission: true,
+            fee_wallet: Pubkey::default(),
+            country_list: Pubkey::default(),
+            bump: 0,
+            deposits_paused: false,
+            withdrawals_paused: false,
+            // Configs created before the field was introduced.
+            fee_in_lamports: false,
+        };
+        assert_eq!(config.sol_fee().unwrap(), 2 * LAMPORTS_PER_SOL);
 
-/// floor(a * b / denominator)
-pub fn mul_div<SrcA, SrcB, SrcD>(a: SrcA, b: SrcB, denominator: SrcD) -> Option<u64>
-where
-    SrcA: fixed::traits::ToFixed,
-    SrcB: fixed::traits::ToFixed,
-    SrcD: fixed::traits::ToFixed,
-{
-    use fixed::types::U64F64;
+        config.migrate().unwrap();
+        assert!(config.fee_in_lamports);
+        assert_eq!(config.fee_in_sol, 2 * LAMPORTS_PER_SOL);
+        assert_eq!(config.sol_fee().unwrap(), 2 * LAMPORTS_PER_SOL);
 
-    let a = U64F64::from_num(a);
-    let b = U64F64::from_num(b);
-    let denominator = U64F64::from_num(denominator);
+        // Migration happens only once.
+        config.migrate().unwrap();
+        assert_eq!(config.fee_in_sol, 2 * LAMPORTS_PER_SOL);
 
-    let max = a.max(b);
-    let min = a.min(b);
+        // Sub-SOL fees are kept as is.
+        config.fee_in_sol = LAMPORTS_PER_SOL / 4;
+        assert_eq!(config.sol_fee().unwrap(), LAMPORTS_PER_SOL / 4);
 
-    // avoiding overflow inside of the computation so intermediate
-    // values are bound by max value
-    max.checked_div(denominator)
-        .and_then(|r| r.checked_mul(min))
-        .and_then(|r| r.floor().checked_as::<u64>())
-}
+        config.fee_in_sol = u64::MAX;
+        config.fee_in_lamports = false;
+        assert!(config.sol_fee().is_err());
+        assert!(config.migrate().is_err());
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn mint_fees_override_config_fees() {
+        let config = Config {
+            admin: Pubkey::default(),
+            fee_in_sol: LAMPORTS_PER_SOL,
+            fee_in_token_numerator: 35,
+            fee_in_token_denominator: 10000,
+            mint_info_permissioned: false,
+            has_linear_emission: true,
+            fee_wallet: Pubkey::default(),
+            country_list: Pubkey::default(),
+            bump: 0,
+            deposits_paused: false,
+            withdrawals_paused: false,
+            fee_in_lamports: true,
+        };
+        let mint_info = MintInfo::default();
+
+        // Mints without the override account pay the config fees.
+        let mint_fee = MintFee::default();
+        assert_eq!(mint_fee.fee_in_sol(&config).unwrap(), LAMPORTS_PER_SOL);
+        assert_eq!(
+            mint_fee.fee_in_token(&config),
+            TokenFee {
+                numerator: 35,
+                denominator: 10000
+            }
+        );
+        assert!(should_pay_in_tokens(&config, &mint_info, &mint_fee, false));
+
+        let mint_fee_override = MintFeeOverride {
+            bump: 254,
+            fee: MintFee {
+                fee_in_sol: Some(0),
+                fee_in_token: Some(TokenFee {
+                    numerator: 1,
+                    denominator: 100,
+                }),
+            },
+        };
+        let mut data = vec![];
+        mint_fee_override.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), MintFeeOverride::LEN);
+        let decoded = MintFeeOverride::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(decoded.fee, mint_fee_override.fee);
+        assert_eq!(decoded.fee.fee_in_sol(&config).unwrap(), 0);
+        assert!(should_pay_in_tokens(
+            &config,
+            &mint_info,
+            &decoded.fee,
+            false
+        ));
+    }
 
-    /// floor(a * b / denominator)
-    pub fn mul_div_old<SrcA, SrcB, SrcD>(a: SrcA, b: SrcB, denominator: SrcD) -> Option<u64>
-    where
-        SrcA: fixed::traits::ToFixed,
-        SrcB: fixed::traits::ToFixed,
-        SrcD: fixed::traits::ToFixed,
-    {
-        use fixed::types::U64F64;
+    #[test]
+    fn fee_discounts_apply_the_highest_tier_reached() {
+        let tiers = vec![
+            DiscountTier {
+                min_balance: 100,
+                basis_points: 1000,
+            },
+            DiscountTier {
+                min_balance: 1000,
+                basis_points: 2500,
+            },
+        ];
+        FeeDiscounts::validate_tiers(&tiers).unwrap();
 
-        let a = U64F64::from_num(a);
-        let b = U64F64::from_num(b);
-        let denominator = U64F64::from_num(denominator);
+        let fee_discounts = FeeDiscounts {
+            bump: 0,
+            mint: Pubkey::new_unique(),
+            tiers,
+        };
+        let mut data = vec![];
+        fee_discounts.try_serialize(&mut data).unwrap();
+        assert!(data.len() <= FeeDiscounts::LEN);
+
+        assert_eq!(fee_discounts.basis_points(99), 0);
+        assert_eq!(fee_discounts.basis_points(100), 1000);
+        assert_eq!(fee_discounts.basis_points(999), 1000);
+        assert_eq!(fee_discounts.basis_points(u64::MAX), 2500);
+
+        assert_eq!(
+            discounted(LAMPORTS_PER_SOL, 2500).unwrap(),
+            LAMPORTS_PER_SOL / 4 * 3
+        );
+        // The discount is rounded down.
+        assert_eq!(discounted(3, 5000).unwrap(), 2);
+        assert_eq!(discounted(3, 0).unwrap(), 3);
+        assert_eq!(discounted(3, BASIS_POINTS_DENOMINATOR).unwrap(), 0);
+
+        let unsorted = [
+            DiscountTier {
+                min_balance: 1000,
+                basis_points: 1000,
+            },
+            DiscountTier {
+                min_balance: 1000,
+                basis_points: 2500,
+            },
+        ];
+        assert!(FeeDiscounts::validate_tiers(&unsorted).is_err());
+        let too_high = [DiscountTier {
+            min_balance: 1,
+            basis_points: BASIS_POINTS_DENOMINATOR + 1,
+        }];
+        assert!(FeeDiscounts::validate_tiers(&too_high).is_err());
+        let too_many = (0..=FeeDiscounts::MAX_TIERS as u64)
+            .map(|min_balance| DiscountTier {
+                min_balance,
+                basis_points: 1,
+            })
+            .collect::<Vec<_>>();
+        assert!(FeeDiscounts::validate_tiers(&too_many).is_err());
+    }
 
-        a.checked_mul(b)
-            .and_then(|r| r.checked_div(denominator))
-            .and_then(|r| r.floor().checked_as::<u64>())
+    #[test]
+    fn split_carries_over_withdrawn_share() {
+        let mut old = test_locker(1024, UNLOCK);
+        old.start_emission = Some(START);
+        // 50% is vested and withdrawn.
+        assert_eq!(old.withdraw(5_000, 1024, 1024).unwrap(), 512);
+
+        // A half of the locked amount goes away with a half of the withdrawn one.
+        assert_eq!(old.split_off(256).unwrap(), (512, 256));
+        assert_eq!(old.deposited_amount, 512);
+        assert_eq!(old.withdrawn_amount, 256);
+
+        // Neither part can release more at the same moment.
+        let mut new = Locker {
+            deposited_amount: 512,
+            withdrawn_amount: 256,
+            ..old.clone()
+        };
+        assert!(old.withdraw(5_000, 1024, 256).is_err());
+        assert!(new.withdraw(5_000, 1024, 256).is_err());
+        // Each part releases a half of the original locker releases.
+        assert_eq!(old.withdraw(7_000, 1024, 256).unwrap(), 128);
+        assert_eq!(new.withdraw(7_000, 1024, 256).unwrap(), 128);
+
+        assert!(old.split_off(0).is_err());
+        assert!(old.split_off(257).is_err());
     }
 
-    // this values cause mul_div to overflow
-    // they're erroneous and now it's not possible to have such values
-    // but we sanity check overflows here just in case
-    const AMOUNT: u64 = 90000000000;
-    const ELAPSED: u64 = 1644602094;
-    const FULL_PERIOD: u64 = 1644606000;
+    #[test]
+    fn revoke_leaves_vested_amount_to_owner() {
+        let revocable = || {
+            let mut locker = test_locker(1024, UNLOCK);
+            locker.start_emission = Some(START);
+            locker.cliff = Some(Cliff {
+                date: 3_000,
+                basis_points: 0,
+            });
+            locker.revoke_authority = Some(Pubkey::new_unique());
+            locker
+        };
+
+        // Before the cliff, at a quarter, a half and right before the unlock.
+        for &(now, vested) in &[(2_000, 0), (3_000, 256), (5_000, 512), (8_999, 1023)] {
+            let mut locker = revocable();
+            assert_eq!(locker.revoke(now).unwrap(), 1024 - vested);
+            assert_eq!(locker.deposited_amount, vested);
+            assert_eq!(locker.revoke_authority, None);
+            assert!(!locker.has_schedule());
+
+            // The vested amount is available at once and nothing more vests later.
+            if vested > 0 {
+                assert_eq!(locker.withdraw(now, u64::MAX, vested).unwrap(), vested);
+            }
+            assert!(locker.withdraw(UNLOCK + 1, u64::MAX, 0).is_err());
+            assert!(locker.revoke(now).is_err());
+        }
+
+        // The withdrawn part is not clawed back.
+        let mut locker = revocable();
+        assert_eq!(locker.withdraw(3_000, 100, 1024).unwrap(), 100);
+        assert_eq!(locker.revoke(3_000).unwrap(), 768);
+        assert_eq!(locker.withdraw(3_000, u64::MAX, 156).unwrap(), 156);
+
+        // Nothing is left to revoke after the unlock.
+        assert!(revocable().revoke(UNLOCK + 1).is_err());
+    }
 
     #[test]
-    fn mul_div_do_not_overflows() {
-        let r = mul_div(AMOUNT, ELAPSED, FULL_PERIOD);
-        assert!(r.is_some());
+    fn lockers_with_same_terms_are_merged() {
+        let mut target = test_locker(1000, UNLOCK);
+        target.tranches = Some(TrancheSchedule::new(&quarterly(), START, UNLOCK).unwrap());
+        let mut source = test_locker(500, UNLOCK);
+        source.tranches = Some(TrancheSchedule::new(&quarterly(), START, UNLOCK).unwrap());
+        assert!(target.can_merge(&source));
+
+        source.country_code = *b"UA";
+        assert!(!target.can_merge(&source));
+        source.country_code = target.country_code;
+
+        source.revoke_authority = Some(Pubkey::new_unique());
+        assert!(!target.can_merge(&source));
+        source.revoke_authority = None;
+
+        let mut tranches = quarterly();
+        tranches[0].date += 1;
+        source.tranches = Some(TrancheSchedule::new(&tranches, START, UNLOCK).unwrap());
+        assert!(!target.can_merge(&source));
     }
 
     #[test]
-    fn mul_div_impls_are_equal() {
-        let r1 = mul_div(1000, 5, 1000);
-        let r2 = mul_div_old(1000, 5, 1000);
-        assert!(r1 == r2);
+    fn lockers_are_merged_into_later_unlock_only() {
+        let target = test_locker(1000, UNLOCK);
+        let mut source = test_locker(500, UNLOCK - 1);
+        source.start_emission = Some(START);
+        assert!(target.can_merge(&source));
+        // The merge would release the source funds earlier.
+        assert!(!source.can_merge(&target));
+
+        let source = test_locker(500, UNLOCK + 1);
+        assert!(!target.can_merge(&source));
+
+        // Linear emission releases the merged funds before the source unlock date.
+        let mut target = test_locker(1000, UNLOCK + 1);
+        target.start_emission = Some(START);
+        assert!(!target.can_merge(&test_locker(500, UNLOCK)));
+    }
+
+    prop_compose! {
+        fn any_locker()(
+            deposited_amount in 1..1_000_000_000_000_000u64,
+            period in 1..10_000_000i64,
+            kind in 0..4u8,
+            cliff_offset in 0..10_000_000i64,
+            cliff_basis_points in 0..=BASIS_POINTS_DENOMINATOR,
+            tranche_offsets in prop::collection::btree_set(
+                0..10_000_000i64,
+                1..=TrancheSchedule::MAX_TRANCHES
+            ),
+            tranche_basis_points in prop::collection::vec(
+                1..=BASIS_POINTS_DENOMINATOR / TrancheSchedule::MAX_TRANCHES as u16,
+                TrancheSchedule::MAX_TRANCHES
+            ),
+        ) -> Locker {
+            let unlock_date = START + period;
+            let mut locker = test_locker(deposited_amount, unlock_date);
+
+            match kind {
+                // hard lock till the unlock date
+                0 => {}
+                // linear emission
+                1 => locker.start_emission = Some(START),
+                // linear emission with cliff
+                2 => {
+                    locker.start_emission = Some(START);
+                    locker.cliff = Some(Cliff {
+                        date: START + cliff_offset % period,
+                        basis_points: cliff_basis_points,
+                    });
+                }
+                // tranches
+                _ => {
+                    let dates = tranche_offsets
+                        .iter()
+                        .map(|offset| START + 1 + offset % period)
+                        .collect::<std::collections::BTreeSet<_>>();
+                    let mut tranches = dates
+                        .into_iter()
+                        .zip(tranche_basis_points)
+                        .map(|(date, basis_points)| Tranche { date, basis_points })
+                        .collect::<Vec<_>>();
+                    let total: u16 = tranches.iter().map(|t| t.basis_points).sum();
+                    tranches.last_mut().unwrap().basis_points +=
+                        BASIS_POINTS_DENOMINATOR - total;
+
+                    locker.tranches =
+                        Some(TrancheSchedule::new(&tranches, START, unlock_date).unwrap());
+                }
+            }
+
+            locker
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn withdrawals_never_exceed_deposits(
+            mut locker in any_locker(),
+            withdraws in prop::collection::vec((0..2_000_000i64, 1..u64::MAX), 1..32),
+        ) {
+            let deposited_amount = locker.deposited_amount;
+            let mut vault_amount = deposited_amount;
+            let mut now = START;
+
+            for (elapsed, amount) in withdraws {
+                now += elapsed;
+
+                let withdrawn = locker.withdraw(now, amount, vault_amount).unwrap_or(0);
+                vault_amount -= withdrawn;
+
+                prop_assert!(locker.withdrawn_amount <= deposited_amount);
+                prop_assert!(locker.withdrawn_amount <= locker.vested_amount(now).unwrap());
+            }
+
+            // Everything left is available after the unlock date.
+            let unlocked = locker.current_unlock_date + 1;
+            let rest = locker.withdraw(unlocked, u64::MAX, vault_amount).unwrap_or(0);
+            prop_assert_eq!(locker.withdrawn_amount, deposited_amount);
+            prop_assert_eq!(rest, vault_amount);
+        }
+
+        #[test]
+        fn split_lockers_release_as_the_original(
+            mut original in any_locker(),
+            split_at in 0..100i64,
+            split_share in 1..=100u64,
+            times in prop::collection::btree_set(0..20_000_000i64, 1..16),
+        ) {
+            let deposited_amount = original.deposited_amount;
+            // Somewhere before the unlock date, so there's something to split.
+            let split_at = START + (original.current_unlock_date - START) * split_at / 100;
+            let mut vault_amount = deposited_amount;
+            vault_amount -= original.withdraw(split_at, u64::MAX, vault_amount).unwrap_or(0);
+
+            let amount = mul_div(vault_amount, split_share, 100).unwrap();
+            prop_assume!(amount > 0);
+            let mut old = original.clone();
+            let (new_deposited, new_withdrawn) = old.split_off(amount).unwrap();
+            let mut new = Locker {
+                deposited_amount: new_deposited,
+                withdrawn_amount: new_withdrawn,
+                ..old.clone()
+            };
+            prop_assert_eq!(old.deposited_amount + new.deposited_amount, deposited_amount);
+            prop_assert_eq!(old.withdrawn_amount + new.withdrawn_amount, original.withdrawn_amount);
+
+            let (mut old_vault, mut new_vault) = (vault_amount - amount, amount);
+            let mut released = 0;
+            let mut split_released = 0;
+            let unlocked = original.current_unlock_date + 1;
+            let times = times.into_iter().map(|time| split_at + time).chain(Some(unlocked));
+
+            for now in times {
+                let withdrawn = original.withdraw(now, u64::MAX, vault_amount).unwrap_or(0);
+                vault_amount -= withdrawn;
+                released += withdrawn;
+
+                for (locker, vault) in [(&mut old, &mut old_vault), (&mut new, &mut new_vault)] {
+                    let withdrawn = locker.withdraw(now, u64::MAX, *vault).unwrap_or(0);
+                    *vault -= withdrawn;
+                    split_released += withdrawn;
+                }
+
+                // Two parts round the vested amounts down separately.
+                prop_assert!(split_released <= released + 1);
+                prop_assert!(split_released + 2 >= released);
+            }
+
+            // Everything is released after the unlock date.
+            prop_assert_eq!(split_released, released);
+            prop_assert_eq!(old_vault + new_vault, 0);
+        }
+
+        #[test]
+        fn vested_amount_is_monotonic(
+            locker in any_locker(),
+            first in 0..20_000_000i64,
+            second in 0..20_000_000i64,
+        ) {
+            let (earlier, later) = (first.min(second), first.max(second));
+
+            let vested_earlier = locker.vested_amount(START + earlier).unwrap();
+            let vested_later = locker.vested_amount(START + later).unwrap();
+
+            prop_assert!(vested_earlier <= vested_later);
+            prop_assert!(vested_later <= locker.deposited_amount);
+        }
     }
 }
 
@@ -944,7 +4035,17 @@ fn should_pay_in_sol(config: &Config, mint_info: &MintInfo, fee_in_sol: bool) ->
     }
 }
 
-fn should_pay_in_tokens(config: &Config, mint_info: &MintInfo, fee_in_sol: bool) -> bool {
+fn should_pay_in_tokens(
+    config: &Config,
+    mint_info: &MintInfo,
+    mint_fee: &MintFee,
+    fee_in_sol: bool,
+) -> bool {
+    // The fee is waived for the mint.
+    if mint_fee.fee_in_token(config).numerator == 0 {
+        return false;
+    }
+
     match (
         config.mint_info_permissioned,
         fee_in_sol,
@@ -959,11 +4060,266 @@ fn should_pay_in_tokens(config: &Config, mint_info: &MintInfo, fee_in_sol: bool)
     }
 }
 
+/// Shared state of `create_locker` and `create_locker_with_pda`.
+struct NewLocker<'pay, 'info> {
+    locker: &'pay mut ProgramAccount<'info, Locker>,
+    owner: &'pay AccountInfo<'info>,
+    funding_wallet_authority: &'pay AccountInfo<'info>,
+    funding_wallet: &'pay mut Account<'info, TokenAccount>,
+    vault: &'pay Account<'info, TokenAccount>,
+    fee_wallet: &'pay AccountInfo<'info>,
+    fee_token_wallet: &'pay Account<'info, TokenAccount>,
+    mint_info: &'pay mut ProgramAccount<'info, MintInfo>,
+    mint_fee: MintFee,
+    stablecoin_fee: Option<StablecoinFee>,
+    config: &'pay ProgramAccount<'info, Config>,
+    country_banlist: &'pay Account<'info, country_list::CountryBanList>,
+    clock: &'pay Sysvar<'info, Clock>,
+    system_program: &'pay Program<'info, System>,
+    token_program: &'pay Program<'info, Token>,
+    remaining_accounts: &'pay [AccountInfo<'info>],
+}
+
+impl NewLocker<'_, '_> {
+    fn create(self, args: CreateLockerArgs) -> Result<()> {
+        let now = self.clock.unix_timestamp;
+        require!(args.unlock_date > now, UnlockInThePast);
+        // Prevents errors when timestamp entered as milliseconds.
+        require!(args.unlock_date < 10000000000, InvalidTimestamp);
+
+        let config = self.config;
+        config.require_deposits_allowed()?;
+
+        // Checking here that args has no linear emission if it's disabled
+        // for the given locker type.
+        if !config.has_linear_emission {
+            require!(args.start_emission.is_none(), LinearEmissionDisabled);
+            require!(args.tranches.is_none(), LinearEmissionDisabled);
+        }
+
+        if let Some(start_emission) = args.start_emission {
+            //  now     start_emission     unlock_date
+            // |--------------------------------------> time, seconds
+            require!(args.unlock_date > start_emission, InvalidPeriod);
+            require!(start_emission >= now, InvalidPeriod);
+        }
+
+        if let Some(cliff) = &args.cliff {
+            // Cliff makes sense only as a part of linear emission.
+            let start_emission = args
+                .start_emission
+                .ok_or(ErrorCode::LinearEmissionDisabled)?;
+
+            //  start_emission     cliff date     unlock_date
+            // |--------------------------------------> time, seconds
+            require!(cliff.date >= start_emission, InvalidPeriod);
+            require!(args.unlock_date > cliff.date, InvalidPeriod);
+            require!(
+                cliff.basis_points <= BASIS_POINTS_DENOMINATOR,
+                InvalidBasisPoints
+            );
+        }
+
+        let tranches = match &args.tranches {
+            Some(tranches) => {
+                // Tranches replace the linear emission, so the locker
+                // can't have both schedules.
+                require!(args.start_emission.is_none(), InvalidSchedule);
+                require!(args.cliff.is_none(), InvalidSchedule);
+
+                Some(TrancheSchedule::new(tranches, now, args.unlock_date)?)
+            }
+            None => None,
+        };
+
+        // Checking here that country is not banned in country list
+        // we've chosen in locker type config.
+        require!(
+            self.country_banlist.is_country_valid(&args.country_code),
+            InvalidCountry
+        );
+
+        sol_log("Create locker: checks passed");
+
+        require!(!(args.fee_in_sol && args.fee_in_stablecoin), InvalidFeeMode);
+        let stablecoin_fee = if args.fee_in_stablecoin {
+            Some(
+                self.stablecoin_fee
+                    .ok_or(ErrorCode::StablecoinFeeDisabled)?,
+            )
+        } else {
+            None
+        };
+
+        // The stablecoin wallets go first, the fee discount accounts follow.
+        let (stablecoin_accounts, discount_accounts) = if args.fee_in_stablecoin {
+            require!(
+                self.remaining_accounts.len() >= 2,
+                InvalidStablecoinFeeAccounts
+            );
+            self.remaining_accounts.split_at(2)
+        } else {
+            self.remaining_accounts.split_at(0)
+        };
+        let discount = holder_discount(discount_accounts, self.funding_wallet_authority)?;
+
+        // The stablecoin replaces SOL, so the flat fee is charged the same
+        // way whichever is chosen, but each has its own amount. The waived
+        // flat fee charges nothing, the fee in tokens isn't charged instead.
+        let flat_fee_amount = match stablecoin_fee {
+            Some(fee) => fee.amount,
+            None => self.mint_fee.fee_in_sol(config)?,
+        };
+        let flat_fee = args.fee_in_sol || args.fee_in_stablecoin;
+
+        let mint_info = &mut *self.mint_info;
+
+        // Check if we should charge the flat fee.
+        if flat_fee_amount > 0 && should_pay_in_sol(config, mint_info, flat_fee) {
+            if let Some(fee) = stablecoin_fee {
+                FeeInStablecoin {
+                    config,
+                    fee,
+                    mint_info,
+                    mint: self.vault.mint,
+                    payer: self.funding_wallet_authority,
+                    payer_wallet: &stablecoin_accounts[0],
+                    fee_wallet: &stablecoin_accounts[1],
+                    discount,
+                    token_program: self.token_program,
+                }
+                .pay()?;
+            } else {
+                FeeInSol {
+                    fee_wallet: self.fee_wallet,
+                    payer: self.funding_wallet_authority,
+                    mint: self.vault.mint,
+                    config,
+                    mint_info,
+                    mint_fee: &self.mint_fee,
+                    discount,
+                    system_program: self.system_program,
+                }
+                .pay()?;
+            }
+        }
+
+        sol_log("Create locker: after flat fee");
+
+        // Check if we should charge the fee in locked tokens.
+        let lock_fee = if should_pay_in_tokens(config, mint_info, &self.mint_fee, flat_fee) {
+            FeeInTokens {
+                config,
+                mint_fee: &self.mint_fee,
+                funding_wallet: &mut *self.funding_wallet,
+                funding_wallet_authority: self.funding_wallet_authority,
+                fee_wallet: self.fee_token_wallet,
+                amount: args.amount,
+                discount,
+                token_program: self.token_program,
+            }
+            .pay()?
+        } else {
+            0
+        };
+
+        sol_log("Create locker: after token fee");
+
+        let amount_to_lock = args
+            .amount
+            .checked_sub(lock_fee)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+        require!(amount_to_lock > 0, NothingToLock);
+
+        let locker = self.locker.deref_mut();
+
+        *locker = Locker {
+            owner: self.owner.key(),
+            country_code: country_list::string_to_byte_array(&args.country_code),
+            current_unlock_date: args.unlock_date,
+            start_emission: args.start_emission,
+            last_withdraw: None,
+            deposited_amount: amount_to_lock,
+            vault: self.vault.key(),
+            vault_bump: args.vault_bump,
+            cliff: args.cliff,
+            tranches,
+            withdrawn_amount: 0,
+            split_count: 0,
+            revoke_authority: args.revoke_authority,
+            emergency_unlocks: 0,
+        };
+
+        TokenTransfer {
+            amount: amount_to_lock,
+            from: self.funding_wallet,
+            to: self.vault,
+            authority: self.funding_wallet_authority,
+            token_program: self.token_program,
+            signers: None,
+        }
+        .make()?;
+
+        emit!(LockerCreated {
+            locker: self.locker.key(),
+            owner: self.owner.key(),
+            mint: self.vault.mint,
+            vault: self.vault.key(),
+            amount: amount_to_lock,
+            fee: lock_fee,
+            unlock_date: args.unlock_date,
+            start_emission: args.start_emission,
+        });
+
+        Ok(())
+    }
+}
+
+/// Discount in basis points for the holder of the platform token. The accounts
+/// are `FeeDiscounts` and the holder wallet owned by `holder`, no accounts
+/// mean no discount is claimed.
+///
+/// Only the current balance of the wallet is checked, nothing is locked or
+/// recorded, so the same balance gives the discount to every locker created
+/// or incremented with it, including several in one transaction.
+fn holder_discount(accounts: &[AccountInfo], holder: &AccountInfo) -> Result<u16> {
+    let (fee_discounts, holder_wallet) = match accounts {
+        [] => return Ok(0),
+        [fee_discounts, holder_wallet] => (fee_discounts, holder_wallet),
+        _ => return Err(ErrorCode::InvalidDiscountAccounts.into()),
+    };
+
+    // Only admin creates the account of this type, at the fixed PDA.
+    let fee_discounts = ProgramAccount::<FeeDiscounts>::try_from(&crate::ID, fee_discounts)?;
+    let holder_wallet = Account::<TokenAccount>::try_from(holder_wallet)?;
+
+    require!(
+        holder_wallet.owner == holder.key() && holder_wallet.mint == fee_discounts.mint,
+        InvalidDiscountWallet
+    );
+
+    Ok(fee_discounts.basis_points(holder_wallet.amount))
+}
+
+/// The fee less the discount in basis points, rounded in favour of the fee.
+fn discounted(fee: u64, discount: u16) -> Result<u64> {
+    let discount =
+        mul_div(fee, discount, BASIS_POINTS_DENOMINATOR).ok_or(ErrorCode::IntegerOverflow)?;
+
+    Ok(fee
+        .checked_sub(discount)
+        .ok_or(ErrorCode::IntegerOverflow)?)
+}
+
 struct FeeInSol<'pay, 'info> {
     fee_wallet: &'pay AccountInfo<'info>,
     payer: &'pay AccountInfo<'info>,
+    mint: Pubkey,
     config: &'pay Config,
     mint_info: &'pay mut MintInfo,
+    mint_fee: &'pay MintFee,
+    /// In basis points, see `holder_discount`.
+    discount: u16,
     system_program: &'pay Program<'info, System>,
 }
 
@@ -977,11 +4333,13 @@ impl FeeInSol<'_, '_> {
         self.payer.key().log();
         self.fee_wallet.key().log();
 
+        let fee = discounted(self.mint_fee.fee_in_sol(self.config)?, self.discount)?;
+
         solana_program::program::invoke(
             &solana_program::system_instruction::transfer(
                 self.payer.to_account_info().key,
                 self.fee_wallet.key,
-                self.config.fee_in_sol * solana_program::native_token::LAMPORTS_PER_SOL,
+                fee,
             ),
             &[
                 self.payer.to_account_info(),
@@ -995,16 +4353,26 @@ impl FeeInSol<'_, '_> {
             self.mint_info.fee_paid = true;
         }
 
+        emit!(FeePaid {
+            payer: self.payer.key(),
+            mint: self.mint,
+            amount: fee,
+            currency: FeeCurrency::Sol,
+        });
+
         Ok(())
     }
 }
 
 struct FeeInTokens<'pay, 'info> {
     config: &'pay Config,
+    mint_fee: &'pay MintFee,
     funding_wallet: &'pay mut Account<'info, TokenAccount>,
     funding_wallet_authority: &'pay AccountInfo<'info>,
     fee_wallet: &'pay Account<'info, TokenAccount>,
     amount: u64,
+    /// In basis points, see `holder_discount`.
+    discount: u16,
     token_program: &'pay Program<'info, Token>,
 }
 
@@ -1018,12 +4386,14 @@ impl FeeInTokens<'_, '_> {
             InvalidFeeWallet
         );
 
+        let fee_in_token = self.mint_fee.fee_in_token(self.config);
         let lock_fee = mul_div(
             self.amount,
-            self.config.fee_in_token_numerator,
-            self.config.fee_in_token_denominator,
+            fee_in_token.numerator,
+            fee_in_token.denominator,
         )
         .ok_or(ErrorCode::IntegerOverflow)?;
+        let lock_fee = discounted(lock_fee, self.discount)?;
 
         TokenTransfer {
             amount: lock_fee,
@@ -1035,12 +4405,166 @@ impl FeeInTokens<'_, '_> {
         }
         .make()?;
 
-        sol_log_64(self.amount, lock_fee, self.amount - lock_fee, 0, 0);
+        sol_log_64(
+            self.amount,
+            lock_fee,
+            self.amount.saturating_sub(lock_fee),
+            0,
+            0,
+        );
+
+        emit!(FeePaid {
+            payer: self.funding_wallet_authority.key(),
+            mint: self.funding_wallet.mint,
+            amount: lock_fee,
+            currency: FeeCurrency::Token,
+        });
 
         Ok(lock_fee)
     }
 }
 
+/// Flat fee in the stablecoin set by `set_stablecoin_fee` paid by the
+/// funding wallet authority. The wallets are the first two of the remaining
+/// accounts, the stablecoin wallet of the payer and the one associated
+/// with the fee wallet, in this order.
+struct FeeInStablecoin<'pay, 'info> {
+    config: &'pay Config,
+    fee: StablecoinFee,
+    mint_info: &'pay mut MintInfo,
+    mint: Pubkey,
+    payer: &'pay AccountInfo<'info>,
+    payer_wallet: &'pay AccountInfo<'info>,
+    fee_wallet: &'pay AccountInfo<'info>,
+    /// In basis points, see `holder_discount`.
+    discount: u16,
+    token_program: &'pay Program<'info, Token>,
+}
+
+impl FeeInStablecoin<'_, '_> {
+    fn pay(self) -> Result<()> {
+        let fee = self.fee;
+        let amount = discounted(fee.amount, self.discount)?;
+
+        let mut payer_wallet = Account::<TokenAccount>::try_from(self.payer_wallet)?;
+        let fee_wallet = Account::<TokenAccount>::try_from(self.fee_wallet)?;
+
+        let associated_token_account =
+            get_associated_token_address(&self.config.fee_wallet, &fee.mint);
+
+        require!(
+            associated_token_account == fee_wallet.key(),
+            InvalidFeeWallet
+        );
+
+        TokenTransfer {
+            amount,
+            from: &mut payer_wallet,
+            to: &fee_wallet,
+            authority: self.payer,
+            token_program: self.token_program,
+            signers: None,
+        }
+        .make()?;
+
+        // if not permissioned we allow one-time fees
+        if !self.config.mint_info_permissioned {
+            self.mint_info.fee_paid = true;
+        }
+
+        emit!(FeePaid {
+            payer: self.payer.key(),
+            mint: self.mint,
+            amount,
+            currency: FeeCurrency::Stablecoin { mint: fee.mint },
+        });
+
+        Ok(())
+    }
+}
+
+/// Creates the program account at the PDA and stores `data` in it the
+/// same way `init` does, for the accounts that can't be listed in
+/// `Accounts`, like the new lockers of `split_locker_batch`.
+fn init_program_account<'info, T: AccountSerialize + AccountDeserialize + Owner + Clone>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    space: usize,
+    seeds: &[&[u8]],
+    data: T,
+    rent: &Rent,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    create_pda_account(
+        payer,
+        account,
+        space,
+        &T::owner(),
+        seeds,
+        rent,
+        system_program,
+    )?;
+
+    let mut program_account = Account::<T>::try_from_unchecked(account)?;
+    *program_account = data;
+    program_account.exit(&T::owner())?;
+
+    Ok(())
+}
+
+/// Creates the PDA account the same way `init` does, so the account
+/// funded in advance doesn't block the creation.
+fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    space: usize,
+    owner: &Pubkey,
+    seeds: &[&[u8]],
+    rent: &Rent,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let current_lamports = account.lamports();
+    let system_program = system_program.to_account_info();
+
+    if current_lamports == 0 {
+        solana_program::program::invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                account.key,
+                rent.minimum_balance(space),
+                space as u64,
+                owner,
+            ),
+            &[payer.clone(), account.clone(), system_program],
+            &[seeds],
+        )?;
+        return Ok(());
+    }
+
+    let required_lamports = rent
+        .minimum_balance(space)
+        .max(1)
+        .saturating_sub(current_lamports);
+    if required_lamports > 0 {
+        solana_program::program::invoke(
+            &system_instruction::transfer(payer.key, account.key, required_lamports),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    solana_program::program::invoke_signed(
+        &system_instruction::allocate(account.key, space as u64),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )?;
+    solana_program::program::invoke_signed(
+        &system_instruction::assign(account.key, owner),
+        &[account.clone(), system_program],
+        &[seeds],
+    )?;
+
+    Ok(())
+}
+
 struct TokenTransfer<'pay, 'info> {
     amount: u64,
     from: &'pay mut Account<'info, TokenAccount>,
@@ -0,0 +1,662 @@
+//! Shared setup for the integration tests.
+//!
+//! Every test starts a fresh bank with the locker and country list programs,
+//! the country list (`RU` allowed, `US` banned), the config, a mint
+//! with mint info and a funded token wallet of the payer.
+
+#![allow(dead_code)]
+
+use anchor_lang::{AccountDeserialize, AccountSerialize, InstructionData, ToAccountMetas};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{from_account, Account},
+    clock::Clock,
+    instruction::{Instruction, InstructionError},
+    native_token::LAMPORTS_PER_SOL,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, system_program, sysvar,
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+
+use locker::{
+    Config, CreateConfigArgs, CreateLockerArgs, CreatorInfo, Locker, LockerBeneficiary, MintFee,
+    MintFeeOverride, MintInfo, PendingAdmin, PendingOwner, StablecoinFee, StablecoinFeeConfig,
+};
+use locker_client::{instruction, pda};
+
+pub const DECIMALS: u8 = 6;
+pub const INITIAL_BALANCE: u64 = 1_000_000_000;
+
+pub const FEE_IN_SOL: u64 = LAMPORTS_PER_SOL;
+pub const FEE_NUMERATOR: u64 = 35;
+pub const FEE_DENOMINATOR: u64 = 10000;
+
+pub const DAY: i64 = 24 * 60 * 60;
+
+/// Sizes of the accounts created before the fields appended to them.
+pub const LEGACY_CONFIG_LEN: usize = 136;
+pub const LEGACY_LOCKER_LEN: usize = 128;
+pub const LEGACY_MINT_INFO_LEN: usize = 10;
+
+/// Mirrors `ConfigPreset` of the admin CLI.
+#[derive(Clone, Copy)]
+pub enum Preset {
+    TokenLocker,
+    LPLocker,
+}
+
+impl Preset {
+    fn config_args(self) -> CreateConfigArgs {
+        let (mint_info_permissioned, has_linear_emission) = match self {
+            Preset::TokenLocker => (false, true),
+            Preset::LPLocker => (true, false),
+        };
+
+        CreateConfigArgs {
+            fee_in_sol: FEE_IN_SOL,
+            fee_in_token_numerator: FEE_NUMERATOR,
+            fee_in_token_denominator: FEE_DENOMINATOR,
+            mint_info_permissioned,
+            has_linear_emission,
+            // Filled by the instruction builder.
+            bump: 0,
+        }
+    }
+}
+
+pub struct TestEnv {
+    pub context: ProgramTestContext,
+    pub country_list: Pubkey,
+    pub fee_wallet: Pubkey,
+    pub mint: Pubkey,
+    pub mint_authority: Keypair,
+    /// Token wallet of the payer holding `INITIAL_BALANCE` tokens.
+    pub funding_wallet: Pubkey,
+}
+
+/// Created locker together with its vault.
+pub struct TestLocker {
+    pub address: Pubkey,
+    pub vault: Pubkey,
+}
+
+pub fn program_test() -> ProgramTest {
+    let mut program_test = ProgramTest::new("locker", locker::id(), processor!(locker::entry));
+    program_test.add_program(
+        "country_list",
+        country_list::id(),
+        processor!(country_list::entry),
+    );
+
+    program_test
+}
+
+/// Account of the locker program holding `data` in `len` bytes,
+/// panics if it doesn't fit there.
+pub fn legacy_account<T: AccountSerialize>(data: &T, len: usize) -> Account {
+    let mut account = Account::new(LAMPORTS_PER_SOL, len, &locker::id());
+    data.try_serialize(&mut account.data.as_mut_slice())
+        .unwrap();
+
+    account
+}
+
+/// Locker with the fields appended to it left empty, so it fits
+/// in `LEGACY_LOCKER_LEN`.
+pub fn legacy_locker(owner: &Pubkey, unlock_date: i64) -> Locker {
+    Locker {
+        owner: *owner,
+        country_code: *b"RU",
+        current_unlock_date: unlock_date,
+        start_emission: None,
+        last_withdraw: None,
+        deposited_amount: INITIAL_BALANCE,
+        vault: Pubkey::new_unique(),
+        vault_bump: 0,
+        cliff: None,
+        tranches: None,
+        withdrawn_amount: 0,
+        split_count: 0,
+        revoke_authority: None,
+        emergency_unlocks: 0,
+    }
+}
+
+impl TestEnv {
+    pub async fn new(preset: Preset) -> Self {
+        Self::with_accounts(preset, vec![]).await
+    }
+
+    /// Same as `new` with `accounts` added to the bank beforehand, e.g.
+    /// the ones from `legacy_account`. The config is kept if it's there.
+    pub async fn with_accounts(preset: Preset, accounts: Vec<(Pubkey, Account)>) -> Self {
+        let mut program_test = program_test();
+        for (address, account) in accounts {
+            program_test.add_account(address, account);
+        }
+        let context = program_test.start_with_context().await;
+
+        let mut env = Self {
+            context,
+            country_list: Pubkey::default(),
+            fee_wallet: Keypair::new().pubkey(),
+            mint: Pubkey::default(),
+            mint_authority: Keypair::new(),
+            funding_wallet: Pubkey::default(),
+        };
+
+        env.country_list = env.init_country_list().await;
+
+        let admin = env.payer();
+        let (config, _bump) = pda::find_config_address(&locker::id());
+        if env.get_account(&config).await.is_none() {
+            env.process(
+                &[instruction::init_config(
+                    &locker::id(),
+                    &admin,
+                    &env.fee_wallet,
+                    &env.country_list,
+                    preset.config_args(),
+                )],
+                &[],
+            )
+            .await
+            .unwrap();
+        }
+
+        env.mint = env.create_mint().await;
+        env.funding_wallet = env.create_token_wallet(&admin).await;
+        let funding_wallet = env.funding_wallet;
+        env.mint_to(&funding_wallet, INITIAL_BALANCE).await;
+        env.create_fee_token_wallet().await;
+
+        let mint = env.mint;
+        env.process(
+            &[instruction::init_mint_info(&locker::id(), &admin, &mint)],
+            &[],
+        )
+        .await
+        .unwrap();
+
+        env
+    }
+
+    pub fn payer(&self) -> Pubkey {
+        self.context.payer.pubkey()
+    }
+
+    /// Signs the transaction by the payer and `signers`.
+    pub async fn process(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), TransportError> {
+        let recent_blockhash = self
+            .context
+            .banks_client
+            .get_recent_blockhash()
+            .await
+            .unwrap();
+
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            recent_blockhash,
+        );
+
+        self.context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+    }
+
+    pub async fn get_account(&mut self, address: &Pubkey) -> Option<Account> {
+        self.context
+            .banks_client
+            .get_account(*address)
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_anchor_account<T: AccountDeserialize>(&mut self, address: &Pubkey) -> T {
+        let account = self.get_account(address).await.unwrap();
+        let mut data: &[u8] = &account.data;
+
+        T::try_deserialize(&mut data).unwrap()
+    }
+
+    pub async fn config(&mut self) -> Config {
+        let (config, _bump) = pda::find_config_address(&locker::id());
+        self.get_anchor_account(&config).await
+    }
+
+    /// `None` unless the admin handover is in progress.
+    pub async fn pending_admin(&mut self) -> Option<PendingAdmin> {
+        let (pending_admin, _bump) = pda::find_pending_admin_address(&locker::id());
+        match self.get_account(&pending_admin).await {
+            Some(_) => Some(self.get_anchor_account(&pending_admin).await),
+            None => None,
+        }
+    }
+
+    pub async fn mint_info(&mut self) -> MintInfo {
+        let (mint_info, _bump) = pda::find_mint_info_address(&locker::id(), &self.mint);
+        self.get_anchor_account(&mint_info).await
+    }
+
+    /// Flat fee in the stablecoin, `None` unless it's accepted.
+    pub async fn stablecoin_fee(&mut self) -> Option<StablecoinFee> {
+        let (stablecoin_fee, _bump) = pda::find_stablecoin_fee_address(&locker::id());
+        match self.get_account(&stablecoin_fee).await {
+            Some(_) => {
+                let account: StablecoinFeeConfig = self.get_anchor_account(&stablecoin_fee).await;
+                account.fee
+            }
+            None => None,
+        }
+    }
+
+    /// Fees set by `set_mint_fee` for the mint, `None` keeps the config ones.
+    pub async fn mint_fee(&mut self) -> Option<MintFee> {
+        let (mint_fee, _bump) = pda::find_mint_fee_address(&locker::id(), &self.mint);
+        match self.get_account(&mint_fee).await {
+            Some(_) => {
+                let account: MintFeeOverride = self.get_anchor_account(&mint_fee).await;
+                Some(account.fee)
+            }
+            None => None,
+        }
+    }
+
+    pub async fn locker(&mut self, locker: &Pubkey) -> Locker {
+        self.get_anchor_account(locker).await
+    }
+
+    /// `None` unless the ownership transfer of `locker` is in progress.
+    pub async fn pending_owner(&mut self, locker: &Pubkey) -> Option<PendingOwner> {
+        let (pending_owner, _bump) = pda::find_pending_owner_address(&locker::id(), locker);
+        match self.get_account(&pending_owner).await {
+            Some(_) => Some(self.get_anchor_account(&pending_owner).await),
+            None => None,
+        }
+    }
+
+    /// `None` unless `locker` has a beneficiary.
+    pub async fn beneficiary(&mut self, locker: &Pubkey) -> Option<Pubkey> {
+        let (beneficiary, _bump) = pda::find_beneficiary_address(&locker::id(), locker);
+        match self.get_account(&beneficiary).await {
+            Some(_) => {
+                let account: LockerBeneficiary = self.get_anchor_account(&beneficiary).await;
+                Some(account.beneficiary)
+            }
+            None => None,
+        }
+    }
+
+    /// Creator info of the payer, `None` until the first PDA locker.
+    pub async fn creator_info(&mut self) -> Option<CreatorInfo> {
+        let (creator_info, _bump) = pda::find_creator_info_address(&locker::id(), &self.payer());
+        match self.get_account(&creator_info).await {
+            Some(_) => Some(self.get_anchor_account(&creator_info).await),
+            None => None,
+        }
+    }
+
+    pub async fn balance(&mut self, address: &Pubkey) -> u64 {
+        self.get_account(address)
+            .await
+            .map(|account| account.lamports)
+            .unwrap_or_default()
+    }
+
+    pub async fn token_account(&mut self, address: &Pubkey) -> spl_token::state::Account {
+        let account = self.get_account(address).await.unwrap();
+        spl_token::state::Account::unpack(&account.data).unwrap()
+    }
+
+    pub async fn token_balance(&mut self, token_account: &Pubkey) -> u64 {
+        self.token_account(token_account).await.amount
+    }
+
+    pub async fn clock(&mut self) -> Clock {
+        let account = self.get_account(&sysvar::clock::id()).await.unwrap();
+        from_account(&account).unwrap()
+    }
+
+    /// Moves the cluster time to the given timestamp.
+    pub async fn warp_to_timestamp(&mut self, unix_timestamp: i64) {
+        let mut clock = self.clock().await;
+        clock.unix_timestamp = unix_timestamp;
+        self.context.set_sysvar(&clock);
+    }
+
+    pub async fn now(&mut self) -> i64 {
+        self.clock().await.unix_timestamp
+    }
+
+    async fn init_country_list(&mut self) -> Pubkey {
+        let country_banlist = Keypair::new();
+        let admin = self.payer();
+
+        let initialize = Instruction {
+            program_id: country_list::id(),
+            accounts: country_list::accounts::Initialize {
+                country_banlist: country_banlist.pubkey(),
+                admin,
+                system_program: system_program::id(),
+            }
+            .to_account_metas(None),
+            data: country_list::instruction::Initialize {
+                countries: vec![*b"RU", *b"US"],
+            }
+            .data(),
+        };
+        let flip_ban = Instruction {
+            program_id: country_list::id(),
+            accounts: country_list::accounts::FlipBan {
+                country_banlist: country_banlist.pubkey(),
+                admin,
+            }
+            .to_account_metas(None),
+            data: country_list::instruction::FlipBan {
+                country: "US".to_string(),
+                value: true,
+            }
+            .data(),
+        };
+
+        self.process(&[initialize, flip_ban], &[&country_banlist])
+            .await
+            .unwrap();
+
+        country_banlist.pubkey()
+    }
+
+    async fn rent_exempt_balance(&mut self, size: usize) -> u64 {
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        rent.minimum_balance(size)
+    }
+
+    pub async fn create_mint(&mut self) -> Pubkey {
+        let mint = Keypair::new();
+        let payer = self.payer();
+        let lamports = self.rent_exempt_balance(spl_token::state::Mint::LEN).await;
+
+        let instructions = [
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                lamports,
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_mint(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &self.mint_authority.pubkey(),
+                Some(&self.mint_authority.pubkey()),
+                DECIMALS,
+            )
+            .unwrap(),
+        ];
+        self.process(&instructions, &[&mint]).await.unwrap();
+
+        mint.pubkey()
+    }
+
+    pub async fn create_token_wallet(&mut self, owner: &Pubkey) -> Pubkey {
+        let wallet = Keypair::new();
+        self.create_token_wallet_at(&wallet, owner).await;
+
+        wallet.pubkey()
+    }
+
+    /// Same as `create_token_wallet` at the address of `wallet`, e.g. the
+    /// vault of the locker from `legacy_account`.
+    pub async fn create_token_wallet_at(&mut self, wallet: &Keypair, owner: &Pubkey) {
+        let payer = self.payer();
+        let lamports = self
+            .rent_exempt_balance(spl_token::state::Account::LEN)
+            .await;
+
+        let instructions =
+            instruction::create_vault(&payer, &wallet.pubkey(), &self.mint, owner, lamports)
+                .unwrap();
+        self.process(&instructions, &[wallet]).await.unwrap();
+    }
+
+    pub async fn mint_to(&mut self, wallet: &Pubkey, amount: u64) {
+        let mint_authority = Keypair::from_bytes(&self.mint_authority.to_bytes()).unwrap();
+        let mint_to = spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &self.mint,
+            wallet,
+            &mint_authority.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap();
+
+        self.process(&[mint_to], &[&mint_authority]).await.unwrap();
+    }
+
+    /// Freezes the token account, the mint authority is the freeze authority too.
+    pub async fn freeze(&mut self, wallet: &Pubkey) {
+        let mint_authority = Keypair::from_bytes(&self.mint_authority.to_bytes()).unwrap();
+        let freeze = spl_token::instruction::freeze_account(
+            &spl_token::id(),
+            wallet,
+            &self.mint,
+            &mint_authority.pubkey(),
+            &[],
+        )
+        .unwrap();
+
+        self.process(&[freeze], &[&mint_authority]).await.unwrap();
+    }
+
+    /// Approves a random delegate for the token wallet owned by the payer.
+    pub async fn approve_delegate(&mut self, wallet: &Pubkey) {
+        let payer = self.payer();
+        let approve = spl_token::instruction::approve(
+            &spl_token::id(),
+            wallet,
+            &Keypair::new().pubkey(),
+            &payer,
+            &[],
+            1,
+        )
+        .unwrap();
+
+        self.process(&[approve], &[]).await.unwrap();
+    }
+
+    /// Changes the authority of the token wallet owned by the payer.
+    pub async fn set_wallet_authority(
+        &mut self,
+        wallet: &Pubkey,
+        authority_type: spl_token::instruction::AuthorityType,
+        new_authority: &Pubkey,
+    ) {
+        let payer = self.payer();
+        let set_authority = spl_token::instruction::set_authority(
+            &spl_token::id(),
+            wallet,
+            Some(new_authority),
+            authority_type,
+            &payer,
+            &[],
+        )
+        .unwrap();
+
+        self.process(&[set_authority], &[]).await.unwrap();
+    }
+
+    async fn create_fee_token_wallet(&mut self) {
+        let fee_wallet = self.fee_wallet;
+        self.create_associated_token_wallet(&fee_wallet).await;
+    }
+
+    pub async fn create_associated_token_wallet(&mut self, owner: &Pubkey) -> Pubkey {
+        let payer = self.payer();
+        let create = spl_associated_token_account::create_associated_token_account(
+            &payer, owner, &self.mint,
+        );
+
+        self.process(&[create], &[]).await.unwrap();
+
+        spl_associated_token_account::get_associated_token_address(owner, &self.mint)
+    }
+
+    pub fn fee_token_wallet(&self) -> Pubkey {
+        spl_associated_token_account::get_associated_token_address(&self.fee_wallet, &self.mint)
+    }
+
+    /// Creates a vault owned by the vault authority of `locker`,
+    /// used to split the lockers.
+    pub async fn create_vault(&mut self, locker: &Pubkey) -> Pubkey {
+        let (vault_authority, _bump) = pda::find_vault_authority_address(&locker::id(), locker);
+        self.create_token_wallet(&vault_authority).await
+    }
+
+    /// Creates a locker owned by the payer and funded from `funding_wallet`,
+    /// the payer pays the fee in the stablecoin if it's chosen and accepted.
+    pub async fn create_locker(
+        &mut self,
+        args: CreateLockerArgs,
+    ) -> Result<TestLocker, TransportError> {
+        let locker = Keypair::new();
+        let (vault, _bump) = pda::find_vault_address(&locker::id(), &locker.pubkey());
+        let config = self.config().await;
+        let payer = self.payer();
+        let stablecoin_fee = self
+            .stablecoin_fee()
+            .await
+            .filter(|_| args.fee_in_stablecoin);
+
+        let mut create_locker = instruction::create_locker(
+            &locker::id(),
+            instruction::CreateLockerAccounts {
+                locker: locker.pubkey(),
+                creator: payer,
+                owner: payer,
+                funding_wallet_authority: payer,
+                funding_wallet: self.funding_wallet,
+                mint: self.mint,
+            },
+            &config,
+            args,
+        );
+        if let Some(fee) = &stablecoin_fee {
+            instruction::add_stablecoin_fee_accounts(&mut create_locker, &config, fee, &payer);
+        }
+        self.process(&[create_locker], &[&locker]).await?;
+
+        Ok(TestLocker {
+            address: locker.pubkey(),
+            vault,
+        })
+    }
+
+    /// Creates a locker at the PDA of the payer and the mint, inits
+    /// the creator info first if needed.
+    pub async fn create_pda_locker(
+        &mut self,
+        args: CreateLockerArgs,
+    ) -> Result<TestLocker, TransportError> {
+        let payer = self.payer();
+
+        let nonce = match self.creator_info().await {
+            Some(creator_info) => creator_info.lockers_created,
+            None => {
+                let init = instruction::init_creator_info(&locker::id(), &payer);
+                self.process(&[init], &[]).await?;
+                0
+            }
+        };
+
+        let (locker, _bump) = pda::find_locker_address(&locker::id(), &payer, &self.mint, nonce);
+        let (vault, _bump) = pda::find_vault_address(&locker::id(), &locker);
+        let config = self.config().await;
+        let stablecoin_fee = self
+            .stablecoin_fee()
+            .await
+            .filter(|_| args.fee_in_stablecoin);
+
+        let mut create_locker = instruction::create_locker_with_pda(
+            &locker::id(),
+            instruction::CreatePdaLockerAccounts {
+                creator: payer,
+                owner: payer,
+                funding_wallet_authority: payer,
+                funding_wallet: self.funding_wallet,
+                mint: self.mint,
+            },
+            &config,
+            nonce,
+            args,
+        );
+        if let Some(fee) = &stablecoin_fee {
+            instruction::add_stablecoin_fee_accounts(&mut create_locker, &config, fee, &payer);
+        }
+        self.process(&[create_locker], &[]).await?;
+
+        Ok(TestLocker {
+            address: locker,
+            vault,
+        })
+    }
+}
+
+/// Arguments for a hard locker of `amount` tokens in the `RU` country.
+pub fn locker_args(amount: u64, unlock_date: i64) -> CreateLockerArgs {
+    CreateLockerArgs {
+        amount,
+        unlock_date,
+        country_code: "RU".to_string(),
+        start_emission: None,
+        cliff: None,
+        tranches: None,
+        // Filled by the instruction builder.
+        vault_bump: 0,
+        fee_in_sol: false,
+        fee_in_stablecoin: false,
+        revoke_authority: None,
+    }
+}
+
+fn assert_custom_error(result: Result<(), TransportError>, error: ProgramError) {
+    let expected = match error {
+        ProgramError::Custom(code) => code,
+        other => panic!("{:?} isn't a custom error", other),
+    };
+    match result {
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            _,
+            InstructionError::Custom(code),
+        ))) => assert_eq!(code, expected),
+        other => panic!("expected {:?}, got {:?}", expected, other),
+    }
+}
+
+/// Checks that the transaction has failed with the given program error.
+pub fn assert_locker_error(result: Result<(), TransportError>, error: locker::ErrorCode) {
+    assert_custom_error(result, error.into())
+}
+
+/// Checks that the transaction has failed with the given Anchor error,
+/// e.g. a violated account constraint.
+pub fn assert_anchor_error(
+    result: Result<(), TransportError>,
+    error: anchor_lang::__private::ErrorCode,
+) {
+    assert_custom_error(result, error.into())
+}
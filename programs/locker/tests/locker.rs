@@ -0,0 +1,2574 @@
+#![cfg(feature = "test-bpf")]
+
+mod common;
+
+use anchor_lang::{__private::ErrorCode as AnchorErrorCode, InstructionData, ToAccountMetas};
+use common::*;
+use locker::{
+    Cliff, Config, DiscountTier, ErrorCode, Locker, MintFee, SetPauseArgs, StablecoinFee, TokenFee,
+    Tranche, UpdateConfigArgs,
+};
+use locker_client::{instruction, pda};
+use solana_sdk::{
+    account::Account,
+    instruction::Instruction,
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, system_program,
+};
+use spl_token::instruction::AuthorityType;
+
+const AMOUNT: u64 = 8_000_000;
+
+fn token_fee(amount: u64) -> u64 {
+    amount * FEE_NUMERATOR / FEE_DENOMINATOR
+}
+
+#[tokio::test]
+async fn init_and_update_config() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+
+    let config = env.config().await;
+    assert_eq!(config.admin, env.payer());
+    assert_eq!(config.fee_wallet, env.fee_wallet);
+    assert_eq!(config.country_list, env.country_list);
+    assert_eq!(config.fee_in_sol, FEE_IN_SOL);
+    assert!(config.fee_in_lamports);
+    assert!(env.stablecoin_fee().await.is_none());
+    assert!(!config.mint_info_permissioned);
+    assert!(config.has_linear_emission);
+
+    // New configs keep the fee in lamports already.
+    env.process(&[instruction::migrate_config(&locker::id())], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.config().await.fee_in_sol, FEE_IN_SOL);
+
+    let admin = env.payer();
+    let new_fee_wallet = Keypair::new().pubkey();
+    let country_list = env.country_list;
+    let update_config = |fee_in_token_numerator| {
+        instruction::update_config(
+            &locker::id(),
+            &admin,
+            &new_fee_wallet,
+            &country_list,
+            UpdateConfigArgs {
+                fee_in_sol: Some(2 * LAMPORTS_PER_SOL),
+                fee_in_token_numerator,
+                fee_in_token_denominator: None,
+                mint_info_permissioned: None,
+                has_linear_emission: Some(false),
+            },
+        )
+    };
+
+    // The fee in tokens can't exceed the amount.
+    let result = env
+        .process(&[update_config(Some(FEE_DENOMINATOR + 1))], &[])
+        .await;
+    assert_locker_error(result, ErrorCode::InvalidFee);
+
+    env.process(&[update_config(None)], &[]).await.unwrap();
+
+    let config = env.config().await;
+    assert_eq!(config.fee_wallet, new_fee_wallet);
+    assert_eq!(config.fee_in_sol, 2 * LAMPORTS_PER_SOL);
+    assert_eq!(config.fee_in_token_numerator, FEE_NUMERATOR);
+    assert!(!config.has_linear_emission);
+}
+
+#[tokio::test]
+async fn admin_handover() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let admin = env.payer();
+    let new_admin = Keypair::new();
+
+    // Nobody can accept the handover before it's proposed.
+    let accept = instruction::accept_admin(&locker::id(), &admin, &new_admin.pubkey());
+    assert!(env.process(&[accept], &[&new_admin]).await.is_err());
+
+    // Proposing again replaces the pending admin.
+    let stranger = Keypair::new();
+    let propose = instruction::propose_admin(&locker::id(), &admin, &stranger.pubkey());
+    env.process(&[propose], &[]).await.unwrap();
+    let propose = instruction::propose_admin(&locker::id(), &admin, &new_admin.pubkey());
+    env.process(&[propose], &[]).await.unwrap();
+    assert_eq!(env.pending_admin().await.unwrap().admin, new_admin.pubkey());
+
+    // Only the proposed admin can accept it.
+    let accept_by_stranger = instruction::accept_admin(&locker::id(), &admin, &stranger.pubkey());
+    assert!(env
+        .process(&[accept_by_stranger], &[&stranger])
+        .await
+        .is_err());
+
+    let accept = instruction::accept_admin(&locker::id(), &admin, &new_admin.pubkey());
+    env.process(&[accept], &[&new_admin]).await.unwrap();
+
+    assert_eq!(env.config().await.admin, new_admin.pubkey());
+    assert!(env.pending_admin().await.is_none());
+
+    // The old admin has no rights anymore.
+    let set_pause = instruction::set_pause(
+        &locker::id(),
+        &admin,
+        SetPauseArgs {
+            deposits: Some(true),
+            withdrawals: None,
+        },
+    );
+    assert!(env.process(&[set_pause], &[]).await.is_err());
+
+    let set_pause = instruction::set_pause(
+        &locker::id(),
+        &new_admin.pubkey(),
+        SetPauseArgs {
+            deposits: Some(true),
+            withdrawals: None,
+        },
+    );
+    env.process(&[set_pause], &[&new_admin]).await.unwrap();
+    assert!(env.config().await.deposits_paused);
+}
+
+#[tokio::test]
+async fn admin_handover_on_legacy_config() {
+    let admin = Keypair::new();
+    let new_admin = Keypair::new();
+
+    let (config, bump) = pda::find_config_address(&locker::id());
+    let legacy_config = Config {
+        admin: admin.pubkey(),
+        fee_in_sol: 1,
+        fee_in_token_numerator: FEE_NUMERATOR,
+        fee_in_token_denominator: FEE_DENOMINATOR,
+        mint_info_permissioned: false,
+        has_linear_emission: true,
+        fee_wallet: Pubkey::new_unique(),
+        country_list: Pubkey::new_unique(),
+        bump,
+        deposits_paused: false,
+        withdrawals_paused: false,
+        fee_in_lamports: false,
+    };
+    let mut env = TestEnv::with_accounts(
+        Preset::TokenLocker,
+        vec![
+            (config, legacy_account(&legacy_config, LEGACY_CONFIG_LEN)),
+            (
+                admin.pubkey(),
+                Account::new(LAMPORTS_PER_SOL, 0, &system_program::id()),
+            ),
+        ],
+    )
+    .await;
+
+    let propose = instruction::propose_admin(&locker::id(), &admin.pubkey(), &new_admin.pubkey());
+    env.process(&[propose], &[&admin]).await.unwrap();
+
+    let accept = instruction::accept_admin(&locker::id(), &admin.pubkey(), &new_admin.pubkey());
+    env.process(&[accept], &[&new_admin]).await.unwrap();
+
+    assert_eq!(env.config().await.admin, new_admin.pubkey());
+    assert!(env.pending_admin().await.is_none());
+    let config_account = env.get_account(&config).await.unwrap();
+    assert_eq!(config_account.data.len(), LEGACY_CONFIG_LEN);
+}
+
+#[tokio::test]
+async fn stablecoin_fee_on_legacy_config() {
+    let admin = Keypair::new();
+
+    let (config, bump) = pda::find_config_address(&locker::id());
+    let legacy_config = Config {
+        admin: admin.pubkey(),
+        fee_in_sol: 1,
+        fee_in_token_numerator: FEE_NUMERATOR,
+        fee_in_token_denominator: FEE_DENOMINATOR,
+        mint_info_permissioned: false,
+        has_linear_emission: true,
+        fee_wallet: Pubkey::new_unique(),
+        country_list: Pubkey::new_unique(),
+        bump,
+        deposits_paused: false,
+        withdrawals_paused: false,
+        fee_in_lamports: false,
+    };
+    let mut env = TestEnv::with_accounts(
+        Preset::TokenLocker,
+        vec![
+            (config, legacy_account(&legacy_config, LEGACY_CONFIG_LEN)),
+            (
+                admin.pubkey(),
+                Account::new(LAMPORTS_PER_SOL, 0, &system_program::id()),
+            ),
+        ],
+    )
+    .await;
+
+    let stablecoin_fee = StablecoinFee {
+        mint: Pubkey::new_unique(),
+        amount: 50_000_000,
+    };
+    let set_stablecoin_fee =
+        instruction::set_stablecoin_fee(&locker::id(), &admin.pubkey(), Some(stablecoin_fee));
+    env.process(&[set_stablecoin_fee], &[&admin]).await.unwrap();
+
+    assert_eq!(env.stablecoin_fee().await, Some(stablecoin_fee));
+    let config_account = env.get_account(&config).await.unwrap();
+    assert_eq!(config_account.data.len(), LEGACY_CONFIG_LEN);
+}
+
+#[tokio::test]
+async fn only_admin_inits_mint_info_for_lp_lockers() {
+    let mut env = TestEnv::new(Preset::LPLocker).await;
+    let mint = env.create_mint().await;
+
+    let stranger = Keypair::new();
+    let fund = system_instruction::transfer(&env.payer(), &stranger.pubkey(), LAMPORTS_PER_SOL);
+    env.process(&[fund], &[]).await.unwrap();
+
+    let mut init_mint_info = instruction::init_mint_info(&locker::id(), &stranger.pubkey(), &mint);
+    // The payer funds the new account, so it has to be writable.
+    init_mint_info.accounts[0].is_writable = true;
+
+    let result = env.process(&[init_mint_info], &[&stranger]).await;
+    assert_locker_error(result, ErrorCode::InitMintInfoNotAuthorized);
+
+    let admin = env.payer();
+    let init_mint_info = instruction::init_mint_info(&locker::id(), &admin, &mint);
+    env.process(&[init_mint_info], &[]).await.unwrap();
+}
+
+#[tokio::test]
+async fn create_locker_with_fee_in_sol() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let fee_wallet = env.fee_wallet;
+
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    assert_eq!(env.balance(&fee_wallet).await, LAMPORTS_PER_SOL);
+    assert!(env.mint_info().await.fee_paid);
+
+    let fee_token_wallet = env.fee_token_wallet();
+    assert_eq!(env.token_balance(&fee_token_wallet).await, 0);
+    assert_eq!(env.token_balance(&locker.vault).await, AMOUNT);
+
+    let locker_account = env.locker(&locker.address).await;
+    assert_eq!(locker_account.owner, env.payer());
+    assert_eq!(&locker_account.country_code, b"RU");
+    assert_eq!(locker_account.current_unlock_date, unlock_date);
+    assert_eq!(locker_account.deposited_amount, AMOUNT);
+    assert_eq!(locker_account.vault, locker.vault);
+
+    // The fee is paid once per mint, so the next lockers are free.
+    env.create_locker(locker_args(AMOUNT / 2, unlock_date))
+        .await
+        .unwrap();
+    assert_eq!(env.balance(&fee_wallet).await, LAMPORTS_PER_SOL);
+    assert_eq!(env.token_balance(&fee_token_wallet).await, 0);
+}
+
+#[tokio::test]
+async fn fee_in_sol_is_paid_by_funding_wallet_authority() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let fee_wallet = env.fee_wallet;
+    let config = env.config().await;
+    let payer = env.payer();
+
+    // The owner doesn't sign, like with the fee in the stablecoin.
+    let owner = Keypair::new().pubkey();
+    let locker = Keypair::new();
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let create_locker = instruction::create_locker(
+        &locker::id(),
+        instruction::CreateLockerAccounts {
+            locker: locker.pubkey(),
+            creator: payer,
+            owner,
+            funding_wallet_authority: payer,
+            funding_wallet: env.funding_wallet,
+            mint: env.mint,
+        },
+        &config,
+        args,
+    );
+    env.process(&[create_locker], &[&locker]).await.unwrap();
+
+    assert_eq!(env.balance(&fee_wallet).await, LAMPORTS_PER_SOL);
+    assert_eq!(env.balance(&owner).await, 0);
+    assert_eq!(env.locker(&locker.pubkey()).await.owner, owner);
+}
+
+#[tokio::test]
+async fn create_locker_with_sub_sol_fee() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let admin = env.payer();
+    let fee_wallet = env.fee_wallet;
+    let country_list = env.country_list;
+
+    let update_config = instruction::update_config(
+        &locker::id(),
+        &admin,
+        &fee_wallet,
+        &country_list,
+        UpdateConfigArgs {
+            fee_in_sol: Some(LAMPORTS_PER_SOL / 4),
+            fee_in_token_numerator: None,
+            fee_in_token_denominator: None,
+            mint_info_permissioned: None,
+            has_linear_emission: None,
+        },
+    );
+    env.process(&[update_config], &[]).await.unwrap();
+
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    env.create_locker(args).await.unwrap();
+
+    assert_eq!(env.balance(&fee_wallet).await, LAMPORTS_PER_SOL / 4);
+}
+
+#[tokio::test]
+async fn create_locker_with_fee_in_stablecoin() {
+    const STABLECOIN_FEE: u64 = 50_000_000;
+
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let admin = env.payer();
+    let fee_wallet = env.fee_wallet;
+
+    let stablecoin_args = |fee_in_sol| {
+        let mut args = locker_args(AMOUNT, unlock_date);
+        args.fee_in_sol = fee_in_sol;
+        args.fee_in_stablecoin = true;
+        args
+    };
+
+    // The stablecoin isn't accepted yet.
+    let result = env.create_locker(stablecoin_args(false)).await;
+    assert_locker_error(result, ErrorCode::StablecoinFeeDisabled);
+
+    // The helpers work with the env mint, so the stablecoin takes its place
+    // while the wallets are funded.
+    let mint = env.mint;
+    let stablecoin = env.create_mint().await;
+    env.mint = stablecoin;
+    let stablecoin_wallet = env.create_associated_token_wallet(&admin).await;
+    env.mint_to(&stablecoin_wallet, STABLECOIN_FEE).await;
+    let fee_stablecoin_wallet = env.create_associated_token_wallet(&fee_wallet).await;
+    env.mint = mint;
+
+    let stablecoin_fee = StablecoinFee {
+        mint: stablecoin,
+        amount: STABLECOIN_FEE,
+    };
+
+    // The stranger pays for the fee account created before the admin check.
+    let stranger = Keypair::new();
+    let fund = system_instruction::transfer(&admin, &stranger.pubkey(), LAMPORTS_PER_SOL);
+    env.process(&[fund], &[]).await.unwrap();
+    let set_by_stranger =
+        instruction::set_stablecoin_fee(&locker::id(), &stranger.pubkey(), Some(stablecoin_fee));
+    let result = env.process(&[set_by_stranger], &[&stranger]).await;
+    assert_anchor_error(result, AnchorErrorCode::ConstraintRaw);
+
+    let set_stablecoin_fee =
+        instruction::set_stablecoin_fee(&locker::id(), &admin, Some(stablecoin_fee));
+    env.process(&[set_stablecoin_fee], &[]).await.unwrap();
+    assert_eq!(env.stablecoin_fee().await, Some(stablecoin_fee));
+
+    // Only one of the flat fees can be chosen.
+    let result = env.create_locker(stablecoin_args(true)).await;
+    assert_locker_error(result, ErrorCode::InvalidFeeMode);
+
+    // The stablecoin wallets are required.
+    let locker = Keypair::new();
+    let create_locker = instruction::create_locker(
+        &locker::id(),
+        instruction::CreateLockerAccounts {
+            locker: locker.pubkey(),
+            creator: admin,
+            owner: admin,
+            funding_wallet_authority: admin,
+            funding_wallet: env.funding_wallet,
+            mint,
+        },
+        &env.config().await,
+        stablecoin_args(false),
+    );
+    let result = env.process(&[create_locker], &[&locker]).await;
+    assert_locker_error(result, ErrorCode::InvalidStablecoinFeeAccounts);
+
+    let locker = env.create_locker(stablecoin_args(false)).await.unwrap();
+
+    assert_eq!(
+        env.token_balance(&fee_stablecoin_wallet).await,
+        STABLECOIN_FEE
+    );
+    assert_eq!(env.token_balance(&stablecoin_wallet).await, 0);
+    // Neither SOL nor the locked tokens are charged.
+    assert_eq!(env.balance(&fee_wallet).await, 0);
+    assert_eq!(env.token_balance(&locker.vault).await, AMOUNT);
+    assert!(env.mint_info().await.fee_paid);
+
+    // `None` stops accepting the stablecoin.
+    let disable = instruction::set_stablecoin_fee(&locker::id(), &admin, None);
+    env.process(&[disable], &[]).await.unwrap();
+    assert!(env.stablecoin_fee().await.is_none());
+    let result = env.create_locker(stablecoin_args(false)).await;
+    assert_locker_error(result, ErrorCode::StablecoinFeeDisabled);
+}
+
+#[tokio::test]
+async fn flat_fees_are_charged_on_their_own() {
+    const STABLECOIN_FEE: u64 = 50_000_000;
+
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let admin = env.payer();
+    let fee_wallet = env.fee_wallet;
+    let fee_token_wallet = env.fee_token_wallet();
+    let country_list = env.country_list;
+
+    let mint = env.mint;
+    let stablecoin = env.create_mint().await;
+    env.mint = stablecoin;
+    let stablecoin_wallet = env.create_associated_token_wallet(&admin).await;
+    env.mint_to(&stablecoin_wallet, STABLECOIN_FEE).await;
+    let fee_stablecoin_wallet = env.create_associated_token_wallet(&fee_wallet).await;
+    env.mint = mint;
+
+    let set_stablecoin_fee = instruction::set_stablecoin_fee(
+        &locker::id(),
+        &admin,
+        Some(StablecoinFee {
+            mint: stablecoin,
+            amount: STABLECOIN_FEE,
+        }),
+    );
+    // The fee in SOL is waived, the one in the stablecoin isn't.
+    let update_config = instruction::update_config(
+        &locker::id(),
+        &admin,
+        &fee_wallet,
+        &country_list,
+        UpdateConfigArgs {
+            fee_in_sol: Some(0),
+            fee_in_token_numerator: None,
+            fee_in_token_denominator: None,
+            mint_info_permissioned: None,
+            has_linear_emission: None,
+        },
+    );
+    env.process(&[set_stablecoin_fee, update_config], &[])
+        .await
+        .unwrap();
+
+    // The waived fee in SOL charges nothing.
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    assert_eq!(env.token_balance(&locker.vault).await, AMOUNT);
+    assert_eq!(env.token_balance(&fee_token_wallet).await, 0);
+    assert_eq!(env.balance(&fee_wallet).await, 0);
+    assert!(!env.mint_info().await.fee_paid);
+
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_stablecoin = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    assert_eq!(
+        env.token_balance(&fee_stablecoin_wallet).await,
+        STABLECOIN_FEE
+    );
+    assert_eq!(env.token_balance(&locker.vault).await, AMOUNT);
+    assert_eq!(env.token_balance(&fee_token_wallet).await, 0);
+    assert!(env.mint_info().await.fee_paid);
+}
+
+#[tokio::test]
+async fn create_locker_with_fee_in_tokens() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let fee_wallet = env.fee_wallet;
+
+    let locker = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+
+    let fee = token_fee(AMOUNT);
+    let fee_token_wallet = env.fee_token_wallet();
+    assert_eq!(env.token_balance(&fee_token_wallet).await, fee);
+    assert_eq!(env.token_balance(&locker.vault).await, AMOUNT - fee);
+    assert_eq!(env.balance(&fee_wallet).await, 0);
+    assert!(!env.mint_info().await.fee_paid);
+
+    let locker_account = env.locker(&locker.address).await;
+    assert_eq!(locker_account.deposited_amount, AMOUNT - fee);
+
+    // The vault is created by the program at the PDA of the locker.
+    let (vault, _bump) = pda::find_vault_address(&locker::id(), &locker.address);
+    assert_eq!(locker_account.vault, vault);
+    let (vault_authority, _bump) =
+        pda::find_vault_authority_address(&locker::id(), &locker.address);
+    let vault = env.token_account(&vault).await;
+    assert_eq!(vault.owner, vault_authority);
+    assert_eq!(vault.mint, env.mint);
+    assert!(vault.delegate.is_none());
+    assert!(vault.close_authority.is_none());
+}
+
+#[tokio::test]
+async fn create_locker_with_pda() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let payer = env.payer();
+    let mint = env.mint;
+
+    let first = env
+        .create_pda_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+    let second = env
+        .create_pda_locker(locker_args(AMOUNT, unlock_date + 1))
+        .await
+        .unwrap();
+
+    let (address, _bump) = pda::find_locker_address(&locker::id(), &payer, &mint, 0);
+    assert_eq!(first.address, address);
+    let (address, _bump) = pda::find_locker_address(&locker::id(), &payer, &mint, 1);
+    assert_eq!(second.address, address);
+    assert_eq!(env.creator_info().await.unwrap().lockers_created, 2);
+
+    let locker_account = env.locker(&second.address).await;
+    assert_eq!(locker_account.owner, payer);
+    assert_eq!(locker_account.current_unlock_date, unlock_date + 1);
+    assert_eq!(locker_account.vault, second.vault);
+    assert_eq!(
+        env.token_balance(&second.vault).await,
+        AMOUNT - token_fee(AMOUNT)
+    );
+
+    // The counter value is a part of the address, so the stale nonce fails.
+    let config = env.config().await;
+    let create_locker = instruction::create_locker_with_pda(
+        &locker::id(),
+        instruction::CreatePdaLockerAccounts {
+            creator: payer,
+            owner: payer,
+            funding_wallet_authority: payer,
+            funding_wallet: env.funding_wallet,
+            mint,
+        },
+        &config,
+        1,
+        locker_args(AMOUNT, unlock_date + 2),
+    );
+    assert!(env.process(&[create_locker], &[]).await.is_err());
+
+    // Keypair lockers are still supported.
+    env.create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn lp_lockers_always_pay_both_fees() {
+    let mut env = TestEnv::new(Preset::LPLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let fee_wallet = env.fee_wallet;
+
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    let fee = token_fee(AMOUNT);
+    let fee_token_wallet = env.fee_token_wallet();
+    assert_eq!(env.balance(&fee_wallet).await, LAMPORTS_PER_SOL);
+    assert_eq!(env.token_balance(&fee_token_wallet).await, fee);
+    assert_eq!(env.token_balance(&locker.vault).await, AMOUNT - fee);
+    assert!(!env.mint_info().await.fee_paid);
+}
+
+#[tokio::test]
+async fn mint_fee_overrides_config_fees() {
+    let mut env = TestEnv::new(Preset::LPLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let fee_wallet = env.fee_wallet;
+    let fee_token_wallet = env.fee_token_wallet();
+    let admin = env.payer();
+    let mint = env.mint;
+
+    // Mint infos keep their size, the fees are set apart from them.
+    let (mint_info, _bump) = pda::find_mint_info_address(&locker::id(), &mint);
+    let mint_info_account = env.get_account(&mint_info).await.unwrap();
+    assert_eq!(mint_info_account.data.len(), LEGACY_MINT_INFO_LEN);
+    assert_eq!(env.mint_fee().await, None);
+
+    let partner_fee = MintFee {
+        fee_in_sol: Some(0),
+        fee_in_token: Some(TokenFee {
+            numerator: 1,
+            denominator: 100,
+        }),
+    };
+
+    // The stranger pays for the fee account created before the admin check.
+    let stranger = Keypair::new();
+    let fund = system_instruction::transfer(&admin, &stranger.pubkey(), LAMPORTS_PER_SOL);
+    env.process(&[fund], &[]).await.unwrap();
+    let set_by_stranger =
+        instruction::set_mint_fee(&locker::id(), &stranger.pubkey(), &mint, partner_fee);
+    let result = env.process(&[set_by_stranger], &[&stranger]).await;
+    assert_anchor_error(result, AnchorErrorCode::ConstraintRaw);
+
+    let invalid_fee = MintFee {
+        fee_in_sol: None,
+        fee_in_token: Some(TokenFee {
+            numerator: 2,
+            denominator: 1,
+        }),
+    };
+    let result = env
+        .process(
+            &[instruction::set_mint_fee(
+                &locker::id(),
+                &admin,
+                &mint,
+                invalid_fee,
+            )],
+            &[],
+        )
+        .await;
+    assert_locker_error(result, ErrorCode::InvalidFee);
+
+    // LP lockers pay both fees, but the fee in SOL is waived for the mint.
+    env.process(
+        &[instruction::set_mint_fee(
+            &locker::id(),
+            &admin,
+            &mint,
+            partner_fee,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(env.mint_fee().await, Some(partner_fee));
+
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    env.create_locker(args).await.unwrap();
+    assert_eq!(env.balance(&fee_wallet).await, 0);
+    assert_eq!(env.token_balance(&fee_token_wallet).await, AMOUNT / 100);
+
+    let waiver = MintFee {
+        fee_in_sol: Some(0),
+        fee_in_token: Some(TokenFee {
+            numerator: 0,
+            denominator: 1,
+        }),
+    };
+    env.process(
+        &[instruction::set_mint_fee(
+            &locker::id(),
+            &admin,
+            &mint,
+            waiver,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let locker = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&locker.vault).await, AMOUNT);
+    assert_eq!(env.balance(&fee_wallet).await, 0);
+    assert_eq!(env.token_balance(&fee_token_wallet).await, AMOUNT / 100);
+
+    // The default restores the config fees.
+    env.process(
+        &[instruction::set_mint_fee(
+            &locker::id(),
+            &admin,
+            &mint,
+            MintFee::default(),
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let locker = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+    let fee = token_fee(AMOUNT);
+    assert_eq!(env.token_balance(&locker.vault).await, AMOUNT - fee);
+    assert_eq!(env.balance(&fee_wallet).await, LAMPORTS_PER_SOL);
+    assert_eq!(
+        env.token_balance(&fee_token_wallet).await,
+        AMOUNT / 100 + fee
+    );
+}
+
+#[tokio::test]
+async fn waived_fee_in_sol_charges_nothing() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let fee_wallet = env.fee_wallet;
+    let fee_token_wallet = env.fee_token_wallet();
+    let admin = env.payer();
+    let mint = env.mint;
+
+    let waiver = MintFee {
+        fee_in_sol: Some(0),
+        fee_in_token: None,
+    };
+    let set_mint_fee = instruction::set_mint_fee(&locker::id(), &admin, &mint, waiver);
+    env.process(&[set_mint_fee], &[]).await.unwrap();
+
+    // Choosing the waived fee in SOL doesn't charge the fee in tokens.
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+    assert_eq!(env.token_balance(&locker.vault).await, AMOUNT);
+    assert_eq!(env.token_balance(&fee_token_wallet).await, 0);
+    assert_eq!(env.balance(&fee_wallet).await, 0);
+
+    // The fee in tokens is charged when it's chosen.
+    let locker = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+    let fee = token_fee(AMOUNT);
+    assert_eq!(env.token_balance(&locker.vault).await, AMOUNT - fee);
+    assert_eq!(env.token_balance(&fee_token_wallet).await, fee);
+    assert_eq!(env.balance(&fee_wallet).await, 0);
+}
+
+#[tokio::test]
+async fn create_locker_fails_for_banned_country() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.country_code = "US".to_string();
+    let result = env.create_locker(args).await.map(|_| ());
+    assert_locker_error(result, ErrorCode::InvalidCountry);
+
+    // Countries missing from the list are not allowed either.
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.country_code = "XX".to_string();
+    let result = env.create_locker(args).await.map(|_| ());
+    assert_locker_error(result, ErrorCode::InvalidCountry);
+}
+
+#[tokio::test]
+async fn create_locker_fails_if_linear_emission_disabled() {
+    let mut env = TestEnv::new(Preset::LPLocker).await;
+    let now = env.now().await;
+
+    let mut args = locker_args(AMOUNT, now + DAY);
+    args.start_emission = Some(now + 100);
+    let result = env.create_locker(args).await.map(|_| ());
+    assert_locker_error(result, ErrorCode::LinearEmissionDisabled);
+}
+
+#[tokio::test]
+async fn relock() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let locker = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+    let owner = env.payer();
+
+    let relock = instruction::relock(&locker::id(), &locker.address, &owner, unlock_date - 1);
+    let result = env.process(&[relock], &[]).await;
+    assert_locker_error(result, ErrorCode::CannotUnlockToEarlierDate);
+
+    let relock = instruction::relock(&locker::id(), &locker.address, &owner, unlock_date + DAY);
+    env.process(&[relock], &[]).await.unwrap();
+
+    let locker_account = env.locker(&locker.address).await;
+    assert_eq!(locker_account.current_unlock_date, unlock_date + DAY);
+}
+
+#[tokio::test]
+async fn relock_rejects_tranches_and_cliffs() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let now = env.now().await;
+    let unlock_date = now + 4 * DAY;
+    let owner = env.payer();
+
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.tranches = Some(vec![
+        Tranche {
+            date: now + DAY,
+            basis_points: 5000,
+        },
+        Tranche {
+            date: unlock_date,
+            basis_points: 5000,
+        },
+    ]);
+    let tranches = env.create_locker(args).await.unwrap();
+
+    let mut args = locker_args(AMOUNT / 2, unlock_date);
+    args.start_emission = Some(now + 100);
+    args.cliff = Some(Cliff {
+        date: now + 2 * DAY,
+        basis_points: 2500,
+    });
+    let cliff = env.create_locker(args).await.unwrap();
+
+    for locker in &[&tranches, &cliff] {
+        let relock = instruction::relock(&locker::id(), &locker.address, &owner, unlock_date + DAY);
+        let result = env.process(&[relock], &[]).await;
+        assert_locker_error(result, ErrorCode::CannotRelockSchedule);
+        assert_eq!(
+            env.locker(&locker.address).await.current_unlock_date,
+            unlock_date
+        );
+    }
+
+    // The linear emission alone is stretched up to the new unlock date.
+    let mut args = locker_args(AMOUNT / 4, unlock_date);
+    args.start_emission = Some(now + 100);
+    let linear = env.create_locker(args).await.unwrap();
+    let relock = instruction::relock(&locker::id(), &linear.address, &owner, unlock_date + DAY);
+    env.process(&[relock], &[]).await.unwrap();
+    assert_eq!(
+        env.locker(&linear.address).await.current_unlock_date,
+        unlock_date + DAY
+    );
+}
+
+#[tokio::test]
+async fn transfer_ownership() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let locker = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+
+    let owner = env.payer();
+    let new_owner = Keypair::new();
+
+    // The one-step transfer should be chosen explicitly.
+    let transfer_ownership = instruction::transfer_ownership(
+        &locker::id(),
+        &locker.address,
+        &owner,
+        &new_owner.pubkey(),
+        false,
+    );
+    let result = env.process(&[transfer_ownership], &[]).await;
+    assert_locker_error(result, ErrorCode::AcceptanceRequired);
+    assert_eq!(env.locker(&locker.address).await.owner, owner);
+
+    let transfer_ownership = instruction::transfer_ownership(
+        &locker::id(),
+        &locker.address,
+        &owner,
+        &new_owner.pubkey(),
+        true,
+    );
+    env.process(&[transfer_ownership], &[]).await.unwrap();
+
+    let locker_account = env.locker(&locker.address).await;
+    assert_eq!(locker_account.owner, new_owner.pubkey());
+
+    // The old owner can't relock anymore.
+    let relock = instruction::relock(&locker::id(), &locker.address, &owner, unlock_date + DAY);
+    assert!(env.process(&[relock], &[]).await.is_err());
+}
+
+#[tokio::test]
+async fn set_beneficiary() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    let owner = env.payer();
+    let beneficiary = Keypair::new().pubkey();
+
+    // Only the owner picks the beneficiary.
+    let stranger = Keypair::new();
+    let set_by_stranger = instruction::set_beneficiary(
+        &locker::id(),
+        &locker.address,
+        &stranger.pubkey(),
+        beneficiary,
+    );
+    assert!(env.process(&[set_by_stranger], &[&stranger]).await.is_err());
+
+    let set_beneficiary = |beneficiary| {
+        instruction::set_beneficiary(&locker::id(), &locker.address, &owner, beneficiary)
+    };
+    env.process(&[set_beneficiary(beneficiary)], &[])
+        .await
+        .unwrap();
+
+    assert_eq!(env.locker(&locker.address).await.owner, owner);
+    assert_eq!(env.beneficiary(&locker.address).await, Some(beneficiary));
+
+    env.warp_to_timestamp(unlock_date + 1).await;
+
+    // The owner signs the withdrawals, but the funds go to the beneficiary.
+    let locker_account = env.locker(&locker.address).await;
+    let owner_wallet = env.create_token_wallet(&owner).await;
+    let beneficiary_wallet = env.create_token_wallet(&beneficiary).await;
+    let withdraw = |target_wallet, amount| {
+        instruction::withdraw_funds(
+            &locker::id(),
+            &locker.address,
+            &locker_account,
+            target_wallet,
+            amount,
+        )
+    };
+    let result = env.process(&[withdraw(&owner_wallet, 1)], &[]).await;
+    assert_locker_error(result, ErrorCode::InvalidBeneficiary);
+
+    env.process(&[withdraw(&beneficiary_wallet, AMOUNT / 2)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&beneficiary_wallet).await, AMOUNT / 2);
+
+    // Without a beneficiary any wallet receives the funds again.
+    let remove = instruction::remove_beneficiary(&locker::id(), &locker.address, &owner);
+    env.process(&[remove], &[]).await.unwrap();
+    assert!(env.beneficiary(&locker.address).await.is_none());
+
+    env.process(&[withdraw(&owner_wallet, AMOUNT / 4)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&owner_wallet).await, AMOUNT / 4);
+
+    // The beneficiary is closed together with the locker.
+    let other_beneficiary = Keypair::new().pubkey();
+    let other_beneficiary_wallet = env.create_token_wallet(&other_beneficiary).await;
+    env.process(&[set_beneficiary(other_beneficiary)], &[])
+        .await
+        .unwrap();
+    env.process(&[withdraw(&other_beneficiary_wallet, AMOUNT)], &[])
+        .await
+        .unwrap();
+    assert_eq!(
+        env.token_balance(&other_beneficiary_wallet).await,
+        AMOUNT / 4
+    );
+    assert!(env.get_account(&locker.address).await.is_none());
+    assert!(env.beneficiary(&locker.address).await.is_none());
+}
+
+#[tokio::test]
+async fn set_beneficiary_of_legacy_locker() {
+    let owner = Keypair::new();
+    let vault = Keypair::new();
+    let address = Pubkey::new_unique();
+    let (vault_authority, vault_bump) = pda::find_vault_authority_address(&locker::id(), &address);
+    let mut legacy_locker = legacy_locker(&owner.pubkey(), 0);
+    legacy_locker.vault = vault.pubkey();
+    legacy_locker.vault_bump = vault_bump;
+
+    let mut env = TestEnv::with_accounts(
+        Preset::TokenLocker,
+        vec![
+            (address, legacy_account(&legacy_locker, LEGACY_LOCKER_LEN)),
+            (
+                owner.pubkey(),
+                Account::new(LAMPORTS_PER_SOL, 0, &system_program::id()),
+            ),
+        ],
+    )
+    .await;
+    env.create_token_wallet_at(&vault, &vault_authority).await;
+    env.mint_to(&vault.pubkey(), INITIAL_BALANCE).await;
+
+    let beneficiary = Keypair::new().pubkey();
+    let set_beneficiary =
+        instruction::set_beneficiary(&locker::id(), &address, &owner.pubkey(), beneficiary);
+    env.process(&[set_beneficiary], &[&owner]).await.unwrap();
+    assert_eq!(env.beneficiary(&address).await, Some(beneficiary));
+
+    let withdraw = |target_wallet| {
+        instruction::withdraw_funds(
+            &locker::id(),
+            &address,
+            &legacy_locker,
+            target_wallet,
+            INITIAL_BALANCE,
+        )
+    };
+    let owner_wallet = env.create_token_wallet(&owner.pubkey()).await;
+    let result = env.process(&[withdraw(&owner_wallet)], &[&owner]).await;
+    assert_locker_error(result, ErrorCode::InvalidBeneficiary);
+
+    let beneficiary_wallet = env.create_token_wallet(&beneficiary).await;
+    env.process(&[withdraw(&beneficiary_wallet)], &[&owner])
+        .await
+        .unwrap();
+    assert_eq!(
+        env.token_balance(&beneficiary_wallet).await,
+        INITIAL_BALANCE
+    );
+    assert!(env.get_account(&address).await.is_none());
+    assert!(env.beneficiary(&address).await.is_none());
+}
+
+#[tokio::test]
+async fn two_step_ownership_transfer() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let locker = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+
+    let owner = env.payer();
+    let new_owner = Keypair::new();
+    let stranger = Keypair::new();
+
+    let propose =
+        instruction::propose_owner(&locker::id(), &locker.address, &owner, &stranger.pubkey());
+    env.process(&[propose], &[]).await.unwrap();
+    assert_eq!(
+        env.pending_owner(&locker.address).await.unwrap().owner,
+        stranger.pubkey()
+    );
+
+    // The owner changes their mind.
+    let cancel = instruction::cancel_ownership_transfer(&locker::id(), &locker.address, &owner);
+    env.process(&[cancel], &[]).await.unwrap();
+    assert!(env.pending_owner(&locker.address).await.is_none());
+
+    let accept =
+        instruction::accept_ownership(&locker::id(), &locker.address, &owner, &stranger.pubkey());
+    assert!(env.process(&[accept], &[&stranger]).await.is_err());
+
+    let propose =
+        instruction::propose_owner(&locker::id(), &locker.address, &owner, &new_owner.pubkey());
+    env.process(&[propose], &[]).await.unwrap();
+
+    // The locker still belongs to the old owner until the transfer is accepted.
+    assert_eq!(env.locker(&locker.address).await.owner, owner);
+
+    let another_stranger = Keypair::new();
+    let accept = instruction::accept_ownership(
+        &locker::id(),
+        &locker.address,
+        &owner,
+        &another_stranger.pubkey(),
+    );
+    assert!(env.process(&[accept], &[&another_stranger]).await.is_err());
+
+    let accept =
+        instruction::accept_ownership(&locker::id(), &locker.address, &owner, &new_owner.pubkey());
+    env.process(&[accept], &[&new_owner]).await.unwrap();
+
+    assert_eq!(env.locker(&locker.address).await.owner, new_owner.pubkey());
+    assert!(env.pending_owner(&locker.address).await.is_none());
+}
+
+#[tokio::test]
+async fn one_step_transfer_lapses_pending_transfer() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let locker = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+
+    let owner = env.payer();
+    let new_owner = Keypair::new();
+    let proposed_owner = Keypair::new();
+
+    let propose = instruction::propose_owner(
+        &locker::id(),
+        &locker.address,
+        &owner,
+        &proposed_owner.pubkey(),
+    );
+    env.process(&[propose], &[]).await.unwrap();
+
+    let transfer_ownership = instruction::transfer_ownership(
+        &locker::id(),
+        &locker.address,
+        &owner,
+        &new_owner.pubkey(),
+        true,
+    );
+    env.process(&[transfer_ownership], &[]).await.unwrap();
+
+    // The transfer proposed by the old owner can't take the locker anymore.
+    let accept = instruction::accept_ownership(
+        &locker::id(),
+        &locker.address,
+        &new_owner.pubkey(),
+        &proposed_owner.pubkey(),
+    );
+    assert!(env.process(&[accept], &[&proposed_owner]).await.is_err());
+    assert_eq!(env.locker(&locker.address).await.owner, new_owner.pubkey());
+}
+
+#[tokio::test]
+async fn two_step_ownership_transfer_of_legacy_locker() {
+    let owner = Keypair::new();
+    let new_owner = Keypair::new();
+    let address = Pubkey::new_unique();
+    let mut env = TestEnv::with_accounts(
+        Preset::TokenLocker,
+        vec![
+            (
+                address,
+                legacy_account(&legacy_locker(&owner.pubkey(), 0), LEGACY_LOCKER_LEN),
+            ),
+            (
+                owner.pubkey(),
+                Account::new(LAMPORTS_PER_SOL, 0, &system_program::id()),
+            ),
+        ],
+    )
+    .await;
+
+    let propose = instruction::propose_owner(
+        &locker::id(),
+        &address,
+        &owner.pubkey(),
+        &new_owner.pubkey(),
+    );
+    env.process(&[propose], &[&owner]).await.unwrap();
+
+    let accept = instruction::accept_ownership(
+        &locker::id(),
+        &address,
+        &owner.pubkey(),
+        &new_owner.pubkey(),
+    );
+    env.process(&[accept], &[&new_owner]).await.unwrap();
+
+    assert_eq!(env.locker(&address).await.owner, new_owner.pubkey());
+    assert!(env.pending_owner(&address).await.is_none());
+}
+
+#[tokio::test]
+async fn increment_lock() {
+    const INCREMENT: u64 = 3_000_000;
+
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let locker = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+    let deposited = AMOUNT - token_fee(AMOUNT);
+
+    let locker_account = env.locker(&locker.address).await;
+    let config = env.config().await;
+    let owner = env.payer();
+    let funding_wallet = env.funding_wallet;
+    let fee_token_wallet = env.fee_token_wallet();
+    let funding_balance = env.token_balance(&funding_wallet).await;
+    let fee_balance = env.token_balance(&fee_token_wallet).await;
+    let vault_balance = env.token_balance(&locker.vault).await;
+
+    let increment = instruction::increment_lock(
+        &locker::id(),
+        &locker.address,
+        &locker_account,
+        &env.mint,
+        &config,
+        &owner,
+        &funding_wallet,
+        INCREMENT,
+    );
+    env.process(&[increment], &[]).await.unwrap();
+
+    // The fee in tokens is charged for increments too, once and on
+    // the incremented amount only, not on the fee itself.
+    let fee = token_fee(INCREMENT);
+    assert_eq!(
+        env.token_balance(&funding_wallet).await,
+        funding_balance - INCREMENT
+    );
+    assert_eq!(
+        env.token_balance(&fee_token_wallet).await - fee_balance,
+        fee
+    );
+    assert_eq!(
+        env.token_balance(&locker.vault).await - vault_balance,
+        INCREMENT - fee
+    );
+
+    let locker_account = env.locker(&locker.address).await;
+    assert_eq!(locker_account.deposited_amount, deposited + INCREMENT - fee);
+}
+
+#[tokio::test]
+async fn fee_discounts_for_holders() {
+    const DISCOUNT: u16 = 2500;
+    let discounted = |fee: u64| fee - fee * DISCOUNT as u64 / 10000;
+
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let payer = env.payer();
+    let fee_wallet = env.fee_wallet;
+    let (mint, funding_wallet) = (env.mint, env.funding_wallet);
+
+    // The helpers work with the env mint, so the discount token takes
+    // its place while the wallets are funded.
+    let discount_mint = env.create_mint().await;
+    env.mint = discount_mint;
+    let holder_wallet = env.create_associated_token_wallet(&payer).await;
+    env.mint_to(&holder_wallet, 1000).await;
+    let foreign_wallet = env.create_associated_token_wallet(&fee_wallet).await;
+    env.mint_to(&foreign_wallet, 1000).await;
+    env.mint = mint;
+
+    let tiers = vec![
+        DiscountTier {
+            min_balance: 100,
+            basis_points: 1000,
+        },
+        DiscountTier {
+            min_balance: 1000,
+            basis_points: DISCOUNT,
+        },
+    ];
+    let set_fee_discounts =
+        instruction::set_fee_discounts(&locker::id(), &payer, &discount_mint, tiers.clone());
+    env.process(&[set_fee_discounts], &[]).await.unwrap();
+
+    let config = env.config().await;
+    let create_locker = |locker: &Keypair, fee_in_sol, holder_wallet: &Pubkey| {
+        let mut args = locker_args(AMOUNT, unlock_date);
+        args.fee_in_sol = fee_in_sol;
+        let mut create_locker = instruction::create_locker(
+            &locker::id(),
+            instruction::CreateLockerAccounts {
+                locker: locker.pubkey(),
+                creator: payer,
+                owner: payer,
+                funding_wallet_authority: payer,
+                funding_wallet,
+                mint,
+            },
+            &config,
+            args,
+        );
+        instruction::add_fee_discount_accounts(&locker::id(), &mut create_locker, holder_wallet);
+        create_locker
+    };
+
+    // Only the wallets of the funding wallet authority give the discount.
+    let locker = Keypair::new();
+    let result = env
+        .process(
+            &[create_locker(&locker, false, &foreign_wallet)],
+            &[&locker],
+        )
+        .await;
+    assert_locker_error(result, ErrorCode::InvalidDiscountWallet);
+
+    env.process(&[create_locker(&locker, false, &holder_wallet)], &[&locker])
+        .await
+        .unwrap();
+
+    let fee_token_wallet = env.fee_token_wallet();
+    let fee = discounted(token_fee(AMOUNT));
+    assert_eq!(env.token_balance(&fee_token_wallet).await, fee);
+
+    let locker_account = env.locker(&locker.pubkey()).await;
+    assert_eq!(locker_account.deposited_amount, AMOUNT - fee);
+
+    let mut increment = instruction::increment_lock(
+        &locker::id(),
+        &locker.pubkey(),
+        &locker_account,
+        &mint,
+        &config,
+        &payer,
+        &funding_wallet,
+        AMOUNT / 2,
+    );
+    instruction::add_fee_discount_accounts(&locker::id(), &mut increment, &holder_wallet);
+    env.process(&[increment], &[]).await.unwrap();
+
+    let increment_fee = discounted(token_fee(AMOUNT / 2));
+    assert_eq!(
+        env.token_balance(&fee_token_wallet).await,
+        fee + increment_fee
+    );
+
+    // The tiers are replaced as a whole, the holder falls to the lower tier.
+    let set_fee_discounts =
+        instruction::set_fee_discounts(&locker::id(), &payer, &discount_mint, tiers[..1].to_vec());
+    env.process(&[set_fee_discounts], &[]).await.unwrap();
+    let lower_discounted = |fee: u64| fee - fee / 10;
+
+    let locker_account = env.locker(&locker.pubkey()).await;
+    let mut increment = instruction::increment_lock(
+        &locker::id(),
+        &locker.pubkey(),
+        &locker_account,
+        &mint,
+        &config,
+        &payer,
+        &funding_wallet,
+        AMOUNT / 4,
+    );
+    instruction::add_fee_discount_accounts(&locker::id(), &mut increment, &holder_wallet);
+    env.process(&[increment], &[]).await.unwrap();
+    assert_eq!(
+        env.token_balance(&fee_token_wallet).await,
+        fee + increment_fee + lower_discounted(token_fee(AMOUNT / 4))
+    );
+
+    // The fee in SOL is discounted the same way.
+    let locker = Keypair::new();
+    env.process(&[create_locker(&locker, true, &holder_wallet)], &[&locker])
+        .await
+        .unwrap();
+    assert_eq!(env.balance(&fee_wallet).await, lower_discounted(FEE_IN_SOL));
+}
+
+#[tokio::test]
+async fn increment_lock_rejects_foreign_and_frozen_vaults() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let locker = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+    let other = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+
+    let locker_account = env.locker(&locker.address).await;
+    let config = env.config().await;
+    let owner = env.payer();
+    let (mint, funding_wallet) = (env.mint, env.funding_wallet);
+    let increment = |amount| {
+        instruction::increment_lock(
+            &locker::id(),
+            &locker.address,
+            &locker_account,
+            &mint,
+            &config,
+            &owner,
+            &funding_wallet,
+            amount,
+        )
+    };
+
+    let mut foreign_vault = increment(AMOUNT);
+    foreign_vault.accounts[1].pubkey = other.vault;
+    let result = env.process(&[foreign_vault], &[]).await;
+    assert_locker_error(result, ErrorCode::InvalidVault);
+
+    let frozen_vault = increment(AMOUNT + 1);
+    env.freeze(&locker.vault).await;
+    let result = env.process(&[frozen_vault], &[]).await;
+    assert_locker_error(result, ErrorCode::TokenAccountFrozen);
+}
+
+#[tokio::test]
+async fn increment_lock_after_fee_in_sol_is_free() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    let locker_account = env.locker(&locker.address).await;
+    let config = env.config().await;
+    let owner = env.payer();
+    let increment = instruction::increment_lock(
+        &locker::id(),
+        &locker.address,
+        &locker_account,
+        &env.mint,
+        &config,
+        &owner,
+        &env.funding_wallet,
+        AMOUNT,
+    );
+    env.process(&[increment], &[]).await.unwrap();
+
+    let fee_token_wallet = env.fee_token_wallet();
+    assert_eq!(env.token_balance(&fee_token_wallet).await, 0);
+    assert_eq!(env.token_balance(&locker.vault).await, 2 * AMOUNT);
+    assert_eq!(
+        env.locker(&locker.address).await.deposited_amount,
+        2 * AMOUNT
+    );
+}
+
+#[tokio::test]
+async fn withdraw_funds_after_unlock_date() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    let owner = env.payer();
+    let target_wallet = env.create_token_wallet(&owner).await;
+    let locker_account = env.locker(&locker.address).await;
+
+    // Identical transactions are rejected as duplicates, so the amounts differ.
+    let withdraw = |amount| {
+        instruction::withdraw_funds(
+            &locker::id(),
+            &locker.address,
+            &locker_account,
+            &target_wallet,
+            amount,
+        )
+    };
+    let result = env.process(&[withdraw(AMOUNT + 1)], &[]).await;
+    assert_locker_error(result, ErrorCode::TooEarlyToWithdraw);
+
+    env.warp_to_timestamp(unlock_date + 1).await;
+    env.process(&[withdraw(AMOUNT)], &[]).await.unwrap();
+
+    assert_eq!(env.token_balance(&target_wallet).await, AMOUNT);
+    // Both the locker and the vault are closed once everything is withdrawn.
+    assert!(env.get_account(&locker.address).await.is_none());
+    assert!(env.get_account(&locker.vault).await.is_none());
+}
+
+#[tokio::test]
+async fn withdraw_funds_rejects_foreign_vault_and_unsafe_target_wallets() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+    let other = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+    env.warp_to_timestamp(unlock_date + 1).await;
+
+    let owner = env.payer();
+    let target_wallet = env.create_token_wallet(&owner).await;
+    let locker_account = env.locker(&locker.address).await;
+    let withdraw = |target_wallet: &Pubkey, amount| {
+        instruction::withdraw_funds(
+            &locker::id(),
+            &locker.address,
+            &locker_account,
+            target_wallet,
+            amount,
+        )
+    };
+
+    // The vault of another locker together with its authority.
+    let mut foreign_vault = withdraw(&target_wallet, AMOUNT);
+    foreign_vault.accounts[2].pubkey =
+        pda::find_vault_authority_address(&locker::id(), &other.address).0;
+    foreign_vault.accounts[3].pubkey = other.vault;
+    let result = env.process(&[foreign_vault], &[]).await;
+    assert_anchor_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    // The vault of another locker only.
+    let mut foreign_vault = withdraw(&target_wallet, AMOUNT);
+    foreign_vault.accounts[3].pubkey = other.vault;
+    let result = env.process(&[foreign_vault], &[]).await;
+    assert_locker_error(result, ErrorCode::InvalidVault);
+
+    let with_delegate = env.create_token_wallet(&owner).await;
+    env.approve_delegate(&with_delegate).await;
+    let result = env.process(&[withdraw(&with_delegate, AMOUNT)], &[]).await;
+    assert_locker_error(result, ErrorCode::TokenAccountHasDelegate);
+
+    let with_close_authority = env.create_token_wallet(&owner).await;
+    env.set_wallet_authority(
+        &with_close_authority,
+        AuthorityType::CloseAccount,
+        &Keypair::new().pubkey(),
+    )
+    .await;
+    let result = env
+        .process(&[withdraw(&with_close_authority, AMOUNT)], &[])
+        .await;
+    assert_locker_error(result, ErrorCode::TokenAccountHasCloseAuthority);
+
+    env.process(&[withdraw(&target_wallet, AMOUNT)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&target_wallet).await, AMOUNT);
+}
+
+#[tokio::test]
+async fn withdraw_funds_with_linear_emission() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let start_emission = env.now().await + 100;
+    // Period is chosen to make the vested amounts exact.
+    let unlock_date = start_emission + 1024;
+
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    args.start_emission = Some(start_emission);
+    let locker = env.create_locker(args).await.unwrap();
+
+    let owner = env.payer();
+    let target_wallet = env.create_token_wallet(&owner).await;
+    let locker_account = env.locker(&locker.address).await;
+    let withdraw = |amount| {
+        instruction::withdraw_funds(
+            &locker::id(),
+            &locker.address,
+            &locker_account,
+            &target_wallet,
+            amount,
+        )
+    };
+
+    let result = env.process(&[withdraw(AMOUNT)], &[]).await;
+    assert_locker_error(result, ErrorCode::TooEarlyToWithdraw);
+
+    // A quarter of the period has passed.
+    env.warp_to_timestamp(start_emission + 256).await;
+    env.process(&[withdraw(AMOUNT - 1)], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&target_wallet).await, AMOUNT / 4);
+
+    // Nothing left to withdraw at the same moment.
+    let result = env.process(&[withdraw(1)], &[]).await;
+    assert_locker_error(result, ErrorCode::InvalidAmount);
+
+    // A half of the period has passed.
+    env.warp_to_timestamp(start_emission + 512).await;
+    env.process(&[withdraw(AMOUNT - 2)], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&target_wallet).await, AMOUNT / 2);
+    assert_eq!(
+        env.locker(&locker.address).await.withdrawn_amount,
+        AMOUNT / 2
+    );
+
+    env.warp_to_timestamp(unlock_date + 1).await;
+    env.process(&[withdraw(AMOUNT + 1)], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&target_wallet).await, AMOUNT);
+    assert!(env.get_account(&locker.address).await.is_none());
+}
+
+#[tokio::test]
+async fn release_vested() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let start_emission = env.now().await + 100;
+    let unlock_date = start_emission + 1024;
+
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    args.start_emission = Some(start_emission);
+    let locker = env.create_locker(args).await.unwrap();
+
+    // The payer cranks the releases of someone else's locker.
+    let owner = Keypair::new();
+    let transfer_ownership = instruction::transfer_ownership(
+        &locker::id(),
+        &locker.address,
+        &env.payer(),
+        &owner.pubkey(),
+        true,
+    );
+    env.process(&[transfer_ownership], &[]).await.unwrap();
+
+    let owner_wallet = env.create_associated_token_wallet(&owner.pubkey()).await;
+    let other_wallet = env.create_token_wallet(&owner.pubkey()).await;
+    let mint = env.mint;
+
+    // A quarter of the period has passed.
+    env.warp_to_timestamp(start_emission + 256).await;
+
+    let locker_account = env.locker(&locker.address).await;
+    let mut release_to_other_wallet =
+        instruction::release_vested(&locker::id(), &locker.address, &locker_account, None, &mint);
+    release_to_other_wallet.accounts[4].pubkey = other_wallet;
+    let result = env.process(&[release_to_other_wallet], &[]).await;
+    assert_locker_error(result, ErrorCode::InvalidReleaseWallet);
+
+    let release =
+        instruction::release_vested(&locker::id(), &locker.address, &locker_account, None, &mint);
+    env.process(&[release], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&owner_wallet).await, AMOUNT / 4);
+
+    // The rest goes to the beneficiary once set, the owner pays for it.
+    let beneficiary = Keypair::new().pubkey();
+    let fund_owner = system_instruction::transfer(&env.payer(), &owner.pubkey(), LAMPORTS_PER_SOL);
+    let set_beneficiary =
+        instruction::set_beneficiary(&locker::id(), &locker.address, &owner.pubkey(), beneficiary);
+    env.process(&[fund_owner, set_beneficiary], &[&owner])
+        .await
+        .unwrap();
+    let beneficiary_wallet = env.create_associated_token_wallet(&beneficiary).await;
+
+    env.warp_to_timestamp(unlock_date + 1).await;
+
+    let locker_account = env.locker(&locker.address).await;
+    let release = instruction::release_vested(
+        &locker::id(),
+        &locker.address,
+        &locker_account,
+        Some(beneficiary),
+        &mint,
+    );
+    env.process(&[release], &[]).await.unwrap();
+
+    assert_eq!(env.token_balance(&beneficiary_wallet).await, AMOUNT / 4 * 3);
+    assert!(env.get_account(&locker.address).await.is_none());
+    assert!(env.get_account(&locker.vault).await.is_none());
+}
+
+#[tokio::test]
+async fn revoke_locker() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let start_emission = env.now().await + 100;
+    let unlock_date = start_emission + 1024;
+
+    let authority = Keypair::new();
+    let args = || {
+        let mut args = locker_args(AMOUNT, unlock_date);
+        args.fee_in_sol = true;
+        args.start_emission = Some(start_emission);
+        args.revoke_authority = Some(authority.pubkey());
+        args
+    };
+
+    // Revoked before the start, at a quarter and at a half of the period.
+    let mut lockers = vec![];
+    for _ in 0..3 {
+        lockers.push(env.create_locker(args()).await.unwrap());
+    }
+
+    let owner = env.payer();
+    let owner_wallet = env.create_token_wallet(&owner).await;
+    let treasury_wallet = env.create_token_wallet(&authority.pubkey()).await;
+
+    let revoke = |locker: &TestLocker, locker_account: &Locker, authority: &Pubkey| {
+        instruction::revoke_locker(
+            &locker::id(),
+            &locker.address,
+            locker_account,
+            authority,
+            &treasury_wallet,
+        )
+    };
+    // Identical transactions are rejected as duplicates, so the amounts differ.
+    let withdraw = |locker: &TestLocker, locker_account: &Locker, amount| {
+        instruction::withdraw_funds(
+            &locker::id(),
+            &locker.address,
+            locker_account,
+            &owner_wallet,
+            amount,
+        )
+    };
+
+    let locker_account = env.locker(&lockers[0].address).await;
+    assert_eq!(locker_account.revoke_authority, Some(authority.pubkey()));
+
+    // Only the revoke authority claws the funds back.
+    let stranger = Keypair::new();
+    let result = env
+        .process(
+            &[revoke(&lockers[0], &locker_account, &stranger.pubkey())],
+            &[&stranger],
+        )
+        .await;
+    assert_locker_error(result, ErrorCode::InvalidRevokeAuthority);
+
+    // Nothing is vested yet, so everything goes back.
+    env.process(
+        &[revoke(&lockers[0], &locker_account, &authority.pubkey())],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    assert_eq!(env.token_balance(&treasury_wallet).await, AMOUNT);
+    assert!(env.get_account(&lockers[0].address).await.is_none());
+    assert!(env.get_account(&lockers[0].vault).await.is_none());
+
+    env.warp_to_timestamp(start_emission + 256).await;
+
+    // The vested quarter stays with the owner.
+    let locker_account = env.locker(&lockers[1].address).await;
+    env.process(
+        &[revoke(&lockers[1], &locker_account, &authority.pubkey())],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        env.token_balance(&treasury_wallet).await,
+        AMOUNT + AMOUNT / 4 * 3
+    );
+
+    let locker_account = env.locker(&lockers[1].address).await;
+    assert_eq!(locker_account.deposited_amount, AMOUNT / 4);
+    assert_eq!(locker_account.revoke_authority, None);
+    env.process(&[withdraw(&lockers[1], &locker_account, AMOUNT)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&owner_wallet).await, AMOUNT / 4);
+    assert!(env.get_account(&lockers[1].address).await.is_none());
+
+    // Withdrawn funds aren't clawed back.
+    let locker_account = env.locker(&lockers[2].address).await;
+    env.process(&[withdraw(&lockers[2], &locker_account, AMOUNT)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&owner_wallet).await, AMOUNT / 2);
+
+    env.warp_to_timestamp(start_emission + 512).await;
+
+    env.process(
+        &[revoke(&lockers[2], &locker_account, &authority.pubkey())],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        env.token_balance(&treasury_wallet).await,
+        AMOUNT * 2 + AMOUNT / 4
+    );
+
+    let locker_account = env.locker(&lockers[2].address).await;
+    assert_eq!(locker_account.deposited_amount, AMOUNT / 2);
+    assert_eq!(locker_account.withdrawn_amount, AMOUNT / 4);
+    env.process(&[withdraw(&lockers[2], &locker_account, AMOUNT + 1)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.token_balance(&owner_wallet).await, AMOUNT * 3 / 4);
+    assert!(env.get_account(&lockers[2].address).await.is_none());
+}
+
+#[tokio::test]
+async fn split_locker() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let start_emission = env.now().await + 100;
+    let unlock_date = start_emission + 1024;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    args.start_emission = Some(start_emission);
+    let locker = env.create_locker(args).await.unwrap();
+
+    let owner = env.payer();
+    let new_owner = Keypair::new().pubkey();
+    let amount = AMOUNT / 4;
+    let locker_account = env.locker(&locker.address).await;
+    let (new_locker, _bump) = pda::find_split_locker_address(
+        &locker::id(),
+        &locker.address,
+        locker_account.current_unlock_date,
+        amount,
+    );
+    let new_vault = env.create_vault(&new_locker).await;
+
+    let (split, split_locker) = instruction::split_locker(
+        &locker::id(),
+        &locker.address,
+        &locker_account,
+        &new_owner,
+        &new_vault,
+        amount,
+    );
+    assert_eq!(split_locker, new_locker);
+    env.process(&[split], &[]).await.unwrap();
+
+    assert_eq!(env.token_balance(&locker.vault).await, AMOUNT - amount);
+    assert_eq!(env.token_balance(&new_vault).await, amount);
+
+    let old_locker_account = env.locker(&locker.address).await;
+    assert_eq!(old_locker_account.owner, owner);
+    assert_eq!(old_locker_account.deposited_amount, AMOUNT - amount);
+
+    let new_locker_account = env.locker(&new_locker).await;
+    assert_eq!(new_locker_account.owner, new_owner);
+    assert_eq!(new_locker_account.deposited_amount, amount);
+    assert_eq!(new_locker_account.vault, new_vault);
+    assert_eq!(new_locker_account.current_unlock_date, unlock_date);
+    assert_eq!(new_locker_account.start_emission, Some(start_emission));
+}
+
+#[tokio::test]
+async fn split_locker_carries_over_withdrawn_amount() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let start_emission = env.now().await + 100;
+    // Period is chosen to make the vested amounts exact.
+    let unlock_date = start_emission + 1024;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    args.start_emission = Some(start_emission);
+    let locker = env.create_locker(args).await.unwrap();
+
+    let owner = env.payer();
+    let target_wallet = env.create_token_wallet(&owner).await;
+
+    // A half of the period has passed and everything vested is withdrawn.
+    env.warp_to_timestamp(start_emission + 512).await;
+    let locker_account = env.locker(&locker.address).await;
+    let withdraw = instruction::withdraw_funds(
+        &locker::id(),
+        &locker.address,
+        &locker_account,
+        &target_wallet,
+        AMOUNT,
+    );
+    env.process(&[withdraw], &[]).await.unwrap();
+
+    // A half of the locked amount is split.
+    let amount = AMOUNT / 4;
+    let locker_account = env.locker(&locker.address).await;
+    let (new_locker, _bump) =
+        pda::find_split_locker_address(&locker::id(), &locker.address, unlock_date, amount);
+    let new_vault = env.create_vault(&new_locker).await;
+    let (split, _new_locker) = instruction::split_locker(
+        &locker::id(),
+        &locker.address,
+        &locker_account,
+        &owner,
+        &new_vault,
+        amount,
+    );
+    env.process(&[split], &[]).await.unwrap();
+
+    let old_locker_account = env.locker(&locker.address).await;
+    assert_eq!(old_locker_account.deposited_amount, AMOUNT / 2);
+    assert_eq!(old_locker_account.withdrawn_amount, AMOUNT / 4);
+    let new_locker_account = env.locker(&new_locker).await;
+    assert_eq!(new_locker_account.deposited_amount, AMOUNT / 2);
+    assert_eq!(new_locker_account.withdrawn_amount, AMOUNT / 4);
+
+    let withdraw = |address, locker_account| {
+        instruction::withdraw_funds(
+            &locker::id(),
+            address,
+            locker_account,
+            &target_wallet,
+            AMOUNT,
+        )
+    };
+
+    // Nothing new is vested at the same moment in either of the parts.
+    let result = env
+        .process(&[withdraw(&locker.address, &old_locker_account)], &[])
+        .await;
+    assert_locker_error(result, ErrorCode::InvalidAmount);
+    let result = env
+        .process(&[withdraw(&new_locker, &new_locker_account)], &[])
+        .await;
+    assert_locker_error(result, ErrorCode::InvalidAmount);
+
+    // Three quarters of the period have passed, the parts release
+    // the same eighth the original locker would have released.
+    env.warp_to_timestamp(start_emission + 768).await;
+    env.process(
+        &[
+            withdraw(&locker.address, &old_locker_account),
+            withdraw(&new_locker, &new_locker_account),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        env.token_balance(&target_wallet).await,
+        AMOUNT / 2 + AMOUNT / 4
+    );
+}
+
+#[tokio::test]
+async fn split_locker_batch() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    let owner = env.payer();
+    let new_owner = Keypair::new().pubkey();
+    let amount = AMOUNT / 4;
+    let mint = env.mint;
+    let split = |locker_account: &Locker, parts: &[(u64, Pubkey)]| {
+        instruction::split_locker_batch(
+            &locker::id(),
+            &locker.address,
+            locker_account,
+            &mint,
+            parts,
+        )
+    };
+
+    let locker_account = env.locker(&locker.address).await;
+    let (mut wrong_accounts, _new_lockers) =
+        split(&locker_account, &[(amount, owner), (amount, new_owner)]);
+    wrong_accounts
+        .accounts
+        .truncate(wrong_accounts.accounts.len() - 4);
+    let result = env.process(&[wrong_accounts], &[]).await;
+    assert_locker_error(result, ErrorCode::InvalidSplitParts);
+
+    // The bumps are checked, not searched for: discriminator, length of
+    // the parts, amount and new owner go before the locker bump.
+    let (mut wrong_bump, _new_lockers) = split(&locker_account, &[(amount, owner)]);
+    wrong_bump.data[8 + 4 + 8 + 32] ^= 1;
+    let result = env.process(&[wrong_bump], &[]).await;
+    assert_locker_error(result, ErrorCode::InvalidSplitParts);
+
+    // Equal parts don't collide.
+    let (ix, new_lockers) = split(&locker_account, &[(amount, owner), (amount, new_owner)]);
+    env.process(&[ix], &[]).await.unwrap();
+
+    // The taken indices can't be split into again.
+    let (stale, _new_lockers) = split(&locker_account, &[(amount, owner)]);
+    let result = env.process(&[stale], &[]).await;
+    assert_locker_error(result, ErrorCode::InvalidSplitParts);
+
+    let locker_account = env.locker(&locker.address).await;
+    assert_eq!(locker_account.deposited_amount, AMOUNT - 2 * amount);
+    assert_eq!(locker_account.split_count, 2);
+
+    for (index, (new_locker, new_owner)) in new_lockers.iter().zip([owner, new_owner]).enumerate() {
+        let (address, _bump) =
+            pda::find_batch_split_locker_address(&locker::id(), &locker.address, index as u32);
+        assert_eq!(*new_locker, address);
+
+        let new_locker_account = env.locker(new_locker).await;
+        assert_eq!(new_locker_account.owner, new_owner);
+        assert_eq!(new_locker_account.deposited_amount, amount);
+        assert_eq!(new_locker_account.current_unlock_date, unlock_date);
+        assert_eq!(
+            new_locker_account.vault,
+            pda::find_vault_address(&locker::id(), new_locker).0
+        );
+
+        let vault = env.token_account(&new_locker_account.vault).await;
+        assert_eq!(vault.amount, amount);
+        assert_eq!(vault.mint, mint);
+        assert_eq!(
+            vault.owner,
+            pda::find_vault_authority_address(&locker::id(), new_locker).0
+        );
+    }
+
+    // The rest is split at the next indices and the old locker is closed.
+    let (ix, new_lockers) = split(&locker_account, &[(amount, owner), (amount, owner)]);
+    env.process(&[ix], &[]).await.unwrap();
+
+    assert_eq!(
+        new_lockers[0],
+        pda::find_batch_split_locker_address(&locker::id(), &locker.address, 2).0
+    );
+    assert!(env.get_account(&locker.address).await.is_none());
+    assert!(env.get_account(&locker.vault).await.is_none());
+}
+
+#[tokio::test]
+async fn split_lockers_keep_the_beneficiary() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    let owner = env.payer();
+    let beneficiary = Keypair::new().pubkey();
+    let set_beneficiary =
+        instruction::set_beneficiary(&locker::id(), &locker.address, &owner, beneficiary);
+    env.process(&[set_beneficiary], &[]).await.unwrap();
+
+    // The owner splits the funds to themselves.
+    let amount = AMOUNT / 4;
+    let locker_account = env.locker(&locker.address).await;
+    let (new_locker, _bump) = pda::find_split_locker_address(
+        &locker::id(),
+        &locker.address,
+        locker_account.current_unlock_date,
+        amount,
+    );
+    let new_vault = env.create_vault(&new_locker).await;
+    let (split, _new_locker) = instruction::split_locker(
+        &locker::id(),
+        &locker.address,
+        &locker_account,
+        &owner,
+        &new_vault,
+        amount,
+    );
+    env.process(&[split], &[]).await.unwrap();
+
+    let locker_account = env.locker(&locker.address).await;
+    let (split_batch, mut new_lockers) = instruction::split_locker_batch(
+        &locker::id(),
+        &locker.address,
+        &locker_account,
+        &env.mint,
+        &[(amount, owner), (AMOUNT - 2 * amount, owner)],
+    );
+    env.process(&[split_batch], &[]).await.unwrap();
+    new_lockers.push(new_locker);
+
+    // Splitting everything closes the old beneficiary with the locker.
+    assert!(env.get_account(&locker.address).await.is_none());
+    assert!(env.beneficiary(&locker.address).await.is_none());
+
+    env.warp_to_timestamp(unlock_date + 1).await;
+
+    let owner_wallet = env.create_token_wallet(&owner).await;
+    let beneficiary_wallet = env.create_token_wallet(&beneficiary).await;
+    let mut withdrawn = 0;
+    for new_locker in new_lockers {
+        assert_eq!(env.beneficiary(&new_locker).await, Some(beneficiary));
+
+        let new_locker_account = env.locker(&new_locker).await;
+        let withdraw = |target_wallet| {
+            instruction::withdraw_funds(
+                &locker::id(),
+                &new_locker,
+                &new_locker_account,
+                target_wallet,
+                new_locker_account.deposited_amount,
+            )
+        };
+        let result = env.process(&[withdraw(&owner_wallet)], &[]).await;
+        assert_locker_error(result, ErrorCode::InvalidBeneficiary);
+
+        env.process(&[withdraw(&beneficiary_wallet)], &[])
+            .await
+            .unwrap();
+        withdrawn += new_locker_account.deposited_amount;
+        assert_eq!(env.token_balance(&beneficiary_wallet).await, withdrawn);
+    }
+    assert_eq!(withdrawn, AMOUNT);
+    assert_eq!(env.token_balance(&owner_wallet).await, 0);
+}
+
+#[tokio::test]
+async fn split_locker_rejects_foreign_and_unsafe_vaults() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+    let other = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+
+    let owner = env.payer();
+    let new_owner = Keypair::new().pubkey();
+    let locker_account = env.locker(&locker.address).await;
+    let split = |new_vault: &Pubkey, amount| {
+        instruction::split_locker(
+            &locker::id(),
+            &locker.address,
+            &locker_account,
+            &new_owner,
+            new_vault,
+            amount,
+        )
+        .0
+    };
+    let new_vault_authority = |amount| {
+        let (new_locker, _bump) =
+            pda::find_split_locker_address(&locker::id(), &locker.address, unlock_date, amount);
+        pda::find_vault_authority_address(&locker::id(), &new_locker).0
+    };
+
+    // The vault of another locker together with its authority as the old vault.
+    let new_vault = env.create_vault(&Keypair::new().pubkey()).await;
+    let mut foreign_vault = split(&new_vault, 1);
+    foreign_vault.accounts[2].pubkey =
+        pda::find_vault_authority_address(&locker::id(), &other.address).0;
+    foreign_vault.accounts[3].pubkey = other.vault;
+    let result = env.process(&[foreign_vault], &[]).await;
+    assert_anchor_error(result, AnchorErrorCode::ConstraintSeeds);
+
+    // The vault of another locker only.
+    let mut foreign_vault = split(&new_vault, 1);
+    foreign_vault.accounts[3].pubkey = other.vault;
+    let result = env.process(&[foreign_vault], &[]).await;
+    assert_locker_error(result, ErrorCode::InvalidVault);
+
+    // The new vault isn't owned by the new vault authority.
+    let result = env.process(&[split(&new_vault, 2)], &[]).await;
+    assert_locker_error(result, ErrorCode::InvalidVault);
+
+    // The close authority survives the owner change.
+    let new_vault = env.create_token_wallet(&owner).await;
+    env.set_wallet_authority(
+        &new_vault,
+        AuthorityType::CloseAccount,
+        &Keypair::new().pubkey(),
+    )
+    .await;
+    env.set_wallet_authority(
+        &new_vault,
+        AuthorityType::AccountOwner,
+        &new_vault_authority(3),
+    )
+    .await;
+    let result = env.process(&[split(&new_vault, 3)], &[]).await;
+    assert_locker_error(result, ErrorCode::TokenAccountHasCloseAuthority);
+
+    let new_vault = env.create_token_wallet(&new_vault_authority(4)).await;
+    env.freeze(&new_vault).await;
+    let result = env.process(&[split(&new_vault, 4)], &[]).await;
+    assert_locker_error(result, ErrorCode::TokenAccountFrozen);
+}
+
+#[tokio::test]
+async fn split_locker_rejects_old_vault_with_close_authority() {
+    let owner = Keypair::new();
+    let vault = Keypair::new();
+    let address = Pubkey::new_unique();
+    let (vault_authority, vault_bump) = pda::find_vault_authority_address(&locker::id(), &address);
+    let mut legacy_locker = legacy_locker(&owner.pubkey(), 0);
+    legacy_locker.vault = vault.pubkey();
+    legacy_locker.vault_bump = vault_bump;
+
+    let mut env = TestEnv::with_accounts(
+        Preset::TokenLocker,
+        vec![
+            (address, legacy_account(&legacy_locker, LEGACY_LOCKER_LEN)),
+            (
+                owner.pubkey(),
+                Account::new(LAMPORTS_PER_SOL, 0, &system_program::id()),
+            ),
+        ],
+    )
+    .await;
+
+    // The vaults supplied by the clients could get a close authority
+    // before they were handed over to the vault authority.
+    let payer = env.payer();
+    env.create_token_wallet_at(&vault, &payer).await;
+    env.set_wallet_authority(
+        &vault.pubkey(),
+        AuthorityType::CloseAccount,
+        &Keypair::new().pubkey(),
+    )
+    .await;
+    env.set_wallet_authority(
+        &vault.pubkey(),
+        AuthorityType::AccountOwner,
+        &vault_authority,
+    )
+    .await;
+    env.mint_to(&vault.pubkey(), INITIAL_BALANCE).await;
+
+    let amount = INITIAL_BALANCE / 2;
+    let (new_locker, _bump) = pda::find_split_locker_address(
+        &locker::id(),
+        &address,
+        legacy_locker.current_unlock_date,
+        amount,
+    );
+    let new_vault = env.create_vault(&new_locker).await;
+    let (split, _new_locker) = instruction::split_locker(
+        &locker::id(),
+        &address,
+        &legacy_locker,
+        &owner.pubkey(),
+        &new_vault,
+        amount,
+    );
+    let result = env.process(&[split], &[&owner]).await;
+    assert_locker_error(result, ErrorCode::TokenAccountHasCloseAuthority);
+
+    let mint = env.mint;
+    let (split_batch, _new_lockers) = instruction::split_locker_batch(
+        &locker::id(),
+        &address,
+        &legacy_locker,
+        &mint,
+        &[(amount, owner.pubkey())],
+    );
+    let result = env.process(&[split_batch], &[&owner]).await;
+    assert_locker_error(result, ErrorCode::TokenAccountHasCloseAuthority);
+}
+
+#[tokio::test]
+async fn merge_lockers() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let args = |unlock_date| {
+        let mut args = locker_args(AMOUNT, unlock_date);
+        args.fee_in_sol = true;
+        args
+    };
+    let first = env.create_locker(args(unlock_date)).await.unwrap();
+    let second = env.create_locker(args(unlock_date)).await.unwrap();
+    let later = env.create_locker(args(unlock_date + DAY)).await.unwrap();
+
+    let first_account = env.locker(&first.address).await;
+    let second_account = env.locker(&second.address).await;
+    let later_account = env.locker(&later.address).await;
+    let merge = |source: &TestLocker,
+                 source_account: &Locker,
+                 target: &TestLocker,
+                 target_account: &Locker| {
+        instruction::merge_lockers(
+            &locker::id(),
+            &source.address,
+            source_account,
+            &target.address,
+            target_account,
+        )
+    };
+
+    let result = env
+        .process(
+            &[merge(&first, &first_account, &first, &first_account)],
+            &[],
+        )
+        .await;
+    assert_locker_error(result, ErrorCode::IncompatibleLockers);
+
+    // The funds of the later locker would be unlocked earlier.
+    let result = env
+        .process(
+            &[merge(&later, &later_account, &first, &first_account)],
+            &[],
+        )
+        .await;
+    assert_locker_error(result, ErrorCode::IncompatibleLockers);
+
+    // The funds would go to someone else.
+    let owner = env.payer();
+    let beneficiary = Keypair::new().pubkey();
+    let set_beneficiary = |locker: &TestLocker| {
+        instruction::set_beneficiary(&locker::id(), &locker.address, &owner, beneficiary)
+    };
+    env.process(&[set_beneficiary(&second)], &[]).await.unwrap();
+    let result = env
+        .process(
+            &[merge(&second, &second_account, &first, &first_account)],
+            &[],
+        )
+        .await;
+    assert_locker_error(result, ErrorCode::IncompatibleLockers);
+
+    env.process(
+        &[
+            set_beneficiary(&first),
+            merge(&second, &second_account, &first, &first_account),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert!(env.get_account(&second.address).await.is_none());
+    assert!(env.get_account(&second.vault).await.is_none());
+    assert!(env.beneficiary(&second.address).await.is_none());
+    assert_eq!(env.beneficiary(&first.address).await, Some(beneficiary));
+    assert_eq!(env.token_balance(&first.vault).await, 2 * AMOUNT);
+    let first_account = env.locker(&first.address).await;
+    assert_eq!(first_account.deposited_amount, 2 * AMOUNT);
+    assert_eq!(first_account.withdrawn_amount, 0);
+
+    let remove_beneficiary = instruction::remove_beneficiary(&locker::id(), &first.address, &owner);
+    env.process(
+        &[
+            remove_beneficiary,
+            merge(&first, &first_account, &later, &later_account),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert!(env.get_account(&first.address).await.is_none());
+    assert_eq!(env.token_balance(&later.vault).await, 3 * AMOUNT);
+    let later_account = env.locker(&later.address).await;
+    assert_eq!(later_account.deposited_amount, 3 * AMOUNT);
+    assert_eq!(later_account.current_unlock_date, unlock_date + DAY);
+}
+
+#[tokio::test]
+async fn migrate_locker_keeps_new_lockers_intact() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    let locker_account = env.locker(&locker.address).await;
+    let migrate = instruction::migrate_locker(&locker::id(), &locker.address, &locker_account);
+    env.process(&[migrate], &[]).await.unwrap();
+
+    let locker_account = env.locker(&locker.address).await;
+    assert_eq!(locker_account.withdrawn_amount, 0);
+    assert_eq!(locker_account.deposited_amount, AMOUNT);
+}
+
+#[tokio::test]
+async fn close_locker() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    let owner = env.payer();
+    let target_wallet = env.create_token_wallet(&owner).await;
+    let (vault_authority, _bump) =
+        pda::find_vault_authority_address(&locker::id(), &locker.address);
+
+    let close = |vault_authority, vault, target_wallet| Instruction {
+        program_id: locker::id(),
+        accounts: locker::accounts::CloseLocker {
+            locker: locker.address,
+            owner,
+            vault_authority,
+            vault,
+            target_wallet,
+            beneficiary: pda::find_beneficiary_address(&locker::id(), &locker.address).0,
+            config: pda::find_config_address(&locker::id()).0,
+            token_program: spl_token::id(),
+        }
+        .to_account_metas(None),
+        data: locker::instruction::CloseLocker {}.data(),
+    };
+
+    let other = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .unwrap();
+    let (other_vault_authority, _bump) =
+        pda::find_vault_authority_address(&locker::id(), &other.address);
+    let result = env
+        .process(
+            &[close(other_vault_authority, other.vault, target_wallet)],
+            &[],
+        )
+        .await;
+    assert_anchor_error(result, AnchorErrorCode::ConstraintSeeds);
+    let result = env
+        .process(&[close(vault_authority, other.vault, target_wallet)], &[])
+        .await;
+    assert_locker_error(result, ErrorCode::InvalidVault);
+
+    let with_delegate = env.create_token_wallet(&owner).await;
+    env.approve_delegate(&with_delegate).await;
+    let result = env
+        .process(&[close(vault_authority, locker.vault, with_delegate)], &[])
+        .await;
+    assert_locker_error(result, ErrorCode::TokenAccountHasDelegate);
+
+    let with_close_authority = env.create_token_wallet(&owner).await;
+    env.set_wallet_authority(
+        &with_close_authority,
+        AuthorityType::CloseAccount,
+        &Keypair::new().pubkey(),
+    )
+    .await;
+    let result = env
+        .process(
+            &[close(vault_authority, locker.vault, with_close_authority)],
+            &[],
+        )
+        .await;
+    assert_locker_error(result, ErrorCode::TokenAccountHasCloseAuthority);
+
+    env.process(&[close(vault_authority, locker.vault, target_wallet)], &[])
+        .await
+        .unwrap();
+
+    assert_eq!(env.token_balance(&target_wallet).await, AMOUNT);
+    assert!(env.get_account(&locker.address).await.is_none());
+    assert!(env.get_account(&locker.vault).await.is_none());
+}
+
+#[tokio::test]
+async fn pause_stops_deposits_and_withdrawals() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + DAY;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    let admin = env.payer();
+    let target_wallet = env.create_token_wallet(&admin).await;
+    let locker_account = env.locker(&locker.address).await;
+    let withdraw = |amount| {
+        instruction::withdraw_funds(
+            &locker::id(),
+            &locker.address,
+            &locker_account,
+            &target_wallet,
+            amount,
+        )
+    };
+    let set_pause = |deposits, withdrawals| {
+        instruction::set_pause(
+            &locker::id(),
+            &admin,
+            SetPauseArgs {
+                deposits,
+                withdrawals,
+            },
+        )
+    };
+
+    // Only admin can pause the program.
+    let stranger = Keypair::new();
+    let mut pause_by_stranger = set_pause(Some(true), Some(true));
+    pause_by_stranger.accounts[0].pubkey = stranger.pubkey();
+    assert!(env
+        .process(&[pause_by_stranger], &[&stranger])
+        .await
+        .is_err());
+
+    env.process(&[set_pause(Some(true), None)], &[])
+        .await
+        .unwrap();
+    let config = env.config().await;
+    assert!(config.deposits_paused);
+    assert!(!config.withdrawals_paused);
+
+    let result = env
+        .create_locker(locker_args(AMOUNT, unlock_date))
+        .await
+        .map(|_| ());
+    assert_locker_error(result, ErrorCode::ProgramPaused);
+
+    let relock = instruction::relock(&locker::id(), &locker.address, &admin, unlock_date + DAY);
+    let result = env.process(&[relock], &[]).await;
+    assert_locker_error(result, ErrorCode::ProgramPaused);
+
+    // Withdrawals are not affected by the deposits pause.
+    env.warp_to_timestamp(unlock_date + 1).await;
+    env.process(&[withdraw(AMOUNT / 2)], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&target_wallet).await, AMOUNT / 2);
+
+    env.process(&[set_pause(Some(false), Some(true))], &[])
+        .await
+        .unwrap();
+    let result = env.process(&[withdraw(AMOUNT)], &[]).await;
+    assert_locker_error(result, ErrorCode::ProgramPaused);
+
+    let relock = instruction::relock(
+        &locker::id(),
+        &locker.address,
+        &admin,
+        unlock_date + 2 * DAY,
+    );
+    env.process(&[relock], &[]).await.unwrap();
+
+    env.process(&[set_pause(None, Some(false))], &[])
+        .await
+        .unwrap();
+    env.warp_to_timestamp(unlock_date + 2 * DAY + 1).await;
+    env.process(&[withdraw(AMOUNT + 1)], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&target_wallet).await, AMOUNT);
+}
+
+#[tokio::test]
+async fn emergency_unlock() {
+    let mut env = TestEnv::new(Preset::TokenLocker).await;
+    let unlock_date = env.now().await + 365 * DAY;
+    let mut args = locker_args(AMOUNT, unlock_date);
+    args.fee_in_sol = true;
+    let locker = env.create_locker(args).await.unwrap();
+
+    let admin = env.payer();
+    let target_wallet = env.create_token_wallet(&admin).await;
+    let locker_account = env.locker(&locker.address).await;
+    let reason = "Support case #1: wrong unlock date".to_string();
+
+    // Only admin can unlock.
+    let stranger = Keypair::new();
+    let unlock_by_stranger = instruction::emergency_unlock(
+        &locker::id(),
+        &stranger.pubkey(),
+        &locker.address,
+        &locker_account,
+        reason.clone(),
+    );
+    assert!(env
+        .process(&[unlock_by_stranger], &[&stranger])
+        .await
+        .is_err());
+
+    let unlock = |locker_account: &Locker, reason: &str| {
+        instruction::emergency_unlock(
+            &locker::id(),
+            &admin,
+            &locker.address,
+            locker_account,
+            reason.to_string(),
+        )
+    };
+    env.process(&[unlock(&locker_account, &reason)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.locker(&locker.address).await.emergency_unlocks, 1);
+
+    let (record, _bump) =
+        pda::find_emergency_unlock_record_address(&locker::id(), &locker.address, 0);
+    let record: locker::EmergencyUnlockRecord = env.get_anchor_account(&record).await;
+    assert_eq!(record.locker, locker.address);
+    assert_eq!(record.index, 0);
+    assert_eq!(record.owner, admin);
+    assert_eq!(record.admin, admin);
+    assert_eq!(record.previous_unlock_date, unlock_date);
+    assert_eq!(record.vault_amount, AMOUNT);
+    assert_eq!(record.reason, reason);
+    let unlocked_at = record.unlocked_at;
+
+    // The record can't be overwritten, a further unlock takes the next index
+    // counted by the locker. The stale locker account points at the taken one.
+    env.warp_to_timestamp(unlocked_at + DAY).await;
+    let reason = "Support case #2: wrong unlock date again".to_string();
+    assert!(env
+        .process(&[unlock(&locker_account, &reason)], &[])
+        .await
+        .is_err());
+    let locker_account = env.locker(&locker.address).await;
+    env.process(&[unlock(&locker_account, &reason)], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.locker(&locker.address).await.emergency_unlocks, 2);
+
+    let (record, _bump) =
+        pda::find_emergency_unlock_record_address(&locker::id(), &locker.address, 1);
+    let record: locker::EmergencyUnlockRecord = env.get_anchor_account(&record).await;
+    assert_eq!(record.index, 1);
+    assert_eq!(record.previous_unlock_date, unlocked_at - 1);
+
+    let withdraw = instruction::withdraw_funds(
+        &locker::id(),
+        &locker.address,
+        &locker_account,
+        &target_wallet,
+        AMOUNT,
+    );
+    env.process(&[withdraw], &[]).await.unwrap();
+    assert_eq!(env.token_balance(&target_wallet).await, AMOUNT);
+}
@@ -70,7 +70,7 @@ describe('locker', () => {
         feeInTokenDenominator: new anchor.BN(10000),
         mintInfoPermissioned: false,
         hasLinearEmission: true,
-        bump: configBump
+        bump: configBump,
       },
       {
         accounts: {
@@ -113,7 +113,10 @@ describe('locker', () => {
 
     assert.ok(await client.isMintWhitelisted(mint.publicKey));
 
-    const vaultAccount = await serumCmn.getTokenAccount(provider, lockerAccount.account.vault);
+    const [vault, _vaultBump] = await client.findVaultAddress(lockerAccount.publicKey);
+    assert.ok(lockerAccount.account.vault.equals(vault));
+
+    const vaultAccount = await serumCmn.getTokenAccount(provider, vault);
     assert.ok(vaultAccount.amount.eqn(10000));
   });
 
@@ -156,6 +159,7 @@ describe('locker', () => {
     await client.transferOwnership({
       locker: lockerAccountBefore,
       newOwner: newOwner.publicKey,
+      unsafeSkipAcceptance: true,
     });
 
     const lockerAccountAfter = await program.account.locker.fetch(lockerAccountBefore.publicKey);
@@ -167,6 +171,7 @@ describe('locker', () => {
         account: lockerAccountAfter,
       },
       newOwner: lockerAccountBefore.account.owner,
+      unsafeSkipAcceptance: true,
       signers: [newOwner],
     });
 
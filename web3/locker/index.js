@@ -35,6 +35,27 @@ class Client {
     return [mintInfo, bump];
   }
 
+  async findMintFeeAddress(mint) {
+    const [mintFee, bump] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        new TextEncoder().encode("mint_fee"),
+        mint.toBytes()
+      ],
+      this.program.programId
+    );
+    return [mintFee, bump];
+  }
+
+  async findStablecoinFeeAddress() {
+    const [stablecoinFee, bump] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        new TextEncoder().encode("stablecoin_fee")
+      ],
+      this.program.programId
+    );
+    return [stablecoinFee, bump];
+  }
+
   async findConfigAddress() {
     const [config, bump] = await anchor.web3.PublicKey.findProgramAddress(
       [
@@ -51,6 +72,28 @@ class Client {
     return configAccount;
   }
 
+  async findVaultAddress(locker) {
+    const [vault, bump] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        new TextEncoder().encode("vault"),
+        locker.toBytes()
+      ],
+      this.program.programId
+    );
+    return [vault, bump];
+  }
+
+  async findBeneficiaryAddress(locker) {
+    const [beneficiary, bump] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        new TextEncoder().encode("beneficiary"),
+        locker.toBytes()
+      ],
+      this.program.programId
+    );
+    return [beneficiary, bump];
+  }
+
   async vaultAuthorityAddress(locker) {
     return await anchor.web3.PublicKey.createProgramAddress(
       [
@@ -109,18 +152,14 @@ class Client {
       this.program.programId,
     );
 
+    const [vault, _vaultBump] = await this.findVaultAddress(locker.publicKey);
     const fundingWalletAccount = await serumCmn.getTokenAccount(this.provider, args.fundingWallet);
-    const vault = new anchor.web3.Account();
-    const createTokenAccountInstrs = await serumCmn.createTokenAccountInstrs(
-      this.provider,
-      vault.publicKey,
-      fundingWalletAccount.mint,
-      vaultAuthority
-    );
 
     const [config, _] = await this.findConfigAddress();
     const configAccount = await this.program.account.config.fetch(config);
 
+    const [mintFee, _mintFeeBump] = await this.findMintFeeAddress(fundingWalletAccount.mint);
+    const [stablecoinFee, _stablecoinFeeBump] = await this.findStablecoinFeeAddress();
     const [mintInfo, initMintInfoInstrs] = await getOrCreateMintInfo(
       this.program,
       fundingWalletAccount.mint,
@@ -132,37 +171,33 @@ class Client {
     );
 
     await this.program.rpc.createLocker(
-      {
-        unlockDate: args.unlockDate,
-        vaultBump,
-        countryCode: args.countryCode,
-        startEmission: args.startEmission,
-        amount: args.amount,
-        feeInSol: args.feeInSol,
-      },
+      createLockerArgs(args, vaultBump),
       {
         accounts: {
           locker: locker.publicKey,
           creator: args.creator,
           owner: args.owner,
-          vault: vault.publicKey,
-          vaultAuthority,
           fundingWalletAuthority: args.fundingWalletAuthority,
           fundingWallet: args.fundingWallet,
+          vaultAuthority,
+          mint: fundingWalletAccount.mint,
+          vault,
           feeWallet: configAccount.feeWallet,
           feeTokenWallet,
           mintInfo,
-          countryBanlist: configAccount.countryList,
+          mintFee,
+          stablecoinFee,
           config,
+          countryBanlist: configAccount.countryList,
 
           clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
           systemProgram: anchor.web3.SystemProgram.programId,
           tokenProgram: utils.TOKEN_PROGRAM_ID,
         },
-        instructions: createTokenAccountInstrs
-          .concat(initMintInfoInstrs)
+        instructions: initMintInfoInstrs
           .concat(createAssociatedTokenAccountInstrs),
-        signers: [vault, locker],
+        signers: [locker],
       }
     );
 
@@ -189,23 +224,29 @@ class Client {
   }
 
   async relock(args) {
+    const [config, _] = await this.findConfigAddress();
+
     return await this.program.rpc.relock(
       args.unlockDate,
       {
         accounts: {
           locker: args.locker.publicKey,
           owner: args.locker.account.owner,
+          config,
         }
       }
     );
   }
 
   async transferOwnership(args) {
+    const [config, _] = await this.findConfigAddress();
+
     const rpcArgs = {
       accounts: {
         locker: args.locker.publicKey,
         owner: args.locker.account.owner,
         newOwner: args.newOwner,
+        config,
       }
     };
 
@@ -213,7 +254,7 @@ class Client {
       rpcArgs.signers = args.signers;
     }
 
-    return await this.program.rpc.transferOwnership(rpcArgs);
+    return await this.program.rpc.transferOwnership(args.unsafeSkipAcceptance === true, rpcArgs);
   }
 
   async incrementLock(args) {
@@ -221,10 +262,12 @@ class Client {
     const configAccount = await this.program.account.config.fetch(config);
 
     const fundingWalletAccount = await serumCmn.getTokenAccount(this.provider, args.fundingWallet);
+    const [mintFee, _mintFeeBump] = await this.findMintFeeAddress(fundingWalletAccount.mint);
     const [mintInfo, initMintInfoInstrs] = await getOrCreateMintInfo(
       this.program,
       fundingWalletAccount.mint,
-      args.fundingWalletAuthority
+      args.fundingWalletAuthority,
+      config
     );
     const [feeTokenWallet, createAssociatedTokenAccountInstrs] = await utils.getOrCreateAssociatedTokenAccountInstrs(
       this.provider, fundingWalletAccount.mint, configAccount.feeWallet
@@ -241,6 +284,7 @@ class Client {
           feeWallet: feeTokenWallet,
           tokenProgram: utils.TOKEN_PROGRAM_ID,
           mintInfo,
+          mintFee,
           config
         },
         instructions: initMintInfoInstrs
@@ -252,6 +296,8 @@ class Client {
   }
 
   async withdrawFunds(args) {
+    const [config, _] = await this.findConfigAddress();
+    const [beneficiary, _beneficiaryBump] = await this.findBeneficiaryAddress(args.locker.publicKey);
     const vaultAuthority = await anchor.web3.PublicKey.createProgramAddress(
       [
         args.locker.publicKey.toBytes(),
@@ -269,7 +315,7 @@ class Client {
         this.provider, vaultWalletAccount.mint, targetWallet
       );
       targetWallet = targetTokenWallet;
-      extraInstructions.concat(createAssociatedTokenAccountInstrs);
+      extraInstructions = extraInstructions.concat(createAssociatedTokenAccountInstrs);
     }
 
     await this.program.rpc.withdrawFunds(
@@ -281,6 +327,8 @@ class Client {
           vaultAuthority,
           vault: args.locker.account.vault,
           targetWallet,
+          beneficiary,
+          config,
 
           clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
           tokenProgram: utils.TOKEN_PROGRAM_ID,
@@ -293,6 +341,8 @@ class Client {
   }
 
   async closeLocker(args) {
+    const [config, _] = await this.findConfigAddress();
+    const [beneficiary, _beneficiaryBump] = await this.findBeneficiaryAddress(args.locker.publicKey);
     const vaultAuthority = await anchor.web3.PublicKey.createProgramAddress(
       [
         args.locker.publicKey.toBytes(),
@@ -301,7 +351,7 @@ class Client {
       this.program.programId,
     );
 
-    await this.program.rpc.withdrawFunds(
+    await this.program.rpc.closeLocker(
       {
         accounts: {
           locker: args.locker.publicKey,
@@ -309,6 +359,8 @@ class Client {
           vaultAuthority,
           vault: args.locker.account.vault,
           targetWallet: args.targetWallet,
+          beneficiary,
+          config,
 
           tokenProgram: utils.TOKEN_PROGRAM_ID,
         }
@@ -319,6 +371,7 @@ class Client {
   }
 
   async splitLocker(args) {
+    const [config, _] = await this.findConfigAddress();
     const oldVaultAuthority = await anchor.web3.PublicKey.createProgramAddress(
       [
         args.locker.publicKey.toBytes(),
@@ -343,6 +396,9 @@ class Client {
       this.program.programId,
     );
 
+    const [oldBeneficiary, _oldBeneficiaryBump] = await this.findBeneficiaryAddress(args.locker.publicKey);
+    const [newBeneficiary, _newBeneficiaryBump] = await this.findBeneficiaryAddress(newLocker);
+
     const vaultAccount = await serumCmn.getTokenAccount(this.provider, args.locker.account.vault);
     const newVault = new anchor.web3.Account();
     const createTokenAccountInstrs = await serumCmn.createTokenAccountInstrs(
@@ -369,7 +425,11 @@ class Client {
           newOwner: args.newOwner,
           newVaultAuthority,
           newVault: newVault.publicKey,
+          oldBeneficiary,
+          newBeneficiary,
+          config,
 
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
           systemProgram: anchor.web3.SystemProgram.programId,
           tokenProgram: utils.TOKEN_PROGRAM_ID,
         },
@@ -413,6 +473,27 @@ async function findMintInfoAddress(program, mint) {
   return [mintInfo, bump];
 }
 
+async function findMintFeeAddress(program, mint) {
+  const [mintFee, bump] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      new TextEncoder().encode("mint_fee"),
+      mint.toBytes()
+    ],
+    program.programId
+  );
+  return [mintFee, bump];
+}
+
+async function findStablecoinFeeAddress(program) {
+  const [stablecoinFee, bump] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      new TextEncoder().encode("stablecoin_fee")
+    ],
+    program.programId
+  );
+  return [stablecoinFee, bump];
+}
+
 async function findConfigAddress(program) {
   const [config, bump] = await anchor.web3.PublicKey.findProgramAddress(
     [
@@ -423,6 +504,45 @@ async function findConfigAddress(program) {
   return [config, bump];
 }
 
+async function findVaultAddress(program, locker) {
+  const [vault, bump] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      new TextEncoder().encode("vault"),
+      locker.toBytes()
+    ],
+    program.programId
+  );
+  return [vault, bump];
+}
+
+async function findBeneficiaryAddress(program, locker) {
+  const [beneficiary, bump] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      new TextEncoder().encode("beneficiary"),
+      locker.toBytes()
+    ],
+    program.programId
+  );
+  return [beneficiary, bump];
+}
+
+// Schedules and the stablecoin fee are optional, so the callers of the
+// plain lockers don't have to pass them.
+function createLockerArgs(args, vaultBump) {
+  return {
+    amount: args.amount,
+    unlockDate: args.unlockDate,
+    countryCode: args.countryCode,
+    startEmission: args.startEmission,
+    cliff: args.cliff === undefined ? null : args.cliff,
+    tranches: args.tranches === undefined ? null : args.tranches,
+    vaultBump,
+    feeInSol: args.feeInSol,
+    feeInStablecoin: args.feeInStablecoin === undefined ? false : args.feeInStablecoin,
+    revokeAuthority: args.revokeAuthority === undefined ? null : args.revokeAuthority,
+  };
+}
+
 const FAILED_TO_FIND_ACCOUNT = "Account does not exist";
 
 async function tryIfExists(program, account, address, found, notFound) {
@@ -496,18 +616,14 @@ async function createLocker(provider, args, cluster) {
     program.programId,
   );
 
+  const [vault, _vaultBump] = await findVaultAddress(program, locker.publicKey);
   const fundingWalletAccount = await serumCmn.getTokenAccount(provider, args.fundingWallet);
-  const vault = new anchor.web3.Account();
-  const createTokenAccountInstrs = await serumCmn.createTokenAccountInstrs(
-    provider,
-    vault.publicKey,
-    fundingWalletAccount.mint,
-    vaultAuthority
-  );
 
   const [config, _] = await findConfigAddress(program);
   const configAccount = await program.account.config.fetch(config);
 
+  const [mintFee, _mintFeeBump] = await findMintFeeAddress(program, fundingWalletAccount.mint);
+  const [stablecoinFee, _stablecoinFeeBump] = await findStablecoinFeeAddress(program);
   const [mintInfo, initMintInfoInstrs] = await getOrCreateMintInfo(
     program,
     fundingWalletAccount.mint,
@@ -518,39 +634,34 @@ async function createLocker(provider, args, cluster) {
     provider, fundingWalletAccount.mint, configAccount.feeWallet
   );
 
-  const finalFeeWallet = args.feeInSol ? configAccount.feeWallet : feeTokenWallet;
-
   await program.rpc.createLocker(
-    {
-      unlockDate: args.unlockDate,
-      vaultBump,
-      countryCode: args.countryCode,
-      startEmission: args.startEmission,
-      amount: args.amount,
-      feeInSol: args.feeInSol,
-    },
+    createLockerArgs(args, vaultBump),
     {
       accounts: {
         locker: locker.publicKey,
         creator: args.creator,
         owner: args.owner,
-        vault: vault.publicKey,
-        vaultAuthority,
         fundingWalletAuthority: args.fundingWalletAuthority,
         fundingWallet: args.fundingWallet,
-        feeWallet: finalFeeWallet,
+        vaultAuthority,
+        mint: fundingWalletAccount.mint,
+        vault,
+        feeWallet: configAccount.feeWallet,
+        feeTokenWallet,
         mintInfo,
-        countryBanlist: configAccount.countryList,
+        mintFee,
+        stablecoinFee,
         config,
+        countryBanlist: configAccount.countryList,
 
         clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: utils.TOKEN_PROGRAM_ID,
       },
-      instructions: createTokenAccountInstrs
-        .concat(initMintInfoInstrs)
+      instructions: initMintInfoInstrs
         .concat(createAssociatedTokenAccountInstrs),
-      signers: [vault, locker],
+      signers: [locker],
     }
   );
 
@@ -580,6 +691,7 @@ async function getLockersOwnedBy(provider, owner, cluster, programName) {
 
 async function relock(provider, args, cluster) {
   const program = initProgram(provider, cluster);
+  const [config, _] = await findConfigAddress(program);
 
   return await program.rpc.relock(
     args.unlockDate,
@@ -587,6 +699,7 @@ async function relock(provider, args, cluster) {
       accounts: {
         locker: args.locker.publicKey,
         owner: args.locker.account.owner,
+        config,
       }
     }
   );
@@ -594,12 +707,14 @@ async function relock(provider, args, cluster) {
 
 async function transferOwnership(provider, args, cluster) {
   const program = initProgram(provider, cluster);
+  const [config, _] = await findConfigAddress(program);
 
   const rpcArgs = {
     accounts: {
       locker: args.locker.publicKey,
       owner: args.locker.account.owner,
       newOwner: args.newOwner,
+      config,
     }
   };
 
@@ -607,7 +722,7 @@ async function transferOwnership(provider, args, cluster) {
     rpcArgs.signers = args.signers;
   }
 
-  return await program.rpc.transferOwnership(rpcArgs);
+  return await program.rpc.transferOwnership(args.unsafeSkipAcceptance === true, rpcArgs);
 }
 
 async function incrementLock(provider, args, cluster) {
@@ -617,10 +732,12 @@ async function incrementLock(provider, args, cluster) {
   const configAccount = await program.account.config.fetch(config);
 
   const fundingWalletAccount = await serumCmn.getTokenAccount(provider, args.fundingWallet);
+  const [mintFee, _mintFeeBump] = await findMintFeeAddress(program, fundingWalletAccount.mint);
   const [mintInfo, initMintInfoInstrs] = await getOrCreateMintInfo(
     program,
     fundingWalletAccount.mint,
-    args.fundingWalletAuthority
+    args.fundingWalletAuthority,
+    config
   );
   const [feeTokenWallet, createAssociatedTokenAccountInstrs] = await utils.getOrCreateAssociatedTokenAccountInstrs(
     provider, fundingWalletAccount.mint, configAccount.feeWallet
@@ -637,6 +754,7 @@ async function incrementLock(provider, args, cluster) {
         feeWallet: feeTokenWallet,
         tokenProgram: utils.TOKEN_PROGRAM_ID,
         mintInfo,
+        mintFee,
         config
       },
       instructions: initMintInfoInstrs
@@ -649,6 +767,8 @@ async function incrementLock(provider, args, cluster) {
 
 async function withdrawFunds(provider, args, cluster) {
   const program = initProgram(provider, cluster);
+  const [config, _] = await findConfigAddress(program);
+  const [beneficiary, _beneficiaryBump] = await findBeneficiaryAddress(program, args.locker.publicKey);
 
   const vaultAuthority = await anchor.web3.PublicKey.createProgramAddress(
     [
@@ -667,7 +787,7 @@ async function withdrawFunds(provider, args, cluster) {
       provider, vaultWalletAccount.mint, targetWallet
     );
     targetWallet = targetTokenWallet;
-    extraInstructions.concat(createAssociatedTokenAccountInstrs);
+    extraInstructions = extraInstructions.concat(createAssociatedTokenAccountInstrs);
   }
 
   await program.rpc.withdrawFunds(
@@ -679,6 +799,8 @@ async function withdrawFunds(provider, args, cluster) {
         vaultAuthority,
         vault: args.locker.account.vault,
         targetWallet,
+        beneficiary,
+        config,
 
         clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
         tokenProgram: utils.TOKEN_PROGRAM_ID,
@@ -692,6 +814,8 @@ async function withdrawFunds(provider, args, cluster) {
 
 async function closeLocker(provider, args, cluster) {
   const program = initProgram(provider, cluster);
+  const [config, _] = await findConfigAddress(program);
+  const [beneficiary, _beneficiaryBump] = await findBeneficiaryAddress(program, args.locker.publicKey);
 
   const vaultAuthority = await anchor.web3.PublicKey.createProgramAddress(
     [
@@ -701,7 +825,7 @@ async function closeLocker(provider, args, cluster) {
     program.programId,
   );
 
-  await program.rpc.withdrawFunds(
+  await program.rpc.closeLocker(
     {
       accounts: {
         locker: args.locker.publicKey,
@@ -709,6 +833,8 @@ async function closeLocker(provider, args, cluster) {
         vaultAuthority,
         vault: args.locker.account.vault,
         targetWallet: args.targetWallet,
+        beneficiary,
+        config,
 
         tokenProgram: utils.TOKEN_PROGRAM_ID,
       }
@@ -720,6 +846,7 @@ async function closeLocker(provider, args, cluster) {
 
 async function splitLocker(provider, args, cluster) {
   const program = initProgram(provider, cluster);
+  const [config, _] = await findConfigAddress(program);
 
   const oldVaultAuthority = await anchor.web3.PublicKey.createProgramAddress(
     [
@@ -745,6 +872,9 @@ async function splitLocker(provider, args, cluster) {
     program.programId,
   );
 
+  const [oldBeneficiary, _oldBeneficiaryBump] = await findBeneficiaryAddress(program, args.locker.publicKey);
+  const [newBeneficiary, _newBeneficiaryBump] = await findBeneficiaryAddress(program, newLocker);
+
   const vaultAccount = await serumCmn.getTokenAccount(provider, args.locker.account.vault);
   const newVault = new anchor.web3.Account();
   const createTokenAccountInstrs = await serumCmn.createTokenAccountInstrs(
@@ -771,7 +901,11 @@ async function splitLocker(provider, args, cluster) {
         newOwner: args.newOwner,
         newVaultAuthority,
         newVault: newVault.publicKey,
+        oldBeneficiary,
+        newBeneficiary,
+        config,
 
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: utils.TOKEN_PROGRAM_ID,
       },
@@ -790,7 +924,11 @@ module.exports = {
   TOKEN_LOCKER,
   Client,
   findMintInfoAddress,
+  findMintFeeAddress,
+  findStablecoinFeeAddress,
   findConfigAddress,
+  findVaultAddress,
+  findBeneficiaryAddress,
   vaultAuthorityAddress,
   isMintWhitelisted,
   createLocker,